@@ -0,0 +1,230 @@
+/// Rule of users which are deleted as they no longer exist in Google
+pub const RULE_MISSING_IN_GOOGLE: &str = "missing in google";
+/// Rule of users which are deleted as they were deleted in Google
+pub const RULE_DELETED_IN_GOOGLE: &str = "deleted in google";
+
+#[derive(Debug, Copy, Clone, serde::Serialize)]
+pub enum Action {
+    CreateUser,
+    DeleteUser,
+    CreateGroup,
+    DeleteGroup,
+    AddMember,
+    RemoveMember,
+    SetManager,
+    CreateAccountAssignment,
+    DeleteAccountAssignment,
+}
+
+/// Change of a single principal in a target
+#[derive(Debug)]
+pub struct Change {
+    action: Action,
+    principal: String,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+    rule: Option<String>,
+}
+
+impl Change {
+    pub(crate) fn new(action: Action, principal: &str) -> Self {
+        Self {
+            action,
+            principal: principal.into(),
+            before: None,
+            after: None,
+            rule: None,
+        }
+    }
+
+    /// State of the principal before the change
+    pub(crate) fn before<T: serde::Serialize>(mut self, value: &T) -> Self {
+        self.before = serde_json::to_value(value).ok();
+        self
+    }
+
+    /// State of the principal after the change
+    pub(crate) fn after<T: serde::Serialize>(mut self, value: &T) -> Self {
+        self.after = serde_json::to_value(value).ok();
+        self
+    }
+
+    /// Config or google state which triggered the change
+    pub(crate) fn rule(mut self, rule: Option<&str>) -> Self {
+        self.rule = rule.map(Into::into);
+        self
+    }
+}
+
+/// Line of the audit log
+#[derive(Debug, serde::Serialize)]
+pub struct Entry {
+    run_id: String,
+    timestamp: chrono::DateTime<chrono::Utc>,
+    target: String,
+    action: Action,
+    principal: String,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+    rule: Option<String>,
+}
+
+/// Changes of all targets of a run. Changes are recorded as soon as they are applied
+/// and written after each target, so they are kept even if the sync of the target fails
+/// afterwards
+#[derive(Debug)]
+pub struct AuditLog {
+    run_id: String,
+    entries: std::sync::Mutex<Vec<(chrono::DateTime<chrono::Utc>, String, Change)>>,
+}
+
+impl AuditLog {
+    pub(crate) fn new(run_id: String) -> Self {
+        Self {
+            run_id,
+            entries: std::sync::Mutex::default(),
+        }
+    }
+
+    pub(crate) fn record(&self, target: &str, change: Change) {
+        self.entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push((chrono::Utc::now(), target.into(), change));
+    }
+
+    /// Removes and returns all changes recorded so far
+    pub(crate) fn take_entries(&self) -> Vec<Entry> {
+        std::mem::take(
+            &mut *self
+                .entries
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+        )
+        .into_iter()
+        .map(|(timestamp, target, change)| Entry {
+            run_id: self.run_id.clone(),
+            timestamp,
+            target,
+            action: change.action,
+            principal: change.principal,
+            before: change.before,
+            after: change.after,
+            rule: change.rule,
+        })
+        .collect()
+    }
+
+    /// Writes all changes recorded since the last call as JSON Lines to the configured
+    /// locations. Every target of an invocation writes a new file, which is never
+    /// overwritten
+    pub(crate) async fn write(
+        &self,
+        config: &crate::event::AuditLogConfig,
+        invocation_id: &str,
+        target: usize,
+    ) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let entries = self.take_entries();
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut content = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut content, &entry)
+                .context("Unable to serialize audit log entry")?;
+            content.push(b'\n');
+        }
+        let now = chrono::Utc::now();
+        let name = format!(
+            "{}/{}-{invocation_id}-{target}.jsonl",
+            now.format("%Y/%m/%d"),
+            now.format("%Y%m%dT%H%M%SZ")
+        );
+        let writers = writers(
+            config.s3.as_ref(),
+            config.directory.as_deref(),
+            "application/x-ndjson",
+        )?;
+        for writer in writers {
+            writer.write(&name, &content).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Location to which the audit log or a snapshot is written
+#[async_trait::async_trait]
+pub trait Writer: Send + Sync {
+    /// Writes the file with the given relative name. Fails if it already exists
+    async fn write(&self, name: &str, content: &[u8]) -> anyhow::Result<()>;
+}
+
+/// Returns a writer for every configured location. Objects in S3 are
+/// created with the given content type
+pub fn writers(
+    s3: Option<&crate::event::S3Prefix>,
+    directory: Option<&str>,
+    content_type: &'static str,
+) -> anyhow::Result<Vec<Box<dyn Writer>>> {
+    let mut writers: Vec<Box<dyn Writer>> = Vec::new();
+    if let Some(s3) = s3 {
+        writers.push(Box::new(S3Writer {
+            s3: crate::aws::S3::new(&s3.region)?,
+            bucket: s3.bucket.clone(),
+            prefix: s3.prefix.clone().unwrap_or_default(),
+            content_type,
+        }));
+    }
+    if let Some(directory) = directory {
+        writers.push(Box::new(FileWriter {
+            directory: directory.into(),
+        }));
+    }
+    Ok(writers)
+}
+
+struct S3Writer {
+    s3: crate::aws::S3,
+    bucket: String,
+    prefix: String,
+    content_type: &'static str,
+}
+
+#[async_trait::async_trait]
+impl Writer for S3Writer {
+    async fn write(&self, name: &str, content: &[u8]) -> anyhow::Result<()> {
+        let key = format!("{}{name}", self.prefix);
+        log::info!("Writing to s3://{}/{}", self.bucket, key);
+        self.s3
+            .put_object(&self.bucket, &key, content, self.content_type)
+            .await
+    }
+}
+
+/// Writes into a local directory, which is meant for testing
+struct FileWriter {
+    directory: std::path::PathBuf,
+}
+
+#[async_trait::async_trait]
+impl Writer for FileWriter {
+    async fn write(&self, name: &str, content: &[u8]) -> anyhow::Result<()> {
+        use anyhow::Context;
+        use std::io::Write;
+
+        let path = self.directory.join(name);
+        log::info!("Writing to {}", path.display());
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Unable to create directory {}", parent.display()))?;
+        }
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(content))
+            .with_context(|| format!("Unable to write to {}", path.display()))
+    }
+}
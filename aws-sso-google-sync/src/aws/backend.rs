@@ -0,0 +1,135 @@
+/// Change of a group membership
+#[derive(Debug, Copy, Clone)]
+pub enum MembershipChange<'a> {
+    Add { group_id: &'a str, user_id: &'a str },
+    Remove { group_id: &'a str, user_id: &'a str },
+}
+
+impl MembershipChange<'_> {
+    pub(crate) const fn group_id(&self) -> &str {
+        match *self {
+            Self::Add { group_id, .. } | Self::Remove { group_id, .. } => group_id,
+        }
+    }
+}
+
+/// Splits the changes by group while keeping their order and index. The changes of one
+/// group are applied one after another, while different groups may be updated concurrently
+pub fn changes_by_group<'a, 'b>(
+    changes: &'b [MembershipChange<'a>],
+) -> Vec<Vec<(usize, &'b MembershipChange<'a>)>> {
+    let mut groups: Vec<Vec<(usize, &MembershipChange<'_>)>> = Vec::new();
+    let mut index = std::collections::HashMap::new();
+    for change in changes.iter().enumerate() {
+        let i = *index.entry(change.1.group_id()).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[i].push(change);
+    }
+    groups
+}
+
+/// Store of AWS SSO users and groups which can be synced with Google Workspace
+#[async_trait::async_trait]
+pub trait Backend: Send + Sync {
+    /// Whether `list_users` and `list_groups` return all entries of the store.
+    /// If not, additional requests are required to keep users and groups in sync
+    fn is_listing_complete(&self) -> bool;
+
+    /// Whether the manager of a user can be stored with `set_user_manager`
+    fn is_manager_supported(&self) -> bool {
+        false
+    }
+
+    /// Whether users can have more than one email. AWS SSO only accepts the primary email
+    fn is_multiple_emails_supported(&self) -> bool {
+        false
+    }
+
+    async fn list_users(&self) -> anyhow::Result<Vec<super::User>>;
+
+    async fn get_user(&self, user_name: &str) -> anyhow::Result<Option<super::User>>;
+
+    /// Returns `None` if the user already exists
+    async fn create_user(&self, user: super::User) -> anyhow::Result<Option<super::User>>;
+
+    async fn delete_user(&self, user_id: &str) -> anyhow::Result<()>;
+
+    /// Sets or removes the manager of the user in the enterprise user extension
+    async fn set_user_manager(
+        &self,
+        _user_id: &str,
+        _manager_id: Option<&str>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn list_groups(&self) -> anyhow::Result<Vec<super::Group>>;
+
+    async fn get_group(&self, display_name: &str) -> anyhow::Result<Option<super::Group>>;
+
+    /// Returns `None` if the group already exists
+    async fn create_group(&self, group: super::Group) -> anyhow::Result<Option<super::Group>>;
+
+    async fn delete_group(&self, group_id: &str) -> anyhow::Result<()>;
+
+    async fn is_group_member(&self, group_id: &str, user_id: &str) -> anyhow::Result<bool>;
+
+    /// Returns the ids of all groups the user is a member of or `None`
+    /// if the store is not able to list them
+    async fn list_group_memberships_for_member(
+        &self,
+        _user_id: &str,
+    ) -> anyhow::Result<Option<std::collections::HashSet<String>>> {
+        Ok(None)
+    }
+
+    /// Returns the ids of all users which are members of the group or `None`
+    /// if the store is not able to list them
+    async fn list_group_members(
+        &self,
+        _group_id: &str,
+    ) -> anyhow::Result<Option<std::collections::HashSet<String>>> {
+        Ok(None)
+    }
+
+    async fn add_group_member(&self, group_id: &str, user_id: &str) -> anyhow::Result<()>;
+
+    async fn remove_group_member(&self, group_id: &str, user_id: &str) -> anyhow::Result<()>;
+
+    /// Applies all given membership changes. Stores which support batching
+    /// may apply multiple changes with a single request. Up to `concurrency`
+    /// groups are updated at the same time. `applied` is called with the index
+    /// of each change as soon as it is applied
+    async fn apply_membership_changes(
+        &self,
+        changes: &[MembershipChange<'_>],
+        concurrency: usize,
+        applied: &(dyn Fn(usize) + Sync),
+    ) -> anyhow::Result<()> {
+        use futures::{StreamExt, TryStreamExt};
+
+        let requests = changes_by_group(changes)
+            .into_iter()
+            .map(|changes| async move {
+                for (index, change) in changes {
+                    match *change {
+                        MembershipChange::Add { group_id, user_id } => {
+                            self.add_group_member(group_id, user_id).await?
+                        }
+                        MembershipChange::Remove { group_id, user_id } => {
+                            self.remove_group_member(group_id, user_id).await?
+                        }
+                    }
+                    applied(index);
+                }
+                anyhow::Ok(())
+            })
+            .collect::<Vec<_>>();
+        futures::stream::iter(requests)
+            .buffer_unordered(concurrency)
+            .try_collect()
+            .await
+    }
+}
@@ -0,0 +1,564 @@
+use super::jsonapi::ApiError;
+
+const MAX_RESULTS: usize = 100;
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct IdentityStoreConfig {
+    pub(crate) region: String,
+    pub(crate) identity_store_id: String,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct StoreUser {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_id: Option<String>,
+    user_name: String,
+    #[serde(skip_serializing)]
+    external_ids: Option<Vec<StoreExternalId>>,
+    name: Option<StoreUserName>,
+    display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    emails: Option<Vec<StoreUserMail>>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StoreExternalId {
+    id: String,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct StoreUserName {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    formatted: Option<String>,
+    family_name: Option<String>,
+    given_name: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct StoreUserMail {
+    value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    r#type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    primary: Option<bool>,
+}
+
+impl From<StoreUser> for super::User {
+    fn from(user: StoreUser) -> Self {
+        let name = user.name.unwrap_or(StoreUserName {
+            formatted: None,
+            family_name: None,
+            given_name: None,
+        });
+        Self {
+            id: user.user_id,
+            external_id: user
+                .external_ids
+                .and_then(|ids| ids.into_iter().next())
+                .map(|id| id.id),
+            display_name: user.display_name.unwrap_or_else(|| user.user_name.clone()),
+            user_name: user.user_name,
+            name: super::UserName {
+                formatted: name.formatted,
+                family_name: name.family_name.unwrap_or_default(),
+                given_name: name.given_name.unwrap_or_default(),
+            },
+            profile_url: user.profile_url,
+            emails: user.emails.map(|emails| {
+                emails
+                    .into_iter()
+                    .filter_map(|email| {
+                        Some(super::UserMail {
+                            value: email.value?,
+                            r#type: email.r#type,
+                            primary: email.primary,
+                        })
+                    })
+                    .collect()
+            }),
+            active: true,
+            enterprise: None,
+        }
+    }
+}
+
+impl From<super::User> for StoreUser {
+    fn from(user: super::User) -> Self {
+        Self {
+            user_id: None,
+            user_name: user.user_name,
+            external_ids: None,
+            name: Some(StoreUserName {
+                formatted: user.name.formatted,
+                family_name: Some(user.name.family_name),
+                given_name: Some(user.name.given_name),
+            }),
+            display_name: Some(user.display_name),
+            profile_url: user.profile_url,
+            emails: user.emails.map(|emails| {
+                emails
+                    .into_iter()
+                    .map(|email| StoreUserMail {
+                        value: Some(email.value),
+                        r#type: email.r#type,
+                        primary: email.primary,
+                    })
+                    .collect()
+            }),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StoreGroup {
+    group_id: String,
+    display_name: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListUsersResponse {
+    users: Vec<StoreUser>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListGroupsResponse {
+    groups: Vec<StoreGroup>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListGroupMembershipsForMemberResponse {
+    group_memberships: Vec<StoreGroupMembership>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StoreGroupMembership {
+    group_id: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListGroupMembershipsResponse {
+    group_memberships: Vec<StoreGroupMember>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StoreGroupMember {
+    member_id: Option<StoreMemberId>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StoreMemberId {
+    user_id: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct UserIdResponse {
+    user_id: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GroupIdResponse {
+    group_id: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MembershipIdResponse {
+    membership_id: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct IsMemberInGroupsResponse {
+    results: Vec<GroupMembershipExistenceResult>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GroupMembershipExistenceResult {
+    membership_exists: Option<bool>,
+}
+
+/// Client for the AWS IAM Identity Store API
+#[derive(Debug)]
+pub struct IdentityStore<'a> {
+    config: &'a IdentityStoreConfig,
+    api: super::jsonapi::JsonApi,
+}
+
+impl<'a> IdentityStore<'a> {
+    pub(crate) fn new(config: &'a IdentityStoreConfig) -> anyhow::Result<Self> {
+        let region = super::jsonapi::JsonApi::region_from_str(&config.region)?;
+        Ok(Self {
+            config,
+            api: super::jsonapi::JsonApi::new(
+                "AWS Identity Store",
+                "identitystore",
+                "AWSIdentityStore",
+                region,
+            ),
+        })
+    }
+
+    async fn request<O: serde::de::DeserializeOwned>(
+        &self,
+        operation: &str,
+        mut input: serde_json::Value,
+    ) -> anyhow::Result<Result<O, ApiError>> {
+        if let Some(input) = input.as_object_mut() {
+            let _ = input.insert(
+                "IdentityStoreId".into(),
+                self.config.identity_store_id.clone().into(),
+            );
+        }
+        self.api.request(operation, input).await
+    }
+
+    async fn get_user_id(&self, user_name: &str) -> anyhow::Result<Option<String>> {
+        use anyhow::Context;
+
+        match self
+            .request::<UserIdResponse>(
+                "GetUserId",
+                serde_json::json!({
+                    "AlternateIdentifier": {
+                        "UniqueAttribute": {
+                            "AttributePath": "userName",
+                            "AttributeValue": user_name
+                        }
+                    }
+                }),
+            )
+            .await?
+        {
+            Ok(res) => Ok(Some(res.user_id)),
+            Err(err) if err.is("ResourceNotFoundException") => Ok(None),
+            Err(err) => Err(err).context("Error returned from server (get_user_id)"),
+        }
+    }
+
+    async fn get_group_membership_id(
+        &self,
+        group_id: &str,
+        user_id: &str,
+    ) -> anyhow::Result<Option<String>> {
+        use anyhow::Context;
+
+        match self
+            .request::<MembershipIdResponse>(
+                "GetGroupMembershipId",
+                serde_json::json!({
+                    "GroupId": group_id,
+                    "MemberId": { "UserId": user_id }
+                }),
+            )
+            .await?
+        {
+            Ok(res) => Ok(Some(res.membership_id)),
+            Err(err) if err.is("ResourceNotFoundException") => Ok(None),
+            Err(err) => Err(err).context("Error returned from server (get_group_membership_id)"),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl super::Backend for IdentityStore<'_> {
+    fn is_listing_complete(&self) -> bool {
+        true
+    }
+
+    async fn list_users(&self) -> anyhow::Result<Vec<super::User>> {
+        use anyhow::Context;
+
+        let mut entries = Vec::new();
+        let mut token = None;
+        loop {
+            let res = self
+                .request::<ListUsersResponse>(
+                    "ListUsers",
+                    serde_json::json!({ "MaxResults": MAX_RESULTS, "NextToken": token }),
+                )
+                .await?
+                .context("Error returned from server (list_users)")?;
+            entries.extend(res.users.into_iter().map(super::User::from));
+            if res.next_token.is_none() {
+                break;
+            }
+            token = res.next_token;
+        }
+        Ok(entries)
+    }
+
+    async fn get_user(&self, user_name: &str) -> anyhow::Result<Option<super::User>> {
+        use anyhow::Context;
+
+        let user_id = match self.get_user_id(user_name).await? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        match self
+            .request::<StoreUser>("DescribeUser", serde_json::json!({ "UserId": user_id }))
+            .await?
+        {
+            Ok(user) => Ok(Some(user.into())),
+            Err(err) if err.is("ResourceNotFoundException") => Ok(None),
+            Err(err) => Err(err).context("Error returned from server (get_user)"),
+        }
+    }
+
+    async fn create_user(&self, user: super::User) -> anyhow::Result<Option<super::User>> {
+        use anyhow::Context;
+
+        let input = serde_json::to_value(StoreUser::from(user))
+            .context("Unable to serialize user for AWS Identity Store")?;
+        match self.request::<UserIdResponse>("CreateUser", input).await? {
+            Ok(res) => {
+                let user = self
+                    .request::<StoreUser>(
+                        "DescribeUser",
+                        serde_json::json!({ "UserId": res.user_id }),
+                    )
+                    .await?
+                    .context("Error returned from server (create_user)")?;
+                Ok(Some(user.into()))
+            }
+            Err(err) if err.is("ConflictException") => Ok(None),
+            Err(err) => Err(err).context("Error returned from server (create_user)"),
+        }
+    }
+
+    async fn delete_user(&self, user_id: &str) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let _ = self
+            .request::<serde::de::IgnoredAny>(
+                "DeleteUser",
+                serde_json::json!({ "UserId": user_id }),
+            )
+            .await?
+            .context("Error returned from server (delete_user)")?;
+        Ok(())
+    }
+
+    async fn list_groups(&self) -> anyhow::Result<Vec<super::Group>> {
+        use anyhow::Context;
+
+        let mut entries = Vec::new();
+        let mut token = None;
+        loop {
+            let res = self
+                .request::<ListGroupsResponse>(
+                    "ListGroups",
+                    serde_json::json!({ "MaxResults": MAX_RESULTS, "NextToken": token }),
+                )
+                .await?
+                .context("Error returned from server (list_groups)")?;
+            entries.extend(res.groups.into_iter().filter_map(|g| {
+                Some(super::Group {
+                    id: Some(g.group_id),
+                    display_name: g.display_name?,
+                })
+            }));
+            if res.next_token.is_none() {
+                break;
+            }
+            token = res.next_token;
+        }
+        Ok(entries)
+    }
+
+    async fn get_group(&self, display_name: &str) -> anyhow::Result<Option<super::Group>> {
+        use anyhow::Context;
+
+        let res = match self
+            .request::<GroupIdResponse>(
+                "GetGroupId",
+                serde_json::json!({
+                    "AlternateIdentifier": {
+                        "UniqueAttribute": {
+                            "AttributePath": "displayName",
+                            "AttributeValue": display_name
+                        }
+                    }
+                }),
+            )
+            .await?
+        {
+            Ok(res) => res,
+            Err(err) if err.is("ResourceNotFoundException") => return Ok(None),
+            Err(err) => return Err(err).context("Error returned from server (get_group)"),
+        };
+        Ok(Some(super::Group {
+            id: Some(res.group_id),
+            display_name: display_name.into(),
+        }))
+    }
+
+    async fn create_group(&self, group: super::Group) -> anyhow::Result<Option<super::Group>> {
+        use anyhow::Context;
+
+        match self
+            .request::<GroupIdResponse>(
+                "CreateGroup",
+                serde_json::json!({ "DisplayName": group.display_name }),
+            )
+            .await?
+        {
+            Ok(res) => Ok(Some(super::Group {
+                id: Some(res.group_id),
+                display_name: group.display_name,
+            })),
+            Err(err) if err.is("ConflictException") => Ok(None),
+            Err(err) => Err(err).context("Error returned from server (create_group)"),
+        }
+    }
+
+    async fn delete_group(&self, group_id: &str) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let _ = self
+            .request::<serde::de::IgnoredAny>(
+                "DeleteGroup",
+                serde_json::json!({ "GroupId": group_id }),
+            )
+            .await?
+            .context("Error returned from server (delete_group)")?;
+        Ok(())
+    }
+
+    async fn is_group_member(&self, group_id: &str, user_id: &str) -> anyhow::Result<bool> {
+        use anyhow::Context;
+
+        let res = self
+            .request::<IsMemberInGroupsResponse>(
+                "IsMemberInGroups",
+                serde_json::json!({
+                    "MemberId": { "UserId": user_id },
+                    "GroupIds": [group_id]
+                }),
+            )
+            .await?
+            .context("Error returned from server (is_group_member)")?;
+        Ok(res
+            .results
+            .into_iter()
+            .any(|r| r.membership_exists == Some(true)))
+    }
+
+    async fn list_group_memberships_for_member(
+        &self,
+        user_id: &str,
+    ) -> anyhow::Result<Option<std::collections::HashSet<String>>> {
+        use anyhow::Context;
+
+        let mut entries = std::collections::HashSet::new();
+        let mut token = None;
+        loop {
+            let res = self
+                .request::<ListGroupMembershipsForMemberResponse>(
+                    "ListGroupMembershipsForMember",
+                    serde_json::json!({
+                        "MemberId": { "UserId": user_id },
+                        "MaxResults": MAX_RESULTS,
+                        "NextToken": token
+                    }),
+                )
+                .await?
+                .context("Error returned from server (list_group_memberships_for_member)")?;
+            entries.extend(res.group_memberships.into_iter().filter_map(|m| m.group_id));
+            if res.next_token.is_none() {
+                break;
+            }
+            token = res.next_token;
+        }
+        Ok(Some(entries))
+    }
+
+    async fn list_group_members(
+        &self,
+        group_id: &str,
+    ) -> anyhow::Result<Option<std::collections::HashSet<String>>> {
+        use anyhow::Context;
+
+        let mut entries = std::collections::HashSet::new();
+        let mut token = None;
+        loop {
+            let res = self
+                .request::<ListGroupMembershipsResponse>(
+                    "ListGroupMemberships",
+                    serde_json::json!({
+                        "GroupId": group_id,
+                        "MaxResults": MAX_RESULTS,
+                        "NextToken": token
+                    }),
+                )
+                .await?
+                .context("Error returned from server (list_group_members)")?;
+            entries.extend(
+                res.group_memberships
+                    .into_iter()
+                    .filter_map(|m| m.member_id.and_then(|m| m.user_id)),
+            );
+            if res.next_token.is_none() {
+                break;
+            }
+            token = res.next_token;
+        }
+        Ok(Some(entries))
+    }
+
+    async fn add_group_member(&self, group_id: &str, user_id: &str) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        match self
+            .request::<MembershipIdResponse>(
+                "CreateGroupMembership",
+                serde_json::json!({
+                    "GroupId": group_id,
+                    "MemberId": { "UserId": user_id }
+                }),
+            )
+            .await?
+        {
+            Ok(_) => Ok(()),
+            Err(err) if err.is("ConflictException") => Ok(()),
+            Err(err) => Err(err).context("Error returned from server (add_group_member)"),
+        }
+    }
+
+    async fn remove_group_member(&self, group_id: &str, user_id: &str) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let membership_id = match self.get_group_membership_id(group_id, user_id).await? {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        let _ = self
+            .request::<serde::de::IgnoredAny>(
+                "DeleteGroupMembership",
+                serde_json::json!({ "MembershipId": membership_id }),
+            )
+            .await?
+            .context("Error returned from server (remove_group_member)")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+const RETRY_ERRORS: &[&str] = &["ThrottlingException", "TooManyRequestsException"];
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ApiError {
+    #[serde(rename = "__type")]
+    r#type: String,
+    #[serde(alias = "Message")]
+    message: Option<String>,
+}
+
+impl ApiError {
+    pub(crate) fn is(&self, kind: &str) -> bool {
+        self.r#type.rsplit('#').next() == Some(kind)
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            self.r#type,
+            self.message.as_deref().unwrap_or("")
+        )
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Client for AWS APIs which use the JSON 1.1 protocol
+pub struct JsonApi {
+    name: &'static str,
+    service: &'static str,
+    target_prefix: &'static str,
+    region: rusoto_core::Region,
+    client: rusoto_core::Client,
+}
+
+impl std::fmt::Debug for JsonApi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonApi")
+            .field("name", &self.name)
+            .field("service", &self.service)
+            .field("target_prefix", &self.target_prefix)
+            .field("region", &self.region)
+            .field("client", &"[...]")
+            .finish()
+    }
+}
+
+impl JsonApi {
+    pub(crate) fn new(
+        name: &'static str,
+        service: &'static str,
+        target_prefix: &'static str,
+        region: rusoto_core::Region,
+    ) -> Self {
+        Self {
+            name,
+            service,
+            target_prefix,
+            region,
+            client: rusoto_core::Client::shared(),
+        }
+    }
+
+    pub(crate) fn region_from_str(region: &str) -> anyhow::Result<rusoto_core::Region> {
+        use anyhow::Context;
+        use std::str::FromStr;
+
+        rusoto_core::Region::from_str(region)
+            .with_context(|| format!("{region} is not a valid AWS Region."))
+    }
+
+    /// Sends a SigV4 signed request to the API. Null values are removed from the input.
+    /// Throttled requests are repeated, while all other errors returned by the API are
+    /// passed to the caller
+    pub(crate) async fn request<O: serde::de::DeserializeOwned>(
+        &self,
+        operation: &str,
+        mut input: serde_json::Value,
+    ) -> anyhow::Result<Result<O, ApiError>> {
+        use anyhow::Context;
+
+        if let Some(input) = input.as_object_mut() {
+            input.retain(|_, v| !v.is_null());
+        }
+        let name = self.name;
+        let payload = serde_json::to_vec(&input)
+            .with_context(|| format!("Unable to serialize request to {name} ({operation})"))?;
+        loop {
+            let mut request =
+                rusoto_core::signature::SignedRequest::new("POST", self.service, &self.region, "/");
+            request.set_content_type("application/x-amz-json-1.1".to_owned());
+            request.add_header(
+                "x-amz-target",
+                &format!("{}.{operation}", self.target_prefix),
+            );
+            request.set_payload(Some(payload.clone()));
+            let mut res = self
+                .client
+                .sign_and_dispatch(request)
+                .await
+                .map_err(rusoto_core::RusotoError::<std::convert::Infallible>::from)
+                .with_context(|| format!("Unable to send request to {name} ({operation})"))?;
+            let res = res
+                .buffer()
+                .await
+                .with_context(|| format!("Unable to read response from {name} ({operation})"))?;
+            if res.status.is_success() {
+                return serde_json::from_slice(&res.body)
+                    .map(Ok)
+                    .with_context(|| format!("Could not parse result from {name} ({operation})"));
+            }
+            let error = serde_json::from_slice::<ApiError>(&res.body).with_context(|| {
+                format!(
+                    "Error returned from server ({operation}): {} {}",
+                    res.status,
+                    String::from_utf8_lossy(&res.body)
+                )
+            })?;
+            if res.status.as_u16() == 429 || RETRY_ERRORS.iter().any(|kind| error.is(kind)) {
+                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
+                continue;
+            }
+            return Ok(Err(error));
+        }
+    }
+}
@@ -1,16 +1,92 @@
+mod backend;
+mod identitystore;
+mod jsonapi;
+mod lambda;
+mod organizations;
+mod s3;
 mod scim;
 mod smc;
+mod ssm;
+mod ssoadmin;
+mod sts;
 
-pub use scim::{Group, Scim, ScimCreds, User, UserMail, UserName};
+pub use backend::{Backend, MembershipChange};
+pub use identitystore::{IdentityStore, IdentityStoreConfig};
+pub use lambda::Lambda;
+pub use organizations::Organizations;
+pub use s3::S3;
+pub use scim::{
+    EnterpriseUser, Group, InvalidScimToken, Manager, Scim, ScimCreds, User, UserMail, UserName,
+};
+pub use ssm::Ssm;
+pub use ssoadmin::SsoAdmin;
+pub use sts::get_caller_identity_token;
 
-pub async fn get_secret_from_secret_manager<S: serde::de::DeserializeOwned>(
+/// Returns the value of the secret parsed from JSON
+pub async fn get_secret<S: serde::de::DeserializeOwned>(
     secret: &super::event::Secret,
 ) -> anyhow::Result<S> {
     use anyhow::Context;
+
+    let value = get_raw_secret(secret).await?;
+    serde_json::from_slice(&value).with_context(|| {
+        format!(
+            "Unable to parse secret value. Value does not confirm to required structure. Id: {}",
+            secret.id
+        )
+    })
+}
+
+/// Returns the unparsed value of the secret from the Secrets Manager,
+/// the SSM Parameter Store or a local file
+pub async fn get_raw_secret(secret: &super::event::Secret) -> anyhow::Result<Vec<u8>> {
+    use anyhow::Context;
     use std::str::FromStr;
 
-    let region = rusoto_core::Region::from_str(&secret.region)
-        .with_context(|| format!("{} is not a valid AWS Region.", secret.region))?;
-    let smc = smc::Smc::new(region);
-    smc.get_secret_value_current(&secret.id).await
+    match secret.source() {
+        super::event::SecretSource::SecretsManager => {
+            let region = rusoto_core::Region::from_str(&secret.region)
+                .with_context(|| format!("{} is not a valid AWS Region.", secret.region))?;
+            let smc = smc::Smc::new(region);
+            smc.get_secret_value_raw(&secret.id).await
+        }
+        super::event::SecretSource::Ssm => Ssm::new(&secret.region)?
+            .get_parameter(&secret.id)
+            .await?
+            .map(String::into_bytes)
+            .with_context(|| format!("Unable to find SSM parameter with name: {}", secret.id)),
+        super::event::SecretSource::File => std::fs::read(&secret.id)
+            .with_context(|| format!("Unable to read secret from file: {}", secret.id)),
+    }
+}
+
+/// Checks whether the given result is a throttling error
+/// and waits for 250 ms if it is
+async fn is_wait_and_repeat<D: Send + Sync, E: std::fmt::Debug + Send + Sync>(
+    error: &Result<D, rusoto_core::RusotoError<E>>,
+) -> bool {
+    if let Err(rusoto_core::RusotoError::Unknown(rusoto_core::request::BufferedHttpResponse {
+        ref status,
+        ref body,
+        ..
+    })) = *error
+    {
+        let cooldown = match status.as_u16() {
+            400 => {
+                let search = b"ThrottlingException";
+                body.as_ref().windows(search.len()).any(|sub| sub == search)
+            }
+            429 => {
+                let search = b"Too Many Requests";
+                body.as_ref().windows(search.len()).any(|sub| sub == search)
+            }
+            _ => false,
+        };
+        if cooldown {
+            println!("Info: Cooling down to prevent request limits");
+            tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
+            return true;
+        }
+    }
+    false
 }
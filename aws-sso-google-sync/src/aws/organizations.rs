@@ -0,0 +1,106 @@
+use super::jsonapi::JsonApi;
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Account {
+    id: String,
+    status: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListAccountsForParentResponse {
+    accounts: Vec<Account>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct OrganizationalUnit {
+    id: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListOrganizationalUnitsForParentResponse {
+    organizational_units: Vec<OrganizationalUnit>,
+    next_token: Option<String>,
+}
+
+/// Client for the AWS Organizations API
+#[derive(Debug)]
+pub struct Organizations {
+    api: JsonApi,
+}
+
+impl Organizations {
+    pub(crate) fn new() -> Self {
+        Self {
+            api: JsonApi::new(
+                "AWS Organizations",
+                "organizations",
+                "AWSOrganizationsV20161128",
+                rusoto_core::Region::UsEast1,
+            ),
+        }
+    }
+
+    /// Returns the ids of all active accounts in the organizational unit
+    /// and all of its child organizational units
+    pub(crate) async fn list_accounts_for_ou(&self, ou_id: &str) -> anyhow::Result<Vec<String>> {
+        use anyhow::Context;
+
+        let mut account_ids = Vec::new();
+        let mut parents = vec![ou_id.to_owned()];
+        while let Some(parent) = parents.pop() {
+            let mut next_token = None;
+            loop {
+                let res: ListAccountsForParentResponse = self
+                    .api
+                    .request(
+                        "ListAccountsForParent",
+                        serde_json::json!({
+                            "ParentId": parent,
+                            "NextToken": next_token,
+                        }),
+                    )
+                    .await?
+                    .with_context(|| {
+                        format!("Error returned from server (list_accounts_for_ou): {parent}")
+                    })?;
+                account_ids.extend(
+                    res.accounts
+                        .into_iter()
+                        .filter(|a| a.status.as_deref().is_none_or(|s| s == "ACTIVE"))
+                        .map(|a| a.id),
+                );
+                next_token = res.next_token;
+                if next_token.is_none() {
+                    break;
+                }
+            }
+            let mut next_token = None;
+            loop {
+                let res: ListOrganizationalUnitsForParentResponse = self
+                    .api
+                    .request(
+                        "ListOrganizationalUnitsForParent",
+                        serde_json::json!({
+                            "ParentId": parent,
+                            "NextToken": next_token,
+                        }),
+                    )
+                    .await?
+                    .with_context(|| {
+                        format!("Error returned from server (list_accounts_for_ou): {parent}")
+                    })?;
+                parents.extend(res.organizational_units.into_iter().map(|ou| ou.id));
+                next_token = res.next_token;
+                if next_token.is_none() {
+                    break;
+                }
+            }
+        }
+        Ok(account_ids)
+    }
+}
@@ -0,0 +1,107 @@
+/// Client to read and write objects in Amazon S3
+pub struct S3 {
+    region: rusoto_core::Region,
+    client: rusoto_core::Client,
+}
+
+impl std::fmt::Debug for S3 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3")
+            .field("region", &self.region)
+            .field("client", &"[...]")
+            .finish()
+    }
+}
+
+impl S3 {
+    pub(crate) fn new(region: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            region: super::jsonapi::JsonApi::region_from_str(region)?,
+            client: rusoto_core::Client::shared(),
+        })
+    }
+
+    /// Returns the content of the object. Throttled requests are repeated
+    pub(crate) async fn get_object(&self, bucket: &str, key: &str) -> anyhow::Result<Vec<u8>> {
+        use anyhow::{bail, Context};
+
+        loop {
+            let request = rusoto_core::signature::SignedRequest::new(
+                "GET",
+                "s3",
+                &self.region,
+                &format!("/{bucket}/{key}"),
+            );
+            let mut res = self
+                .client
+                .sign_and_dispatch(request)
+                .await
+                .map_err(rusoto_core::RusotoError::<std::convert::Infallible>::from)
+                .with_context(|| {
+                    format!("Unable to send request to AWS S3 (get_object): s3://{bucket}/{key}")
+                })?;
+            let res = res.buffer().await.with_context(|| {
+                format!("Unable to read response from AWS S3 (get_object): s3://{bucket}/{key}")
+            })?;
+            if res.status.is_success() {
+                return Ok(res.body.to_vec());
+            }
+            if res.status.as_u16() == 429 || res.status.as_u16() == 503 {
+                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
+                continue;
+            }
+            bail!(
+                "Error returned from server (get_object): s3://{bucket}/{key} {} {}",
+                res.status,
+                String::from_utf8_lossy(&res.body)
+            );
+        }
+    }
+
+    /// Creates the object. Fails if it already exists, so existing objects
+    /// are never overwritten. Throttled requests are repeated
+    pub(crate) async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        content: &[u8],
+        content_type: &str,
+    ) -> anyhow::Result<()> {
+        use anyhow::{bail, Context};
+
+        loop {
+            let mut request = rusoto_core::signature::SignedRequest::new(
+                "PUT",
+                "s3",
+                &self.region,
+                &format!("/{bucket}/{key}"),
+            );
+            request.set_content_type(content_type.to_owned());
+            request.add_header("If-None-Match", "*");
+            request.set_payload(Some(content.to_vec()));
+            let mut res = self
+                .client
+                .sign_and_dispatch(request)
+                .await
+                .map_err(rusoto_core::RusotoError::<std::convert::Infallible>::from)
+                .with_context(|| {
+                    format!("Unable to send request to AWS S3 (put_object): s3://{bucket}/{key}")
+                })?;
+            let res = res.buffer().await.with_context(|| {
+                format!("Unable to read response from AWS S3 (put_object): s3://{bucket}/{key}")
+            })?;
+            if res.status.is_success() {
+                return Ok(());
+            }
+            if res.status.as_u16() == 429 || res.status.as_u16() == 503 {
+                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
+                continue;
+            }
+            bail!(
+                "Error returned from server (put_object): s3://{bucket}/{key} {} {}",
+                res.status,
+                String::from_utf8_lossy(&res.body)
+            );
+        }
+    }
+}
@@ -1,10 +1,44 @@
+const SCIM_TOKEN_LIFETIME_DAYS: i64 = 365;
+const DEFAULT_TOKEN_EXPIRY_WARNING_DAYS: u32 = 30;
+/// Limits of AWS SSO for the user name and for all other user attributes
+const MAX_USER_NAME_LEN: usize = 128;
+const MAX_ATTRIBUTE_LEN: usize = 1024;
+const ENTERPRISE_MANAGER_PATH: &str =
+    "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User:manager";
+
 #[derive(Debug, serde::Deserialize)]
 struct ListResponse<Inner> {
-    #[serde(rename = "Resources")]
+    #[serde(rename = "Resources", default = "Vec::new")]
     resources: Vec<Inner>,
+    #[serde(rename = "totalResults")]
+    total_results: Option<usize>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct GroupMembers {
+    #[serde(default)]
+    members: Vec<GroupMember>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct GroupMember {
+    value: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct BulkResponse {
+    #[serde(rename = "Operations", default)]
+    operations: Vec<BulkOperationResponse>,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, serde::Deserialize)]
+struct BulkOperationResponse {
+    path: Option<String>,
+    status: String,
+    response: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Group {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) id: Option<String>,
@@ -12,7 +46,7 @@ pub struct Group {
     pub(crate) display_name: String,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct User {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) id: Option<String>,
@@ -27,9 +61,61 @@ pub struct User {
     pub(crate) profile_url: Option<String>,
     pub(crate) emails: Option<Vec<UserMail>>,
     pub(crate) active: bool,
+    #[serde(
+        rename = "urn:ietf:params:scim:schemas:extension:enterprise:2.0:User",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) enterprise: Option<EnterpriseUser>,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+impl User {
+    /// Returns the id of the manager of the user
+    pub(crate) fn manager_id(&self) -> Option<&str> {
+        self.enterprise
+            .as_ref()
+            .and_then(|e| e.manager.as_ref())
+            .map(|m| m.value.as_str())
+    }
+}
+
+impl User {
+    /// Verifies the user against the constraints of AWS SSO, so that an invalid user
+    /// is skipped instead of failing the request
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        use anyhow::bail;
+
+        if self.user_name.is_empty() || self.user_name.chars().count() > MAX_USER_NAME_LEN {
+            bail!("userName must have between 1 and {MAX_USER_NAME_LEN} characters");
+        }
+        if self.user_name.chars().any(char::is_whitespace) {
+            bail!("userName must not contain whitespace");
+        }
+        let attributes = [
+            ("displayName", Some(&self.display_name)),
+            ("name.givenName", Some(&self.name.given_name)),
+            ("name.familyName", Some(&self.name.family_name)),
+            ("name.formatted", self.name.formatted.as_ref()),
+        ];
+        for (name, value) in attributes {
+            let value = match value {
+                Some(v) => v,
+                None => continue,
+            };
+            if value.trim().is_empty() || value.chars().count() > MAX_ATTRIBUTE_LEN {
+                bail!("{name} must have between 1 and {MAX_ATTRIBUTE_LEN} characters");
+            }
+        }
+        for email in self.emails.iter().flatten() {
+            if email.value.trim().is_empty() || email.value.chars().count() > MAX_ATTRIBUTE_LEN {
+                bail!("emails must have between 1 and {MAX_ATTRIBUTE_LEN} characters");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct UserName {
     #[serde(rename = "formatted", skip_serializing_if = "Option::is_none")]
     pub(crate) formatted: Option<String>,
@@ -39,7 +125,19 @@ pub struct UserName {
     pub(crate) given_name: String,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+/// Attributes of the SCIM enterprise user extension
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct EnterpriseUser {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) manager: Option<Manager>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Manager {
+    pub(crate) value: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct UserMail {
     pub(crate) value: String,
     #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
@@ -48,405 +146,643 @@ pub struct UserMail {
     pub(crate) primary: Option<bool>,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterOperator {
+    Eq,
+    And,
+}
+
+#[derive(Debug, Copy, Clone, serde::Deserialize)]
+pub enum PatchPathStyle {
+    /// Members are removed with `path: members` and the member in `value`
+    Members,
+    /// Members are removed with `path: members[value eq "<id>"]`
+    MemberFilter,
+}
+
+/// Capabilities of a SCIM 2.0 service provider. Defaults to the capabilities of AWS SSO
+#[derive(Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct ScimProfile {
+    filter_operators: Vec<FilterOperator>,
+    pagination: bool,
+    page_size: usize,
+    patch_path_style: PatchPathStyle,
+    members_on_get_groups: bool,
+    bulk: bool,
+    bulk_max_operations: usize,
+    multiple_emails: bool,
+}
+
+impl Default for ScimProfile {
+    fn default() -> Self {
+        Self {
+            filter_operators: vec![FilterOperator::Eq, FilterOperator::And],
+            pagination: false,
+            page_size: 100,
+            patch_path_style: PatchPathStyle::Members,
+            members_on_get_groups: false,
+            bulk: false,
+            bulk_max_operations: 100,
+            multiple_emails: false,
+        }
+    }
+}
+
+impl ScimProfile {
+    fn supports(&self, operator: FilterOperator) -> bool {
+        self.filter_operators.contains(&operator)
+    }
+
+    /// Whether group memberships can be checked with `id eq ... and members eq ...`
+    fn supports_membership_filter(&self) -> bool {
+        self.supports(FilterOperator::Eq) && self.supports(FilterOperator::And)
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct ScimCreds {
     endpoint: String,
     access_token: String,
+    #[serde(default)]
+    profile: ScimProfile,
+    #[serde(default)]
+    token_created: Option<chrono::NaiveDate>,
+    #[serde(default)]
+    token_expires: Option<chrono::NaiveDate>,
+    #[serde(default)]
+    token_expiry_warning_days: Option<u32>,
+}
+
+impl ScimCreds {
+    /// Expiry date of the access token. Tokens of AWS SSO are valid for one year
+    /// after their creation
+    fn token_expiry(&self) -> Option<chrono::NaiveDate> {
+        self.token_expires.or_else(|| {
+            self.token_created
+                .map(|d| d + chrono::Duration::days(SCIM_TOKEN_LIFETIME_DAYS))
+        })
+    }
+
+    /// Days until the access token expires or `None` if the expiry date is unknown
+    pub(crate) fn token_days_left(&self, today: chrono::NaiveDate) -> Option<i64> {
+        self.token_expiry().map(|d| (d - today).num_days())
+    }
+
+    pub(crate) fn is_token_expiring(&self, days_left: i64) -> bool {
+        days_left
+            <= i64::from(
+                self.token_expiry_warning_days
+                    .unwrap_or(DEFAULT_TOKEN_EXPIRY_WARNING_DAYS),
+            )
+    }
+
+    pub(crate) fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
 }
 
+/// Returned if the SCIM endpoint rejects the access token, which usually means
+/// that it expired and has to be rotated
+#[derive(Debug)]
+pub struct InvalidScimToken {
+    endpoint: String,
+    expiry: Option<chrono::NaiveDate>,
+}
+
+impl std::fmt::Display for InvalidScimToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The SCIM endpoint {} rejected the access token",
+            self.endpoint
+        )?;
+        if let Some(expiry) = self.expiry {
+            write!(f, " (expiry date: {expiry})")?;
+        }
+        write!(
+            f,
+            ". Generate a new access token in the AWS SSO console and update the secret."
+        )
+    }
+}
+
+impl std::error::Error for InvalidScimToken {}
+
 #[derive(Debug)]
 pub struct Scim<'a> {
     secret: &'a ScimCreds,
     client: reqwest::Client,
+    throttle: crate::throttle::Throttle,
 }
 
 impl<'a> Scim<'a> {
-    pub(crate) fn new(secret: &'a ScimCreds) -> Self {
-        Self {
+    pub(crate) fn new(secret: &'a ScimCreds) -> anyhow::Result<Self> {
+        use anyhow::bail;
+
+        let profile = &secret.profile;
+        if !profile.supports_membership_filter() && !profile.members_on_get_groups {
+            bail!("The SCIM profile of {} must either support the filter operators eq and and or return members on GET /Groups.", secret.endpoint);
+        }
+        if profile.page_size == 0 || profile.bulk_max_operations == 0 {
+            bail!(
+                "The SCIM profile of {} requires a page_size and bulk_max_operations greater than 0.",
+                secret.endpoint
+            );
+        }
+        Ok(Self {
             secret,
             client: reqwest::Client::new(),
-        }
+            throttle: crate::throttle::Throttle::default(),
+        })
     }
 
-    pub(crate) async fn list_users(&self) -> anyhow::Result<Vec<User>> {
+    /// Sends a request to the SCIM endpoint and repeats it while being throttled.
+    /// Concurrent requests back off together
+    async fn send(
+        &self,
+        operation: &str,
+        method: reqwest::Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<&serde_json::Value>,
+    ) -> anyhow::Result<reqwest::Response> {
         use anyhow::Context;
 
         loop {
-            let res = self
+            let mut req = self
                 .client
-                .request(
-                    reqwest::Method::GET,
-                    &format!("{}/Users", &self.secret.endpoint),
-                )
+                .request(method.clone(), format!("{}{}", &self.secret.endpoint, path))
                 .header(
                     "Authorization",
                     format!("Bearer {}", &self.secret.access_token),
                 )
+                .query(query);
+            if let Some(body) = body {
+                req = req.json(body);
+            }
+            self.throttle.wait().await;
+            let res = req
                 .send()
                 .await
-                .context("Unable to send request to AWS SCIM (list_users)")?;
+                .with_context(|| format!("Unable to send request to SCIM ({operation})"))?;
             if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
+                self.throttle.throttled().await;
                 continue;
             }
-            return res
-                .error_for_status()
-                .context("Error returned from server (list_users)")?
-                .json::<ListResponse<User>>()
-                .await
-                .map(|d| d.resources)
-                .context("Could not parse result from AWS SCIM (list_users)");
+            if res.status() == reqwest::StatusCode::UNAUTHORIZED {
+                return Err(InvalidScimToken {
+                    endpoint: self.secret.endpoint.clone(),
+                    expiry: self.secret.token_expiry(),
+                })
+                .with_context(|| format!("Error returned from server ({operation})"));
+            }
+            return Ok(res);
         }
     }
 
-    pub(crate) async fn get_user(&self, primary_email: &str) -> anyhow::Result<Option<User>> {
+    /// Lists all resources of the given path. Without pagination, only the
+    /// first page returned by the service provider is listed
+    async fn list<Inner: serde::de::DeserializeOwned>(
+        &self,
+        operation: &str,
+        path: &str,
+        filter: Option<String>,
+    ) -> anyhow::Result<Vec<Inner>> {
         use anyhow::Context;
 
+        let profile = &self.secret.profile;
+        let mut resources = Vec::new();
         loop {
-            let res = self
-                .client
-                .request(
-                    reqwest::Method::GET,
-                    &format!("{}/Users?userName={}", &self.secret.endpoint, primary_email),
-                )
-                .header(
-                    "Authorization",
-                    format!("Bearer {}", &self.secret.access_token),
-                )
-                .send()
-                .await
-                .context("Unable to send request to AWS SCIM (get_user)")?;
-            if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
-                continue;
+            let mut query = Vec::new();
+            if let Some(ref filter) = filter {
+                query.push(("filter", filter.clone()));
             }
+            if profile.pagination {
+                query.push(("startIndex", (resources.len() + 1).to_string()));
+                query.push(("count", profile.page_size.to_string()));
+            }
+            let res = self
+                .send(operation, reqwest::Method::GET, path, &query, None)
+                .await?;
             if res.status() == reqwest::StatusCode::NOT_FOUND {
-                return Ok(None);
+                return Ok(resources);
             }
-            return res
+            let page = res
                 .error_for_status()
-                .context("Error returned from server (get_user)")?
-                .json::<ListResponse<User>>()
+                .with_context(|| format!("Error returned from server ({operation})"))?
+                .json::<ListResponse<Inner>>()
                 .await
-                .context("Could not parse result from AWS SCIM (get_user)")
-                .map(|mut d| d.resources.pop());
+                .with_context(|| format!("Could not parse result from SCIM ({operation})"))?;
+            let page_len = page.resources.len();
+            resources.extend(page.resources);
+            if !profile.pagination
+                || page_len == 0
+                || page
+                    .total_results
+                    .is_none_or(|total| resources.len() >= total)
+            {
+                return Ok(resources);
+            }
         }
     }
 
-    pub(crate) async fn create_user(&self, user: User) -> anyhow::Result<Option<User>> {
+    /// Returns the ids of all members of the group. Requires `members_on_get_groups`
+    async fn fetch_group_members(
+        &self,
+        operation: &str,
+        group_id: &str,
+    ) -> anyhow::Result<std::collections::HashSet<String>> {
         use anyhow::Context;
 
-        loop {
-            let res = self
-                .client
-                .request(
-                    reqwest::Method::POST,
-                    &format!("{}/Users", &self.secret.endpoint),
-                )
-                .header(
-                    "Authorization",
-                    format!("Bearer {}", &self.secret.access_token),
-                )
-                .json(&user)
-                .send()
-                .await
-                .context("Unable to send request to AWS SCIM (create_user)")?;
-            if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
-                continue;
-            }
-            if res.status() == reqwest::StatusCode::CONFLICT {
-                return Ok(None);
+        self.send(
+            operation,
+            reqwest::Method::GET,
+            &format!("/Groups/{group_id}"),
+            &[("attributes", "members".into())],
+            None,
+        )
+        .await?
+        .error_for_status()
+        .with_context(|| format!("Error returned from server ({operation})"))?
+        .json::<GroupMembers>()
+        .await
+        .with_context(|| format!("Could not parse result from SCIM ({operation})"))
+        .map(|g| g.members.into_iter().map(|m| m.value).collect())
+    }
+
+    fn eq_filter(attribute: &str, value: &str) -> String {
+        format!(
+            "{attribute} eq \"{}\"",
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        )
+    }
+
+    fn patch_op(&self, change: &super::MembershipChange<'_>) -> serde_json::Value {
+        let operation = match *change {
+            super::MembershipChange::Add { user_id, .. } => serde_json::json!({
+                "op": "add",
+                "path": "members",
+                "value": [{ "value": user_id }]
+            }),
+            super::MembershipChange::Remove { user_id, .. } => {
+                match self.secret.profile.patch_path_style {
+                    PatchPathStyle::Members => serde_json::json!({
+                        "op": "remove",
+                        "path": "members",
+                        "value": [{ "value": user_id }]
+                    }),
+                    PatchPathStyle::MemberFilter => serde_json::json!({
+                        "op": "remove",
+                        "path": format!("members[{}]", Self::eq_filter("value", user_id)),
+                    }),
+                }
             }
-            return res
-                .error_for_status()
-                .context("Error returned from server (create_user)")?
-                .json::<User>()
-                .await
-                .context("Could not parse result from AWS SCIM (create_user)")
-                .map(Some);
-        }
+        };
+        serde_json::json!({
+           "schemas": [
+              "urn:ietf:params:scim:api:messages:2.0:PatchOp"
+           ],
+           "Operations": [operation]
+        })
     }
 
-    pub(crate) async fn delete_user(&self, user_id: &str) -> anyhow::Result<()> {
+    async fn patch_group(
+        &self,
+        operation: &str,
+        change: &super::MembershipChange<'_>,
+    ) -> anyhow::Result<()> {
         use anyhow::Context;
 
-        loop {
-            let res = self
-                .client
-                .request(
-                    reqwest::Method::DELETE,
-                    &format!("{}/Users/{}", &self.secret.endpoint, user_id),
-                )
-                .header(
-                    "Authorization",
-                    format!("Bearer {}", &self.secret.access_token),
-                )
-                .send()
-                .await
-                .context("Unable to send request to AWS SCIM (delete_user)")?;
-            if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
-                continue;
+        let _ = self
+            .send(
+                operation,
+                reqwest::Method::PATCH,
+                &format!("/Groups/{}", change.group_id()),
+                &[],
+                Some(&self.patch_op(change)),
+            )
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Error returned from server ({operation})"))?;
+        Ok(())
+    }
+
+    /// Applies the changes with a single bulk request. `applied` is called with the
+    /// position of each change within `changes` which succeeded
+    async fn bulk(
+        &self,
+        changes: &[super::MembershipChange<'_>],
+        applied: &(dyn Fn(usize) + Sync),
+    ) -> anyhow::Result<()> {
+        use anyhow::{bail, Context};
+
+        let operations = changes
+            .iter()
+            .map(|change| {
+                serde_json::json!({
+                    "method": "PATCH",
+                    "path": format!("/Groups/{}", change.group_id()),
+                    "data": self.patch_op(change),
+                })
+            })
+            .collect::<Vec<_>>();
+        let res = self
+            .send(
+                "bulk",
+                reqwest::Method::POST,
+                "/Bulk",
+                &[],
+                Some(&serde_json::json!({
+                    "schemas": ["urn:ietf:params:scim:api:messages:2.0:BulkRequest"],
+                    "failOnErrors": 1,
+                    "Operations": operations,
+                })),
+            )
+            .await?
+            .error_for_status()
+            .context("Error returned from server (bulk)")?
+            .json::<BulkResponse>()
+            .await
+            .context("Could not parse result from SCIM (bulk)")?;
+        // The operations of the response are in the order of the request. With
+        // `failOnErrors` the operations after the first failure are missing
+        for (index, operation) in res.operations.iter().take(changes.len()).enumerate() {
+            if operation.status.starts_with('2') {
+                applied(index);
             }
-            let _ = res
-                .error_for_status()
-                .context("Error returned from server (delete_user)")?;
-            return Ok(());
         }
+        let failed = res
+            .operations
+            .iter()
+            .filter(|o| !o.status.starts_with('2'))
+            .map(|o| {
+                format!(
+                    "{} {} {}",
+                    o.path.as_deref().unwrap_or_default(),
+                    o.status,
+                    o.response
+                        .as_ref()
+                        .map(ToString::to_string)
+                        .unwrap_or_default()
+                )
+            })
+            .collect::<Vec<_>>();
+        if !failed.is_empty() {
+            bail!("Error returned from server (bulk): {}", failed.join(", "));
+        }
+        Ok(())
     }
+}
 
-    pub(crate) async fn list_groups(&self) -> anyhow::Result<Vec<Group>> {
-        use anyhow::Context;
+#[async_trait::async_trait]
+impl super::Backend for Scim<'_> {
+    fn is_listing_complete(&self) -> bool {
+        self.secret.profile.pagination
+    }
 
-        loop {
-            let res = self
-                .client
-                .request(
-                    reqwest::Method::GET,
-                    &format!("{}/Groups", &self.secret.endpoint),
-                )
-                .header(
-                    "Authorization",
-                    format!("Bearer {}", &self.secret.access_token),
-                )
-                .send()
-                .await
-                .context("Unable to send request to AWS SCIM (list_groups)")?;
-            if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
-                continue;
-            }
-            return res
-                .error_for_status()
-                .context("Error returned from server (list_groups)")?
-                .json::<ListResponse<Group>>()
-                .await
-                .map(|d| d.resources)
-                .context("Could not parse result from AWS SCIM (list_groups)");
+    async fn list_users(&self) -> anyhow::Result<Vec<User>> {
+        self.list("list_users", "/Users", None).await
+    }
+
+    fn is_manager_supported(&self) -> bool {
+        true
+    }
+
+    fn is_multiple_emails_supported(&self) -> bool {
+        self.secret.profile.multiple_emails
+    }
+
+    async fn get_user(&self, user_name: &str) -> anyhow::Result<Option<User>> {
+        if !self.secret.profile.supports(FilterOperator::Eq) {
+            return Ok(self
+                .list::<User>("get_user", "/Users", None)
+                .await?
+                .into_iter()
+                .find(|u| u.user_name == user_name));
         }
+        Ok(self
+            .list(
+                "get_user",
+                "/Users",
+                Some(Self::eq_filter("userName", user_name)),
+            )
+            .await?
+            .pop())
     }
 
-    pub(crate) async fn get_group(&self, display_name: &str) -> anyhow::Result<Group> {
+    async fn create_user(&self, user: User) -> anyhow::Result<Option<User>> {
         use anyhow::Context;
 
-        loop {
-            let res = self
-                .client
-                .request(
-                    reqwest::Method::GET,
-                    &format!(
-                        "{}/Groups?displayName={}",
-                        &self.secret.endpoint, display_name
-                    ),
-                )
-                .header(
-                    "Authorization",
-                    format!("Bearer {}", &self.secret.access_token),
-                )
-                .send()
-                .await
-                .context("Unable to send request to AWS SCIM (get_group)")?;
-            if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
-                continue;
-            }
-            return res
-                .error_for_status()
-                .context("Error returned from server (get_group)")?
-                .json::<ListResponse<Group>>()
-                .await
-                .context("Could not parse result from AWS SCIM (get_group)")
-                .and_then(|mut d| {
-                    d.resources
-                        .pop()
-                        .with_context(|| format!("Unable to find group with name: {display_name}"))
-                });
+        let res = self
+            .send(
+                "create_user",
+                reqwest::Method::POST,
+                "/Users",
+                &[],
+                Some(&serde_json::to_value(&user).context("Unable to serialize user")?),
+            )
+            .await?;
+        if res.status() == reqwest::StatusCode::CONFLICT {
+            return Ok(None);
         }
+        res.error_for_status()
+            .context("Error returned from server (create_user)")?
+            .json::<User>()
+            .await
+            .context("Could not parse result from SCIM (create_user)")
+            .map(Some)
     }
 
-    pub(crate) async fn create_group(&self, group: Group) -> anyhow::Result<Option<Group>> {
+    async fn delete_user(&self, user_id: &str) -> anyhow::Result<()> {
         use anyhow::Context;
 
-        loop {
-            let res = self
-                .client
-                .request(
-                    reqwest::Method::POST,
-                    &format!("{}/Groups", &self.secret.endpoint),
-                )
-                .header(
-                    "Authorization",
-                    format!("Bearer {}", &self.secret.access_token),
-                )
-                .json(&group)
-                .send()
-                .await
-                .context("Unable to send request to AWS SCIM (create_group)")?;
-            if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
-                continue;
-            }
-            if res.status() == reqwest::StatusCode::CONFLICT {
-                return Ok(None);
-            }
-            return res
-                .error_for_status()
-                .context("Error returned from server (create_group)")?
-                .json::<Group>()
-                .await
-                .context("Could not parse result from AWS SCIM (create_group)")
-                .map(Some);
-        }
+        let _ = self
+            .send(
+                "delete_user",
+                reqwest::Method::DELETE,
+                &format!("/Users/{user_id}"),
+                &[],
+                None,
+            )
+            .await?
+            .error_for_status()
+            .context("Error returned from server (delete_user)")?;
+        Ok(())
+    }
+
+    async fn list_groups(&self) -> anyhow::Result<Vec<Group>> {
+        self.list("list_groups", "/Groups", None).await
     }
 
-    pub(crate) async fn delete_group(&self, group_id: &str) -> anyhow::Result<()> {
+    async fn get_group(&self, display_name: &str) -> anyhow::Result<Option<Group>> {
+        let groups = if self.secret.profile.supports(FilterOperator::Eq) {
+            self.list::<Group>(
+                "get_group",
+                "/Groups",
+                Some(Self::eq_filter("displayName", display_name)),
+            )
+            .await?
+        } else {
+            self.list("get_group", "/Groups", None).await?
+        };
+        Ok(groups
+            .into_iter()
+            .rev()
+            .find(|g| g.display_name == display_name))
+    }
+
+    async fn create_group(&self, group: Group) -> anyhow::Result<Option<Group>> {
         use anyhow::Context;
 
-        loop {
-            let res = self
-                .client
-                .request(
-                    reqwest::Method::DELETE,
-                    &format!("{}/Groups/{}", &self.secret.endpoint, group_id),
-                )
-                .header(
-                    "Authorization",
-                    format!("Bearer {}", &self.secret.access_token),
-                )
-                .send()
-                .await
-                .context("Unable to send request to AWS SCIM (delete_group)")?;
-            if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
-                continue;
-            }
-            let _ = res
-                .error_for_status()
-                .context("Error returned from server (delete_group)")?;
-            return Ok(());
+        let res = self
+            .send(
+                "create_group",
+                reqwest::Method::POST,
+                "/Groups",
+                &[],
+                Some(&serde_json::to_value(&group).context("Unable to serialize group")?),
+            )
+            .await?;
+        if res.status() == reqwest::StatusCode::CONFLICT {
+            return Ok(None);
         }
+        res.error_for_status()
+            .context("Error returned from server (create_group)")?
+            .json::<Group>()
+            .await
+            .context("Could not parse result from SCIM (create_group)")
+            .map(Some)
     }
 
-    pub(crate) async fn is_group_member(
-        &self,
-        group_id: &str,
-        user_id: &str,
-    ) -> anyhow::Result<bool> {
+    async fn delete_group(&self, group_id: &str) -> anyhow::Result<()> {
         use anyhow::Context;
 
-        loop {
-            let res = self
-                .client
-                .request(
-                    reqwest::Method::GET,
-                    &format!(
-                        "{}/Groups?filter=id eq \"{}\" and members eq \"{}\"",
-                        &self.secret.endpoint, group_id, user_id
-                    ),
-                )
-                .header(
-                    "Authorization",
-                    format!("Bearer {}", &self.secret.access_token),
-                )
-                .send()
-                .await
-                .context("Unable to send request to AWS SCIM (is_group_member)")?;
-            if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
-                continue;
-            }
-            return res
-                .error_for_status()
-                .context("Error returned from server (is_group_member)")?
-                .json::<ListResponse<Group>>()
-                .await
-                .context("Could not parse result from AWS SCIM (is_group_member)")
-                .map(|d| !d.resources.is_empty());
+        let _ = self
+            .send(
+                "delete_group",
+                reqwest::Method::DELETE,
+                &format!("/Groups/{group_id}"),
+                &[],
+                None,
+            )
+            .await?
+            .error_for_status()
+            .context("Error returned from server (delete_group)")?;
+        Ok(())
+    }
+
+    async fn is_group_member(&self, group_id: &str, user_id: &str) -> anyhow::Result<bool> {
+        let profile = &self.secret.profile;
+        if profile.supports_membership_filter() {
+            let filter = format!(
+                "{} and {}",
+                Self::eq_filter("id", group_id),
+                Self::eq_filter("members", user_id)
+            );
+            return Ok(!self
+                .list::<Group>("is_group_member", "/Groups", Some(filter))
+                .await?
+                .is_empty());
         }
+        Ok(self
+            .fetch_group_members("is_group_member", group_id)
+            .await?
+            .contains(user_id))
     }
 
-    pub(crate) async fn add_group_member(
+    async fn list_group_members(
         &self,
         group_id: &str,
+    ) -> anyhow::Result<Option<std::collections::HashSet<String>>> {
+        if !self.secret.profile.members_on_get_groups {
+            return Ok(None);
+        }
+        self.fetch_group_members("list_group_members", group_id)
+            .await
+            .map(Some)
+    }
+
+    async fn set_user_manager(
+        &self,
         user_id: &str,
+        manager_id: Option<&str>,
     ) -> anyhow::Result<()> {
         use anyhow::Context;
 
-        loop {
-            let res = self
-                .client
-                .request(
-                    reqwest::Method::PATCH,
-                    &format!("{}/Groups/{}", &self.secret.endpoint, group_id),
-                )
-                .header(
-                    "Authorization",
-                    format!("Bearer {}", &self.secret.access_token),
-                )
-                .json(&serde_json::json!({
-                   "schemas": [
-                      "urn:ietf:params:scim:api:messages:2.0:PatchOp"
-                   ],
-                   "Operations": [{
-                         "op": "add",
-                         "path": "members",
-                         "value": [{ "value": user_id }]
-                    }]
-                }))
-                .send()
-                .await
-                .context("Unable to send request to AWS SCIM (add_group_member)")?;
-            if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
-                continue;
-            }
-            let _ = res
-                .error_for_status()
-                .context("Error returned from server (add_group_member)")?;
-            return Ok(());
-        }
+        let operation = manager_id.map_or_else(
+            || serde_json::json!({ "op": "remove", "path": ENTERPRISE_MANAGER_PATH }),
+            |manager_id| {
+                serde_json::json!({
+                    "op": "replace",
+                    "path": ENTERPRISE_MANAGER_PATH,
+                    "value": { "value": manager_id }
+                })
+            },
+        );
+        let _ = self
+            .send(
+                "set_user_manager",
+                reqwest::Method::PATCH,
+                &format!("/Users/{user_id}"),
+                &[],
+                Some(&serde_json::json!({
+                    "schemas": ["urn:ietf:params:scim:api:messages:2.0:PatchOp"],
+                    "Operations": [operation]
+                })),
+            )
+            .await?
+            .error_for_status()
+            .context("Error returned from server (set_user_manager)")?;
+        Ok(())
+    }
+
+    async fn add_group_member(&self, group_id: &str, user_id: &str) -> anyhow::Result<()> {
+        self.patch_group(
+            "add_group_member",
+            &super::MembershipChange::Add { group_id, user_id },
+        )
+        .await
+    }
+
+    async fn remove_group_member(&self, group_id: &str, user_id: &str) -> anyhow::Result<()> {
+        self.patch_group(
+            "remove_group_member",
+            &super::MembershipChange::Remove { group_id, user_id },
+        )
+        .await
     }
 
-    pub(crate) async fn remove_group_member(
+    async fn apply_membership_changes(
         &self,
-        group_id: &str,
-        user_id: &str,
+        changes: &[super::MembershipChange<'_>],
+        concurrency: usize,
+        applied: &(dyn Fn(usize) + Sync),
     ) -> anyhow::Result<()> {
-        use anyhow::Context;
+        use futures::{StreamExt, TryStreamExt};
 
-        loop {
-            let res = self
-                .client
-                .request(
-                    reqwest::Method::PATCH,
-                    &format!("{}/Groups/{}", &self.secret.endpoint, group_id),
-                )
-                .header(
-                    "Authorization",
-                    format!("Bearer {}", &self.secret.access_token),
-                )
-                .json(&serde_json::json!({
-                   "schemas": [
-                      "urn:ietf:params:scim:api:messages:2.0:PatchOp"
-                   ],
-                   "Operations": [{
-                         "op": "remove",
-                         "path": "members",
-                         "value": [{ "value": user_id }]
-                    }]
-                }))
-                .send()
-                .await
-                .context("Unable to send request to AWS SCIM (remove_group_member)")?;
-            if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
-                continue;
-            }
-            let _ = res
-                .error_for_status()
-                .context("Error returned from server (remove_group_member)")?;
-            return Ok(());
+        if !self.secret.profile.bulk {
+            let requests = super::backend::changes_by_group(changes)
+                .into_iter()
+                .map(|changes| async move {
+                    for (index, change) in changes {
+                        self.patch_group("apply_membership_changes", change).await?;
+                        applied(index);
+                    }
+                    anyhow::Ok(())
+                })
+                .collect::<Vec<_>>();
+            return futures::stream::iter(requests)
+                .buffer_unordered(concurrency)
+                .try_collect()
+                .await;
+        }
+        let size = self.secret.profile.bulk_max_operations;
+        for (offset, chunk) in changes.chunks(size).enumerate() {
+            self.bulk(chunk, &|index| applied(offset * size + index))
+                .await?;
         }
+        Ok(())
     }
 }
@@ -19,10 +19,7 @@ impl Smc {
     }
 
     /// Fetches the current secret value of the given secret_id
-    pub(crate) async fn get_secret_value_current<S: serde::de::DeserializeOwned>(
-        &self,
-        secret_id: &str,
-    ) -> anyhow::Result<S> {
+    pub(crate) async fn get_secret_value_raw(&self, secret_id: &str) -> anyhow::Result<Vec<u8>> {
         use anyhow::Context;
         use rusoto_secretsmanager::SecretsManager;
 
@@ -35,51 +32,18 @@ impl Smc {
                     version_stage: Some("AWSCURRENT".to_string()),
                 })
                 .await;
-            if Self::is_wait_and_repeat(&res).await {
+            if super::is_wait_and_repeat(&res).await {
                 continue;
             }
             break res
                 .with_context(|| format!("Unable to fetch SecretValue with id: {secret_id}"))?;
         };
-        let inner = match (secret_value.secret_string, secret_value.secret_binary) {
-            (Some(string), _) => serde_json::from_str(&string),
-            (_, Some(bytes)) => serde_json::from_slice(&bytes),
-            _ => anyhow::bail!("Neither secret_string nor secret_binary is set for id: {secret_id}"),
-        }
-        .with_context(|| format!("Unable to parse secret value. Value does not confirm to required structure. Id: {secret_id}"))?;
-        Ok(inner)
-    }
-
-    /// Checks whether the given result is a throttling error
-    /// and waits for 250 ms if it is
-    async fn is_wait_and_repeat<D: Send + Sync, E: std::fmt::Debug + Send + Sync>(
-        error: &Result<D, rusoto_core::RusotoError<E>>,
-    ) -> bool {
-        if let Err(rusoto_core::RusotoError::Unknown(
-            rusoto_core::request::BufferedHttpResponse {
-                ref status,
-                ref body,
-                ..
-            },
-        )) = *error
-        {
-            let cooldown = match status.as_u16() {
-                400 => {
-                    let search = b"ThrottlingException";
-                    body.as_ref().windows(search.len()).any(|sub| sub == search)
-                }
-                429 => {
-                    let search = b"Too Many Requests";
-                    body.as_ref().windows(search.len()).any(|sub| sub == search)
-                }
-                _ => false,
-            };
-            if cooldown {
-                println!("Info: Cooling down to prevent request limits");
-                tokio::time::sleep(tokio::time::Duration::from_millis(250)).await;
-                return true;
+        match (secret_value.secret_string, secret_value.secret_binary) {
+            (Some(string), _) => Ok(string.into_bytes()),
+            (_, Some(bytes)) => Ok(bytes.to_vec()),
+            _ => {
+                anyhow::bail!("Neither secret_string nor secret_binary is set for id: {secret_id}")
             }
         }
-        false
     }
 }
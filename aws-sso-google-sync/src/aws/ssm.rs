@@ -0,0 +1,72 @@
+use super::jsonapi::JsonApi;
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetParameterResponse {
+    parameter: Parameter,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Parameter {
+    value: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PutParameterResponse {}
+
+/// Client for the AWS Systems Manager Parameter Store
+#[derive(Debug)]
+pub struct Ssm {
+    api: JsonApi,
+}
+
+impl Ssm {
+    pub(crate) fn new(region: &str) -> anyhow::Result<Self> {
+        let region = JsonApi::region_from_str(region)?;
+        Ok(Self {
+            api: JsonApi::new("AWS SSM", "ssm", "AmazonSSM", region),
+        })
+    }
+
+    /// Returns the value of the parameter or `None` if it does not exist
+    pub(crate) async fn get_parameter(&self, name: &str) -> anyhow::Result<Option<String>> {
+        use anyhow::Context;
+
+        match self
+            .api
+            .request::<GetParameterResponse>(
+                "GetParameter",
+                serde_json::json!({
+                    "Name": name,
+                    "WithDecryption": true,
+                }),
+            )
+            .await?
+        {
+            Ok(res) => Ok(Some(res.parameter.value)),
+            Err(err) if err.is("ParameterNotFound") => Ok(None),
+            Err(err) => Err(err)
+                .with_context(|| format!("Error returned from server (get_parameter): {name}")),
+        }
+    }
+
+    pub(crate) async fn put_parameter(&self, name: &str, value: &str) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let _ = self
+            .api
+            .request::<PutParameterResponse>(
+                "PutParameter",
+                serde_json::json!({
+                    "Name": name,
+                    "Value": value,
+                    "Type": "String",
+                    "Overwrite": true,
+                }),
+            )
+            .await?
+            .with_context(|| format!("Error returned from server (put_parameter): {name}"))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use super::jsonapi::JsonApi;
+
+const MAX_RESULTS: usize = 100;
+/// Number of times the status of an assignment is polled, once per second,
+/// before waiting for it is given up
+const MAX_STATUS_POLLS: usize = 60;
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AccountAssignment {
+    pub(crate) account_id: String,
+    pub(crate) permission_set_arn: String,
+    pub(crate) principal_type: String,
+    pub(crate) principal_id: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListAccountAssignmentsResponse {
+    account_assignments: Vec<AccountAssignment>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListAccountsForProvisionedPermissionSetResponse {
+    account_ids: Vec<String>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AssignmentOperationStatus {
+    status: String,
+    request_id: String,
+    failure_reason: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CreationStatusResponse {
+    account_assignment_creation_status: AssignmentOperationStatus,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DeletionStatusResponse {
+    account_assignment_deletion_status: AssignmentOperationStatus,
+}
+
+/// Client for the AWS SSO Admin API
+#[derive(Debug)]
+pub struct SsoAdmin<'a> {
+    instance_arn: &'a str,
+    api: JsonApi,
+}
+
+impl<'a> SsoAdmin<'a> {
+    pub(crate) fn new(instance_arn: &'a str, region: &str) -> anyhow::Result<Self> {
+        let region = JsonApi::region_from_str(region)?;
+        Ok(Self {
+            instance_arn,
+            api: JsonApi::new("AWS SSO Admin", "sso", "SWBExternalService", region),
+        })
+    }
+
+    /// Returns the ids of all accounts the permission set is provisioned to
+    pub(crate) async fn list_accounts_for_permission_set(
+        &self,
+        permission_set_arn: &str,
+    ) -> anyhow::Result<Vec<String>> {
+        use anyhow::Context;
+
+        let mut next_token = None;
+        let mut account_ids = Vec::new();
+        loop {
+            let res: ListAccountsForProvisionedPermissionSetResponse = self
+                .api
+                .request(
+                    "ListAccountsForProvisionedPermissionSet",
+                    serde_json::json!({
+                        "InstanceArn": self.instance_arn,
+                        "PermissionSetArn": permission_set_arn,
+                        "MaxResults": MAX_RESULTS,
+                        "NextToken": next_token,
+                    }),
+                )
+                .await?
+                .context("Error returned from server (list_accounts_for_permission_set)")?;
+            account_ids.extend(res.account_ids);
+            next_token = res.next_token;
+            if next_token.is_none() {
+                break;
+            }
+        }
+        Ok(account_ids)
+    }
+
+    pub(crate) async fn list_account_assignments(
+        &self,
+        account_id: &str,
+        permission_set_arn: &str,
+    ) -> anyhow::Result<Vec<AccountAssignment>> {
+        use anyhow::Context;
+
+        let mut next_token = None;
+        let mut assignments = Vec::new();
+        loop {
+            let res: ListAccountAssignmentsResponse = self
+                .api
+                .request(
+                    "ListAccountAssignments",
+                    serde_json::json!({
+                        "InstanceArn": self.instance_arn,
+                        "AccountId": account_id,
+                        "PermissionSetArn": permission_set_arn,
+                        "MaxResults": MAX_RESULTS,
+                        "NextToken": next_token,
+                    }),
+                )
+                .await?
+                .context("Error returned from server (list_account_assignments)")?;
+            assignments.extend(res.account_assignments);
+            next_token = res.next_token;
+            if next_token.is_none() {
+                break;
+            }
+        }
+        Ok(assignments)
+    }
+
+    /// Assigns the permission set to the group in the given account and waits
+    /// until the assignment is provisioned
+    pub(crate) async fn create_account_assignment(
+        &self,
+        account_id: &str,
+        permission_set_arn: &str,
+        group_id: &str,
+    ) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let res: CreationStatusResponse = self
+            .api
+            .request(
+                "CreateAccountAssignment",
+                Self::assignment_input(self.instance_arn, account_id, permission_set_arn, group_id),
+            )
+            .await?
+            .context("Error returned from server (create_account_assignment)")?;
+        let mut status = res.account_assignment_creation_status;
+        for _ in 0..MAX_STATUS_POLLS {
+            if status.status != "IN_PROGRESS" {
+                break;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            let res: CreationStatusResponse = self
+                .api
+                .request(
+                    "DescribeAccountAssignmentCreationStatus",
+                    serde_json::json!({
+                        "InstanceArn": self.instance_arn,
+                        "AccountAssignmentCreationRequestId": status.request_id,
+                    }),
+                )
+                .await?
+                .context("Error returned from server (create_account_assignment)")?;
+            status = res.account_assignment_creation_status;
+        }
+        Self::check_status(&status, "create_account_assignment")
+    }
+
+    /// Removes the permission set from the group in the given account and waits
+    /// until the assignment is deprovisioned
+    pub(crate) async fn delete_account_assignment(
+        &self,
+        account_id: &str,
+        permission_set_arn: &str,
+        group_id: &str,
+    ) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let res: DeletionStatusResponse = self
+            .api
+            .request(
+                "DeleteAccountAssignment",
+                Self::assignment_input(self.instance_arn, account_id, permission_set_arn, group_id),
+            )
+            .await?
+            .context("Error returned from server (delete_account_assignment)")?;
+        let mut status = res.account_assignment_deletion_status;
+        for _ in 0..MAX_STATUS_POLLS {
+            if status.status != "IN_PROGRESS" {
+                break;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            let res: DeletionStatusResponse = self
+                .api
+                .request(
+                    "DescribeAccountAssignmentDeletionStatus",
+                    serde_json::json!({
+                        "InstanceArn": self.instance_arn,
+                        "AccountAssignmentDeletionRequestId": status.request_id,
+                    }),
+                )
+                .await?
+                .context("Error returned from server (delete_account_assignment)")?;
+            status = res.account_assignment_deletion_status;
+        }
+        Self::check_status(&status, "delete_account_assignment")
+    }
+
+    fn assignment_input(
+        instance_arn: &str,
+        account_id: &str,
+        permission_set_arn: &str,
+        group_id: &str,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "InstanceArn": instance_arn,
+            "TargetId": account_id,
+            "TargetType": "AWS_ACCOUNT",
+            "PermissionSetArn": permission_set_arn,
+            "PrincipalType": "GROUP",
+            "PrincipalId": group_id,
+        })
+    }
+
+    fn check_status(status: &AssignmentOperationStatus, operation: &str) -> anyhow::Result<()> {
+        use anyhow::bail;
+
+        if status.status == "IN_PROGRESS" {
+            bail!(
+                "Request {} ({operation}) is still in progress after {MAX_STATUS_POLLS} seconds",
+                status.request_id
+            );
+        }
+        if status.status != "SUCCEEDED" {
+            bail!(
+                "Error returned from server ({operation}): {} {}",
+                status.status,
+                status.failure_reason.as_deref().unwrap_or("")
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+/// Returns a signed `GetCallerIdentity` request for the credentials of the lambda,
+/// serialized as subject token for the Google Workload Identity Federation.
+/// The request is not sent, Google verifies the identity by sending it to AWS
+pub async fn get_caller_identity_token(region: &str, audience: &str) -> anyhow::Result<String> {
+    use anyhow::Context;
+    use rusoto_core::credential::ProvideAwsCredentials;
+
+    let credentials = rusoto_core::credential::ChainProvider::new()
+        .credentials()
+        .await
+        .context("Unable to load the AWS credentials of the lambda")?;
+    let region = super::jsonapi::JsonApi::region_from_str(region)?;
+    let mut request = rusoto_core::signature::SignedRequest::new("POST", "sts", &region, "/");
+    request.add_param("Action", "GetCallerIdentity");
+    request.add_param("Version", "2011-06-15");
+    request.add_header("x-goog-cloud-target-resource", audience);
+    request.sign(&credentials);
+
+    let headers = request
+        .headers()
+        .iter()
+        .map(|(key, values)| {
+            let value = values
+                .iter()
+                .map(|v| String::from_utf8_lossy(v))
+                .collect::<Vec<_>>()
+                .join(",");
+            serde_json::json!({ "key": key, "value": value })
+        })
+        .collect::<Vec<_>>();
+    let token = serde_json::json!({
+        "url": format!(
+            "https://{}/?{}",
+            request.hostname(),
+            request.canonical_query_string()
+        ),
+        "method": "POST",
+        "headers": headers,
+    });
+    Ok(percent_encode(&token.to_string()))
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                char::from(b).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
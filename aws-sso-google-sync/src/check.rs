@@ -0,0 +1,273 @@
+use crate::report::{Check, CheckStatus};
+
+/// Results of all checks which were run so far
+#[derive(Debug, Default)]
+struct Checks(Vec<Check>);
+
+impl Checks {
+    fn push(&mut self, name: String, status: CheckStatus, message: Option<String>) {
+        match status {
+            CheckStatus::Passed => log::info!("Check {} passed", name),
+            CheckStatus::Warning => {
+                log::warn!("Check {}: {}", name, message.as_deref().unwrap_or(""))
+            }
+            CheckStatus::Failed => log::error!(
+                "Check {} failed: {}",
+                name,
+                message.as_deref().unwrap_or("")
+            ),
+        }
+        self.0.push(Check {
+            name,
+            status,
+            message,
+        });
+    }
+
+    /// Records the result and returns the value on success
+    fn record<T>(&mut self, name: String, result: anyhow::Result<T>) -> Option<T> {
+        match result {
+            Ok(v) => {
+                self.push(name, CheckStatus::Passed, None);
+                Some(v)
+            }
+            Err(err) => {
+                self.push(name, CheckStatus::Failed, Some(format!("{err:#}")));
+                None
+            }
+        }
+    }
+
+    /// Records the result of a check which is not always able to verify everything.
+    /// `Some` contains the reason why it was not verified and is recorded as warning
+    fn record_unverified(&mut self, name: String, result: anyhow::Result<Option<String>>) {
+        match result {
+            Ok(Some(reason)) => self.push(name, CheckStatus::Warning, Some(reason)),
+            Ok(None) => self.push(name, CheckStatus::Passed, None),
+            Err(err) => self.push(name, CheckStatus::Failed, Some(format!("{err:#}"))),
+        }
+    }
+
+    /// Records the number of entries returned by a listing. No entries
+    /// usually mean a misconfigured query or filter
+    fn record_count<T>(&mut self, name: String, kind: &str, result: anyhow::Result<Vec<T>>) {
+        match result {
+            Ok(entries) if entries.is_empty() => {
+                self.push(name, CheckStatus::Warning, Some(format!("No {kind} found")))
+            }
+            Ok(entries) => self.push(
+                name,
+                CheckStatus::Passed,
+                Some(format!("{} {kind} found", entries.len())),
+            ),
+            Err(err) => self.push(name, CheckStatus::Failed, Some(format!("{err:#}"))),
+        }
+    }
+}
+
+/// Verifies the configuration, the credentials of all tenants and targets and the
+/// required permissions without syncing anything
+pub async fn run(event: &crate::event::Event) -> Vec<Check> {
+    let mut checks = Checks::default();
+
+    let tenants = checks.record("config google_tenants".into(), event.get_google_tenants());
+    let targets = checks.record("config targets".into(), event.get_scim_targets());
+    let _ = checks.record("config sync_strategie".into(), event.get_sync_strategie());
+    let _ = checks.record(
+        "config sync_strategie_overrides".into(),
+        event.get_sync_strategie_overrides(),
+    );
+    let _ = checks.record(
+        "config group_member_options".into(),
+        event.get_group_member_options(),
+    );
+    let _ = checks.record(
+        "config group_member_options_overrides".into(),
+        event.get_group_member_options_overrides(),
+    );
+    let _ = checks.record(
+        "config external_members".into(),
+        event.get_external_members(),
+    );
+    let _ = checks.record(
+        "config identity_normalization".into(),
+        event.get_identity_normalization(),
+    );
+    let _ = checks.record("config user_name".into(), event.get_user_name());
+    let incremental = checks
+        .record("config incremental".into(), event.get_incremental())
+        .flatten();
+    let watch_channels = checks
+        .record("config watch_channels".into(), event.get_watch_channels())
+        .flatten();
+    let _ = checks.record("config deadline".into(), event.get_deadline());
+    let _ = checks.record("config concurrency".into(), event.get_concurrency());
+    let _ = checks.record("config audit_log".into(), event.get_audit_log());
+    let _ = checks.record("config snapshot".into(), event.get_snapshot());
+    let _ = checks.record("config diff".into(), event.get_diff());
+
+    for tenant in tenants.iter().flatten() {
+        check_tenant(
+            &mut checks,
+            tenant,
+            incremental.is_some() || watch_channels.is_some(),
+        )
+        .await;
+    }
+    for target in targets.iter().flatten() {
+        check_target(&mut checks, target).await;
+    }
+    if let Some(config) = incremental {
+        let _ = checks.record(
+            format!("incremental checkpoint {}", config.checkpoint.name),
+            crate::incremental::Checkpoint::load(&config).await,
+        );
+    }
+    if let Some(config) = watch_channels {
+        let _ = checks.record(
+            format!("watch_channels token {}", config.token.id),
+            crate::aws::get_secret::<crate::event::ChannelToken>(&config.token).await,
+        );
+        let state = match crate::aws::Ssm::new(&config.state.region) {
+            Ok(ssm) => ssm.get_parameter(&config.state.name).await,
+            Err(err) => Err(err),
+        };
+        let _ = checks.record(format!("watch_channels state {}", config.state.name), state);
+    }
+    checks.0
+}
+
+async fn check_tenant(checks: &mut Checks, tenant: &crate::event::Tenant<'_>, audit_log: bool) {
+    let name = tenant.name();
+    let creds = match checks.record(
+        format!("tenant {name} credentials"),
+        crate::directory::DirectoryCreds::load(&tenant.source).await,
+    ) {
+        Some(v) => v,
+        None => return,
+    };
+    if let crate::directory::DirectoryCreds::Google(ref admin_creds) = creds {
+        for (scope, result) in crate::google::Admin::check_scopes(admin_creds).await {
+            checks.record_unverified(format!("tenant {name} scope {scope}"), result);
+        }
+        if audit_log {
+            let result = match crate::google::Reports::new(admin_creds).await {
+                Ok(reports) => reports.check().await,
+                Err(err) => Err(err),
+            };
+            let _ = checks.record(
+                format!("tenant {name} scope https://www.googleapis.com/auth/admin.reports.audit.readonly"),
+                result,
+            );
+        }
+    }
+    let directory = match checks.record(format!("tenant {name} connect"), creds.connect().await) {
+        Some(v) => v,
+        None => return,
+    };
+    let users = directory
+        .list_users(
+            tenant
+                .google_api_query_for_users
+                .as_ref()
+                .map(AsRef::as_ref),
+        )
+        .await
+        .map(|users| {
+            users
+                .into_iter()
+                .filter(|u| tenant.is_user_included(&u.primary_email))
+                .collect::<Vec<_>>()
+        });
+    checks.record_count(format!("tenant {name} users"), "users", users);
+    let groups = directory
+        .list_groups(
+            tenant
+                .google_api_query_for_groups
+                .as_ref()
+                .map(AsRef::as_ref),
+        )
+        .await
+        .map(|groups| {
+            groups
+                .into_iter()
+                .filter(|g| tenant.is_group_included(&g.email))
+                .collect::<Vec<_>>()
+        });
+    checks.record_count(format!("tenant {name} groups"), "groups", groups);
+}
+
+async fn check_target(checks: &mut Checks, target: &crate::event::Target<'_>) {
+    let name = target.name();
+    match target.backend {
+        crate::event::TargetBackend::Scim(ref secret) => {
+            let creds = match checks.record(
+                format!("target {name} credentials"),
+                crate::aws::get_secret::<crate::aws::ScimCreds>(secret).await,
+            ) {
+                Some(v) => v,
+                None => return,
+            };
+            if let Some(days_left) = creds.token_days_left(chrono::Utc::now().date_naive()) {
+                let (status, message) = if days_left < 0 {
+                    (
+                        CheckStatus::Failed,
+                        format!("Expired {} days ago", -days_left),
+                    )
+                } else if creds.is_token_expiring(days_left) {
+                    (CheckStatus::Warning, format!("Expires in {days_left} days"))
+                } else {
+                    (CheckStatus::Passed, format!("Expires in {days_left} days"))
+                };
+                checks.push(format!("target {name} token expiry"), status, Some(message));
+            }
+            let scim = match checks.record(
+                format!("target {name} client"),
+                crate::aws::Scim::new(&creds),
+            ) {
+                Some(v) => v,
+                None => return,
+            };
+            check_backend(checks, name, &scim).await;
+        }
+        crate::event::TargetBackend::IdentityStore(ref config) => {
+            let identity_store = match checks.record(
+                format!("target {name} client"),
+                crate::aws::IdentityStore::new(config),
+            ) {
+                Some(v) => v,
+                None => return,
+            };
+            check_backend(checks, name, &identity_store).await;
+        }
+    }
+    if let Some(ref config) = target.account_assignments {
+        let sso_admin = match checks.record(
+            format!("target {name} account_assignments client"),
+            crate::aws::SsoAdmin::new(&config.instance_arn, &config.region),
+        ) {
+            Some(v) => v,
+            None => return,
+        };
+        for rule in &config.rules {
+            let _ = checks.record(
+                format!(
+                    "target {name} account_assignments {}",
+                    rule.permission_set_arn
+                ),
+                sso_admin
+                    .list_accounts_for_permission_set(&rule.permission_set_arn)
+                    .await,
+            );
+        }
+    }
+}
+
+/// Verifies the endpoint and the credentials of the target with a read-only request
+async fn check_backend(checks: &mut Checks, name: &str, backend: &dyn crate::aws::Backend) {
+    checks.record_count(
+        format!("target {name} groups"),
+        "groups",
+        backend.list_groups().await,
+    );
+}
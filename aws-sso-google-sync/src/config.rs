@@ -0,0 +1,117 @@
+/// Fields of the event together with the environment variable which overrides them
+const ENV_VARS: &[(&str, &str)] = &[
+    ("security_hub_google_creds", crate::ENV_VAR_SH_GOOGLE_CREDS),
+    ("security_hub_ldap_creds", crate::ENV_VAR_SH_LDAP_CREDS),
+    ("security_hub_scim_creds", crate::ENV_VAR_SH_SCIM_CREDS),
+    (
+        "google_api_query_for_users",
+        crate::ENV_VAR_GOOGLE_API_QUERY_FOR_USERS,
+    ),
+    (
+        "google_api_query_for_groups",
+        crate::ENV_VAR_GOOGLE_API_QUERY_FOR_GROUPS,
+    ),
+    ("ignore_users_regexes", crate::ENV_VAR_IGNORE_USERS_REGEXES),
+    (
+        "include_users_regexes",
+        crate::ENV_VAR_INCLUDE_USERS_REGEXES,
+    ),
+    (
+        "ignore_groups_regexes",
+        crate::ENV_VAR_IGNORE_GROUPS_REGEXES,
+    ),
+    (
+        "include_groups_regexes",
+        crate::ENV_VAR_INCLUDE_GROUPS_REGEXES,
+    ),
+    ("sync_strategie", crate::ENV_VAR_SYNC_STRATEGIE),
+    (
+        "sync_strategie_overrides",
+        crate::ENV_VAR_SYNC_STRATEGIE_OVERRIDES,
+    ),
+    ("group_member_options", crate::ENV_VAR_GROUP_MEMBER_OPTIONS),
+    (
+        "group_member_options_overrides",
+        crate::ENV_VAR_GROUP_MEMBER_OPTIONS_OVERRIDES,
+    ),
+    ("external_members", crate::ENV_VAR_EXTERNAL_MEMBERS),
+    (
+        "identity_normalization",
+        crate::ENV_VAR_IDENTITY_NORMALIZATION,
+    ),
+    ("user_name", crate::ENV_VAR_USER_NAME),
+    ("google_tenants", crate::ENV_VAR_GOOGLE_TENANTS),
+    ("identity_store", crate::ENV_VAR_IDENTITY_STORE),
+    ("account_assignments", crate::ENV_VAR_ACCOUNT_ASSIGNMENTS),
+    ("incremental", crate::ENV_VAR_INCREMENTAL),
+    ("watch_channels", crate::ENV_VAR_WATCH_CHANNELS),
+    ("deadline", crate::ENV_VAR_DEADLINE),
+    ("concurrency", crate::ENV_VAR_CONCURRENCY),
+    ("audit_log", crate::ENV_VAR_AUDIT_LOG),
+    ("snapshot", crate::ENV_VAR_SNAPSHOT),
+    ("diff", crate::ENV_VAR_DIFF),
+];
+
+/// Merges the config document referenced by the event into the event. Fields of the
+/// event and environment variables take precedence over the document. The returned
+/// event no longer references the document, so a continuation uses the same config
+pub async fn load(event: crate::event::Event) -> anyhow::Result<crate::event::Event> {
+    use anyhow::{anyhow, bail, Context};
+
+    let document = match event.get_config()? {
+        Some(v) => v.into_owned(),
+        None => return Ok(event),
+    };
+    let content = fetch(&document).await?;
+    let value = match document.format() {
+        crate::event::ConfigFormat::Json => serde_json::from_slice(&content)
+            .with_context(|| format!("Unable to parse config document {document} as JSON")),
+        crate::event::ConfigFormat::Yaml => serde_yaml::from_slice(&content)
+            .with_context(|| format!("Unable to parse config document {document} as YAML")),
+        crate::event::ConfigFormat::Toml => std::str::from_utf8(&content)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| toml::from_str(content).map_err(anyhow::Error::from))
+            .with_context(|| format!("Unable to parse config document {document} as TOML")),
+    }?;
+    let mut fields = match value {
+        serde_json::Value::Object(fields) => fields,
+        _ => bail!("The config document {document} is not an object"),
+    };
+    for (field, env) in ENV_VARS {
+        if std::env::var_os(env).is_some() {
+            let _ = fields.remove(*field);
+        }
+    }
+    let overrides = serde_json::to_value(&event).context("Unable to serialize the event")?;
+    if let serde_json::Value::Object(overrides) = overrides {
+        fields.extend(overrides.into_iter().filter(|(_, v)| !v.is_null()));
+    }
+    let _ = fields.remove("config");
+    serde_path_to_error::deserialize(serde_json::Value::Object(fields)).map_err(|err| {
+        anyhow!(
+            "Invalid value for {} in config document {document}: {}",
+            err.path(),
+            err.inner()
+        )
+    })
+}
+
+/// Returns the content of the config document
+async fn fetch(document: &crate::event::ConfigDocument) -> anyhow::Result<Vec<u8>> {
+    use anyhow::{bail, Context};
+
+    match (&document.s3, &document.ssm, &document.secrets_manager) {
+        (Some(s3), None, None) => {
+            crate::aws::S3::new(&s3.region)?
+                .get_object(&s3.bucket, &s3.key)
+                .await
+        }
+        (None, Some(ssm), None) => crate::aws::Ssm::new(&ssm.region)?
+            .get_parameter(&ssm.name)
+            .await?
+            .map(String::into_bytes)
+            .with_context(|| format!("The config document {document} does not exist")),
+        (None, None, Some(secret)) => crate::aws::get_raw_secret(secret).await,
+        _ => bail!("Exactly one of s3, ssm or secrets_manager must be set in config."),
+    }
+}
@@ -0,0 +1,112 @@
+/// Phases of the full sync of a target in the order in which they are run
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+pub enum Phase {
+    #[default]
+    Groups,
+    Users,
+    Associations,
+    AccountAssignments,
+}
+
+impl Phase {
+    pub(crate) const ALL: [Self; 4] = [
+        Self::Groups,
+        Self::Users,
+        Self::Associations,
+        Self::AccountAssignments,
+    ];
+}
+
+/// Position within the full sync of a target at which the sync was interrupted
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Progress {
+    pub(crate) phase: Phase,
+    /// Key of the last user whose memberships were synced
+    pub(crate) last_user: Option<String>,
+}
+
+/// Progress of a full sync which is continued by another invocation of the lambda
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Continuation {
+    /// Start of the first invocation. Saved as incremental checkpoint once the sync completes
+    pub(crate) started: chrono::DateTime<chrono::Utc>,
+    pub(crate) invocation: u32,
+    /// Request id of the first invocation, used as run id in the audit log
+    #[serde(default)]
+    pub(crate) run_id: String,
+    /// Index of the interrupted target. All targets before it are synced already
+    pub(crate) target: usize,
+    /// Names of the targets which failed in a previous invocation. Once the sync
+    /// completes, it fails and does not save the incremental checkpoint
+    #[serde(default)]
+    pub(crate) failed_targets: Vec<String>,
+    #[serde(flatten)]
+    pub(crate) progress: Progress,
+}
+
+impl Continuation {
+    /// Returns the progress from which the target with the given index is synced
+    pub(crate) fn resume_point(continuation: Option<&Self>, target: usize) -> Progress {
+        match continuation {
+            Some(c) if c.target == target => c.progress.clone(),
+            _ => Progress::default(),
+        }
+    }
+}
+
+/// Point in time at which a full sync is interrupted to finish before the lambda times out
+#[derive(Debug, Copy, Clone)]
+pub struct Deadline(Option<std::time::SystemTime>);
+
+impl Deadline {
+    /// Creates the deadline from the deadline of the lambda in milliseconds since the epoch.
+    /// Without config, the sync is never interrupted
+    pub(crate) fn new(lambda_deadline: u64, config: Option<&crate::event::DeadlineConfig>) -> Self {
+        Self(config.and_then(|config| {
+            (std::time::UNIX_EPOCH + std::time::Duration::from_millis(lambda_deadline))
+                .checked_sub(config.margin())
+        }))
+    }
+
+    pub(crate) fn is_reached(&self) -> bool {
+        self.0
+            .is_some_and(|deadline| std::time::SystemTime::now() >= deadline)
+    }
+}
+
+/// Invokes the lambda again with the given continuation to finish the full sync
+pub async fn invoke(
+    event: &crate::event::Event,
+    ctx: &lambda_runtime_types::Context,
+    region: &str,
+    continuation: &Continuation,
+) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use std::str::FromStr;
+
+    let region = rusoto_core::Region::from_str(region)
+        .with_context(|| format!("{region} is not a valid AWS Region."))?;
+    let mut args = serde_json::to_value(event).context("Unable to serialize event")?;
+    args["continuation"] =
+        serde_json::to_value(continuation).context("Unable to serialize continuation")?;
+    log::info!(
+        "Continuing sync of target {} at {:?} in invocation {}",
+        continuation.target,
+        continuation.progress.phase,
+        continuation.invocation
+    );
+    crate::aws::Lambda::new(region)
+        .invoke_async(&ctx.invoked_function_arn, &args)
+        .await
+}
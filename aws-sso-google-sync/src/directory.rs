@@ -0,0 +1,55 @@
+/// Directory from which users, groups and group memberships are synced
+#[async_trait::async_trait]
+pub trait DirectorySource: Send + Sync {
+    /// Lists all users. `query` is a directory specific filter
+    async fn list_users(&self, query: Option<&str>) -> anyhow::Result<Vec<crate::google::User>>;
+
+    /// Lists users which were deleted recently. Directories which do not keep
+    /// deleted users return an empty list
+    async fn list_deleted_users(&self) -> anyhow::Result<Vec<crate::google::User>>;
+
+    /// Returns the user with the given primary email or `None` if it does not exist
+    async fn get_user(&self, email: &str) -> anyhow::Result<Option<crate::google::User>>;
+
+    /// Returns the group with the given email or `None` if it does not exist
+    async fn get_group(&self, email: &str) -> anyhow::Result<Option<crate::google::Group>>;
+
+    /// Lists all groups. `query` is a directory specific filter
+    async fn list_groups(&self, query: Option<&str>) -> anyhow::Result<Vec<crate::google::Group>>;
+
+    async fn list_group_members(
+        &self,
+        group_id: &str,
+        options: &crate::event::GroupMemberOptions,
+    ) -> anyhow::Result<Vec<crate::google::Member>>;
+
+    /// Checks whether the given mail belongs to the directory
+    fn is_internal(&self, mail: &str) -> bool;
+}
+
+/// Credentials of a directory which are loaded from AWS Secret Manager
+#[derive(Debug)]
+pub enum DirectoryCreds {
+    Google(Box<crate::google::AdminCreds>),
+    Ldap(Box<crate::ldap::LdapCreds>),
+}
+
+impl DirectoryCreds {
+    pub(crate) async fn load(source: &crate::event::TenantSource<'_>) -> anyhow::Result<Self> {
+        Ok(match *source {
+            crate::event::TenantSource::Google(ref secret) => {
+                Self::Google(crate::aws::get_secret(secret.as_ref()).await?)
+            }
+            crate::event::TenantSource::Ldap(ref secret) => {
+                Self::Ldap(crate::aws::get_secret(secret.as_ref()).await?)
+            }
+        })
+    }
+
+    pub(crate) async fn connect(&self) -> anyhow::Result<Box<dyn DirectorySource + '_>> {
+        Ok(match *self {
+            Self::Google(ref creds) => Box::new(crate::google::Admin::new(creds).await?),
+            Self::Ldap(ref creds) => Box::new(crate::ldap::Ldap::new(creds).await?),
+        })
+    }
+}
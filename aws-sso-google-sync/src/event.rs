@@ -1,19 +1,849 @@
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Copy, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub enum SecretSource {
+    #[default]
+    SecretsManager,
+    Ssm,
+    File,
+}
+
+/// Secret in the Secrets Manager. Alternatively a SecureString parameter in the
+/// SSM Parameter Store or a local file, where `id` is the name or path
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Secret {
+    #[serde(default)]
     pub(crate) region: String,
     pub(crate) id: String,
+    pub(crate) source: Option<SecretSource>,
 }
 
-#[derive(Debug, Copy, Clone, serde::Deserialize)]
+impl Secret {
+    pub(crate) fn source(&self) -> SecretSource {
+        self.source.unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize)]
 pub enum SyncStrategie {
     AllUsers,
     GroupMembersOnly,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum SyncStrategieAction {
+    Include,
+    Exclude,
+}
+
+/// Includes or excludes all users which match every given condition,
+/// regardless of the sync strategie
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct SyncStrategieOverride {
+    group_regex: Option<String>,
+    org_unit_path: Option<String>,
+    user_regex: Option<String>,
+    action: SyncStrategieAction,
+}
+
+/// Parsed `SyncStrategieOverride`
+#[derive(Debug, Clone)]
+pub struct SyncStrategieRule {
+    group_regex: Option<regex::Regex>,
+    org_unit_path: Option<String>,
+    user_regex: Option<regex::Regex>,
+    action: SyncStrategieAction,
+}
+
+impl SyncStrategieRule {
+    fn new(o: &SyncStrategieOverride) -> anyhow::Result<Self> {
+        use anyhow::{bail, Context};
+
+        if o.group_regex.is_none() && o.org_unit_path.is_none() && o.user_regex.is_none() {
+            bail!("Each entry of sync_strategie_overrides requires a group_regex, org_unit_path or user_regex.");
+        }
+        let parse = |regex: &Option<String>| {
+            regex
+                .as_deref()
+                .map(|r| {
+                    regex::Regex::new(r).with_context(|| {
+                        format!("Unable to parse the following regex value from sync_strategie_overrides: {r}")
+                    })
+                })
+                .transpose()
+        };
+        Ok(Self {
+            group_regex: parse(&o.group_regex)?,
+            org_unit_path: o.org_unit_path.clone(),
+            user_regex: parse(&o.user_regex)?,
+            action: o.action,
+        })
+    }
+
+    /// Checks whether the user matches all conditions. `email` is the normalized
+    /// primary email of the user and `groups` are the normalized emails of the
+    /// groups the user is a member of
+    fn matches(&self, user: &crate::google::User, email: &str, groups: &[&str]) -> bool {
+        self.user_regex.as_ref().is_none_or(|r| r.is_match(email))
+            && self.org_unit_path.as_deref().is_none_or(|path| {
+                user.org_unit_path
+                    .as_deref()
+                    .is_some_and(|u| Self::is_in_org_unit(u, path))
+            })
+            && self
+                .group_regex
+                .as_ref()
+                .is_none_or(|r| groups.iter().any(|g| r.is_match(g)))
+    }
+
+    /// Checks whether `org_unit` is the given org unit or one of its children
+    fn is_in_org_unit(org_unit: &str, path: &str) -> bool {
+        let path = path.trim_end_matches('/');
+        org_unit
+            .strip_prefix(path)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+/// Decides which google users are synced. The first override matching a user
+/// takes precedence over the sync strategie
+#[derive(Debug, Clone)]
+pub struct UserSyncRules {
+    strategie: SyncStrategie,
+    overrides: Vec<SyncStrategieRule>,
+}
+
+impl UserSyncRules {
+    pub(crate) const fn new(strategie: SyncStrategie, overrides: Vec<SyncStrategieRule>) -> Self {
+        Self {
+            strategie,
+            overrides,
+        }
+    }
+
+    /// Checks whether the user is synced and returns the rule which decided it.
+    /// `email` is the normalized primary email of the user, like the keys the
+    /// include and ignore regexes are matched against. `is_member` tells whether
+    /// the user is a member of one of the synced groups
+    pub(crate) fn evaluate(
+        &self,
+        user: &crate::google::User,
+        email: &str,
+        groups: &[&str],
+        is_member: bool,
+    ) -> (bool, String) {
+        self.overrides
+            .iter()
+            .position(|o| o.matches(user, email, groups))
+            .map_or_else(
+                || match self.strategie {
+                    SyncStrategie::AllUsers => (true, "sync_strategie AllUsers".into()),
+                    SyncStrategie::GroupMembersOnly => {
+                        (is_member, "sync_strategie GroupMembersOnly".into())
+                    }
+                },
+                |i| {
+                    (
+                        self.overrides[i].action == SyncStrategieAction::Include,
+                        format!("sync_strategie_overrides[{i}]"),
+                    )
+                },
+            )
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct GroupMemberOptions {
+    include_derived_membership: Option<bool>,
+    roles: Option<Vec<crate::google::MemberRole>>,
+    exclude_suspended: Option<bool>,
+}
+
+impl GroupMemberOptions {
+    pub(crate) fn include_derived_membership(&self) -> bool {
+        self.include_derived_membership.unwrap_or(true)
+    }
+
+    pub(crate) fn is_role_included(&self, role: Option<crate::google::MemberRole>) -> bool {
+        let role = role.unwrap_or(crate::google::MemberRole::Member);
+        self.roles.as_ref().is_none_or(|r| r.contains(&role))
+    }
+
+    pub(crate) fn exclude_suspended(&self) -> bool {
+        self.exclude_suspended.unwrap_or(false)
+    }
+
+    /// Returns a copy of `self` where every option set in `other` takes precedence
+    pub(crate) fn merge(&self, other: &Self) -> Self {
+        Self {
+            include_derived_membership: other
+                .include_derived_membership
+                .or(self.include_derived_membership),
+            roles: other.roles.clone().or_else(|| self.roles.clone()),
+            exclude_suspended: other.exclude_suspended.or(self.exclude_suspended),
+        }
+    }
+}
+
+/// Normalization of emails and user names before they are compared
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct IdentityNormalization {
+    lowercase: Option<bool>,
+    unicode_normalization: Option<bool>,
+    resolve_aliases: Option<bool>,
+}
+
+impl IdentityNormalization {
+    /// Returns the key under which an email or user name is looked up
+    pub(crate) fn normalize(&self, value: &str) -> String {
+        use unicode_normalization::UnicodeNormalization;
+
+        let value = if self.unicode_normalization.unwrap_or(true) {
+            value.nfc().collect::<String>()
+        } else {
+            value.to_owned()
+        };
+        if self.lowercase.unwrap_or(true) {
+            value.to_lowercase()
+        } else {
+            value
+        }
+    }
+
+    /// Whether group members which are referenced by an alias are resolved to the user
+    pub(crate) fn resolve_aliases(&self) -> bool {
+        self.resolve_aliases.unwrap_or(false)
+    }
+}
+
+/// Placeholder of the user name template which is replaced with the value of the source
+const USER_NAME_PLACEHOLDER: &str = "{value}";
+
+#[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize)]
+pub enum UserNameSource {
+    PrimaryEmail,
+    LocalPart,
+    Alias,
+    ExternalId,
+    CustomSchema,
+}
+
+/// Defines from which attribute of a google user the AWS SSO user name is derived
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct UserNameConfig {
+    source: Option<UserNameSource>,
+    alias_domain: Option<String>,
+    external_id_type: Option<String>,
+    schema: Option<String>,
+    field: Option<String>,
+    template: Option<String>,
+}
+
+impl UserNameConfig {
+    fn validate(self) -> anyhow::Result<Self> {
+        use anyhow::bail;
+
+        if matches!(self.source, Some(UserNameSource::CustomSchema))
+            && (self.schema.is_none() || self.field.is_none())
+        {
+            bail!("schema and field must be defined for the user name source CustomSchema");
+        }
+        if let Some(ref template) = self.template {
+            if !template.contains(USER_NAME_PLACEHOLDER) {
+                bail!("The user name template {template} does not contain {{value}}");
+            }
+        }
+        Ok(self)
+    }
+
+    /// Returns the AWS SSO user name of the given google user. Returns `None`
+    /// if the user has no value for the configured source
+    pub(crate) fn get(&self, user: &crate::google::User) -> Option<String> {
+        let value = match self.source.unwrap_or(UserNameSource::PrimaryEmail) {
+            UserNameSource::PrimaryEmail => Some(user.primary_email.clone()),
+            UserNameSource::LocalPart => Some(Self::local_part(&user.primary_email).into()),
+            UserNameSource::Alias => user
+                .aliases
+                .iter()
+                .find(|alias| {
+                    self.alias_domain.as_ref().is_none_or(|domain| {
+                        alias
+                            .rsplit_once('@')
+                            .is_some_and(|(_, d)| d.eq_ignore_ascii_case(domain))
+                    })
+                })
+                .cloned(),
+            UserNameSource::ExternalId => user
+                .external_ids
+                .iter()
+                .find(|id| {
+                    self.external_id_type
+                        .as_ref()
+                        .is_none_or(|t| id.type_name() == Some(t.as_str()))
+                })
+                .map(|id| id.value.clone()),
+            UserNameSource::CustomSchema => {
+                let value = user
+                    .custom_schemas
+                    .get(self.schema.as_deref()?)?
+                    .get(self.field.as_deref()?)?;
+                match *value {
+                    serde_json::Value::String(ref v) => Some(v.clone()),
+                    serde_json::Value::Number(ref v) => Some(v.to_string()),
+                    _ => None,
+                }
+            }
+        }?;
+        Some(self.apply_template(&value))
+    }
+
+    /// Returns the AWS SSO user name of a user which no longer exists in google.
+    /// This is only possible if the user name is derived from the primary email
+    pub(crate) fn get_by_email(&self, email: &str) -> Option<String> {
+        match self.source.unwrap_or(UserNameSource::PrimaryEmail) {
+            UserNameSource::PrimaryEmail => Some(self.apply_template(email)),
+            UserNameSource::LocalPart => Some(self.apply_template(Self::local_part(email))),
+            UserNameSource::Alias | UserNameSource::ExternalId | UserNameSource::CustomSchema => {
+                None
+            }
+        }
+    }
+
+    fn apply_template(&self, value: &str) -> String {
+        self.template.as_ref().map_or_else(
+            || value.into(),
+            |template| template.replace(USER_NAME_PLACEHOLDER, value),
+        )
+    }
+
+    fn local_part(email: &str) -> &str {
+        email.rsplit_once('@').map_or(email, |(l, _)| l)
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct GroupMemberOptionsOverride {
+    group_regex: String,
+    #[serde(flatten)]
+    options: GroupMemberOptions,
+}
+
+#[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize)]
+pub enum ExternalMemberStrategie {
+    Include,
+    Skip,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ExternalMembers {
+    strategie: ExternalMemberStrategie,
+    allowed_domains: Option<Vec<String>>,
+}
+
+impl Default for ExternalMembers {
+    fn default() -> Self {
+        Self {
+            strategie: ExternalMemberStrategie::Skip,
+            allowed_domains: None,
+        }
+    }
+}
+
+impl ExternalMembers {
+    /// Checks whether the given external mail should be synced as AWS SSO user
+    pub(crate) fn is_included(&self, mail: &str) -> bool {
+        match self.strategie {
+            ExternalMemberStrategie::Skip => false,
+            ExternalMemberStrategie::Include => self.allowed_domains.as_ref().is_none_or(|d| {
+                mail.rsplit_once('@')
+                    .is_some_and(|(_, domain)| d.iter().any(|d| d.eq_ignore_ascii_case(domain)))
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct AccountAssignmentsConfig {
+    instance_arn: String,
+    region: String,
+    dry_run: Option<bool>,
+    allow_deletions: Option<bool>,
+    max_deletions: Option<usize>,
+    rules: Vec<AccountAssignmentRuleConfig>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct AccountAssignmentRuleConfig {
+    group_regex: String,
+    permission_set_arn: String,
+    account_ids: Option<Vec<String>>,
+    organizational_units: Option<Vec<String>>,
+}
+
+impl AccountAssignmentsConfig {
+    fn into_assignments(self) -> anyhow::Result<AccountAssignments> {
+        use anyhow::Context;
+
+        let rules = self
+            .rules
+            .into_iter()
+            .map(|rule| {
+                Ok(AccountAssignmentRule {
+                    group_regex: regex::Regex::new(&rule.group_regex).with_context(|| {
+                        format!(
+                            "Unable to parse the following regex value from account_assignments of instance {}: {}",
+                            self.instance_arn, rule.group_regex
+                        )
+                    })?,
+                    permission_set_arn: rule.permission_set_arn,
+                    account_ids: rule.account_ids.unwrap_or_default(),
+                    organizational_units: rule.organizational_units.unwrap_or_default(),
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(AccountAssignments {
+            instance_arn: self.instance_arn,
+            region: self.region,
+            dry_run: self.dry_run.unwrap_or(false),
+            allow_deletions: self.allow_deletions.unwrap_or(false),
+            max_deletions: self.max_deletions,
+            rules,
+        })
+    }
+}
+
+/// Permission sets which are assigned to synced groups in AWS accounts
+#[derive(Debug)]
+pub struct AccountAssignments {
+    pub(crate) instance_arn: String,
+    pub(crate) region: String,
+    pub(crate) dry_run: bool,
+    pub(crate) allow_deletions: bool,
+    pub(crate) max_deletions: Option<usize>,
+    pub(crate) rules: Vec<AccountAssignmentRule>,
+}
+
+#[derive(Debug)]
+pub struct AccountAssignmentRule {
+    pub(crate) group_regex: regex::Regex,
+    pub(crate) permission_set_arn: String,
+    pub(crate) account_ids: Vec<String>,
+    pub(crate) organizational_units: Vec<String>,
+}
+
+/// SSM parameter which keeps state between invocations
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Parameter {
+    pub(crate) region: String,
+    pub(crate) name: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct S3Object {
+    pub(crate) region: String,
+    pub(crate) bucket: String,
+    pub(crate) key: String,
+}
+
+#[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize)]
+pub enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct S3Prefix {
+    pub(crate) region: String,
+    pub(crate) bucket: String,
+    pub(crate) prefix: Option<String>,
+}
+
+/// JSON Lines file of all changes which is written by every invocation
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct AuditLogConfig {
+    pub(crate) s3: Option<S3Prefix>,
+    /// Local directory, which is meant for testing
+    pub(crate) directory: Option<String>,
+}
+
+/// Locations to which the snapshot is written. Without any location, the snapshot
+/// is returned in the lambda result
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct SnapshotConfig {
+    pub(crate) s3: Option<S3Prefix>,
+    /// Local directory, which is meant for testing
+    pub(crate) directory: Option<String>,
+}
+
+/// Snapshot which is read by the mode `Diff`. Exactly one location has to be set
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct DiffConfig {
+    pub(crate) s3: Option<S3Object>,
+    pub(crate) file: Option<String>,
+}
+
+impl std::fmt::Display for DiffConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.s3.as_ref(), self.file.as_deref()) {
+            (Some(s3), _) => write!(f, "s3://{}/{}", s3.bucket, s3.key),
+            (None, Some(file)) => write!(f, "{file}"),
+            (None, None) => write!(f, "<none>"),
+        }
+    }
+}
+
+/// Document in S3, the SSM Parameter Store or the Secrets Manager which provides
+/// the fields of the event. Exactly one location has to be set
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ConfigDocument {
+    pub(crate) s3: Option<S3Object>,
+    pub(crate) ssm: Option<Parameter>,
+    pub(crate) secrets_manager: Option<Secret>,
+    format: Option<ConfigFormat>,
+}
+
+impl ConfigDocument {
+    /// Returns the configured format or derives it from the file extension
+    /// of the location. Defaults to `Json`
+    pub(crate) fn format(&self) -> ConfigFormat {
+        if let Some(format) = self.format {
+            return format;
+        }
+        let name = self.to_string().to_lowercase();
+        if name.ends_with(".yaml") || name.ends_with(".yml") {
+            ConfigFormat::Yaml
+        } else if name.ends_with(".toml") {
+            ConfigFormat::Toml
+        } else {
+            ConfigFormat::Json
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.s3, &self.ssm, &self.secrets_manager) {
+            (Some(s3), _, _) => write!(f, "s3://{}/{}", s3.bucket, s3.key),
+            (_, Some(ssm), _) => write!(f, "ssm:{}", ssm.name),
+            (_, _, Some(secret)) => write!(f, "secretsmanager:{}", secret.id),
+            _ => write!(f, "<none>"),
+        }
+    }
+}
+
+/// Incremental sync based on the Google Admin audit log
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct IncrementalSync {
+    pub(crate) checkpoint: Parameter,
+    full_sync_interval_minutes: Option<u32>,
+    overlap_minutes: Option<u32>,
+}
+
+impl IncrementalSync {
+    /// Time after which a full sync is run even if no relevant changes were detected
+    pub(crate) fn full_sync_interval(&self) -> chrono::Duration {
+        chrono::Duration::minutes(i64::from(self.full_sync_interval_minutes.unwrap_or(1440)))
+    }
+
+    /// Time by which the audit log window is extended into the past, as
+    /// audit events are not available immediately
+    pub(crate) fn overlap(&self) -> chrono::Duration {
+        chrono::Duration::minutes(i64::from(self.overlap_minutes.unwrap_or(10)))
+    }
+}
+
+/// Interrupts a full sync shortly before the lambda times out and continues
+/// it in a new invocation of the lambda
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct DeadlineConfig {
+    margin_seconds: Option<u64>,
+}
+
+impl DeadlineConfig {
+    /// Time before the deadline of the lambda at which the sync is interrupted
+    pub(crate) fn margin(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.margin_seconds.unwrap_or(120))
+    }
+}
+
+/// Number of requests which are sent concurrently to the Google Admin API and
+/// to the AWS backend. Throttled requests back off together
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize, serde::Serialize)]
+pub struct ConcurrencyConfig {
+    google: Option<std::num::NonZeroUsize>,
+    aws: Option<std::num::NonZeroUsize>,
+}
+
+impl ConcurrencyConfig {
+    /// Number of groups whose members are listed concurrently
+    pub(crate) fn google(self) -> usize {
+        self.google.map_or(1, std::num::NonZeroUsize::get)
+    }
+
+    /// Number of users and groups which are created and of groups whose
+    /// members are patched concurrently
+    pub(crate) fn aws(self) -> usize {
+        self.aws.map_or(1, std::num::NonZeroUsize::get)
+    }
+}
+
+/// Google push notification channels which trigger a targeted sync
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct WatchChannels {
+    /// HTTPS endpoint of the lambda (Function URL or API Gateway)
+    pub(crate) address: String,
+    pub(crate) token: Secret,
+    pub(crate) state: Parameter,
+    ttl_minutes: Option<u32>,
+    renew_before_minutes: Option<u32>,
+}
+
+impl WatchChannels {
+    /// Lifetime of a new channel. Google limits channels to 6 hours
+    pub(crate) fn ttl(&self) -> chrono::Duration {
+        chrono::Duration::minutes(i64::from(self.ttl_minutes.unwrap_or(360)))
+    }
+
+    /// Time before the expiration of a channel at which it is renewed
+    pub(crate) fn renew_before(&self) -> chrono::Duration {
+        chrono::Duration::minutes(i64::from(self.renew_before_minutes.unwrap_or(60)))
+    }
+}
+
+/// Secret which contains the token that validates push notifications
 #[derive(Debug, serde::Deserialize)]
-pub struct Event {
+pub struct ChannelToken {
+    pub(crate) token: String,
+}
+
+/// Operation which is run by the lambda
+#[derive(Debug, Copy, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub enum Mode {
+    #[default]
+    Sync,
+    RenewWatchChannels,
+    /// Verifies the configuration and credentials without syncing
+    Check,
+    /// Exports the state of google and of every target without syncing
+    Snapshot,
+    /// Computes the changes of a sync from a snapshot without accessing google or AWS
+    Diff,
+}
+
+/// Request of a Lambda Function URL or an API Gateway
+#[derive(Debug)]
+pub struct HttpRequest<'a> {
+    headers: &'a std::collections::HashMap<String, String>,
+    pub(crate) body: Option<&'a str>,
+    pub(crate) is_base64_encoded: bool,
+}
+
+impl HttpRequest<'_> {
+    /// Returns the value of the header. Header names are case insensitive
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct GoogleTenant {
     security_hub_google_creds: Option<Secret>,
+    security_hub_ldap_creds: Option<Secret>,
+    google_api_query_for_users: Option<String>,
+    google_api_query_for_groups: Option<String>,
+    ignore_users_regexes: Option<Vec<String>>,
+    include_users_regexes: Option<Vec<String>>,
+    ignore_groups_regexes: Option<Vec<String>>,
+    include_groups_regexes: Option<Vec<String>>,
+}
+
+impl GoogleTenant {
+    fn into_tenant(self) -> anyhow::Result<Tenant<'static>> {
+        use anyhow::bail;
+
+        let source = match (self.security_hub_google_creds, self.security_hub_ldap_creds) {
+            (Some(secret), None) => TenantSource::Google(std::borrow::Cow::Owned(secret)),
+            (None, Some(secret)) => TenantSource::Ldap(std::borrow::Cow::Owned(secret)),
+            _ => bail!("Each tenant in google_tenants must contain either a security_hub_google_creds or a security_hub_ldap_creds object."),
+        };
+        let owner = format!("google tenant {}", source.name());
+        let parse = |name: &str, regexes| parse_regexes(name, &owner, regexes);
+        Ok(Tenant {
+            ignore_users_regexes: parse("ignore_users_regexes", self.ignore_users_regexes)?,
+            include_users_regexes: parse("include_users_regexes", self.include_users_regexes)?,
+            ignore_groups_regexes: parse("ignore_groups_regexes", self.ignore_groups_regexes)?,
+            include_groups_regexes: parse("include_groups_regexes", self.include_groups_regexes)?,
+            google_api_query_for_users: self
+                .google_api_query_for_users
+                .map(std::borrow::Cow::Owned),
+            google_api_query_for_groups: self
+                .google_api_query_for_groups
+                .map(std::borrow::Cow::Owned),
+            source,
+        })
+    }
+}
+
+fn parse_regexes(
+    name: &str,
+    owner: &str,
+    regexes: Option<Vec<String>>,
+) -> anyhow::Result<Option<regex::RegexSet>> {
+    use anyhow::Context;
+
+    regexes
+        .map(|v| {
+            regex::RegexSet::new(&v).with_context(|| {
+                format!("Unable to parse the following regex values from {name} of {owner}: {v:?}")
+            })
+        })
+        .transpose()
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+#[allow(variant_size_differences)]
+pub enum ScimTargets {
+    Single(Secret),
+    Multiple(Vec<ScimTarget>),
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ScimTarget {
     security_hub_scim_creds: Option<Secret>,
+    identity_store: Option<crate::aws::IdentityStoreConfig>,
+    ignore_groups_regexes: Option<Vec<String>>,
+    include_groups_regexes: Option<Vec<String>>,
+    account_assignments: Option<AccountAssignmentsConfig>,
+}
+
+impl ScimTarget {
+    fn into_target(self) -> anyhow::Result<Target<'static>> {
+        use anyhow::bail;
+
+        let backend = match (self.security_hub_scim_creds, self.identity_store) {
+            (Some(secret), None) => TargetBackend::Scim(std::borrow::Cow::Owned(secret)),
+            (None, Some(config)) => TargetBackend::IdentityStore(std::borrow::Cow::Owned(config)),
+            _ => bail!("Each target in security_hub_scim_creds must contain either a security_hub_scim_creds or an identity_store object."),
+        };
+        let owner = format!("target {}", backend.name());
+        let parse = |name: &str, regexes| parse_regexes(name, &owner, regexes);
+        Ok(Target {
+            ignore_groups_regexes: parse("ignore_groups_regexes", self.ignore_groups_regexes)?,
+            include_groups_regexes: parse("include_groups_regexes", self.include_groups_regexes)?,
+            account_assignments: self
+                .account_assignments
+                .map(AccountAssignmentsConfig::into_assignments)
+                .transpose()?,
+            backend,
+        })
+    }
+}
+
+/// Store of AWS SSO users and groups which is used by a target
+#[derive(Debug)]
+pub enum TargetBackend<'a> {
+    Scim(std::borrow::Cow<'a, Secret>),
+    IdentityStore(std::borrow::Cow<'a, crate::aws::IdentityStoreConfig>),
+}
+
+impl TargetBackend<'_> {
+    fn name(&self) -> &str {
+        match *self {
+            Self::Scim(ref secret) => &secret.id,
+            Self::IdentityStore(ref config) => &config.identity_store_id,
+        }
+    }
+}
+
+/// AWS SSO instance to which users and groups are synced
+/// together with the filters that apply to it
+#[derive(Debug)]
+pub struct Target<'a> {
+    pub(crate) backend: TargetBackend<'a>,
+    pub(crate) ignore_groups_regexes: Option<regex::RegexSet>,
+    pub(crate) include_groups_regexes: Option<regex::RegexSet>,
+    pub(crate) account_assignments: Option<AccountAssignments>,
+}
+
+impl Target<'_> {
+    pub(crate) fn name(&self) -> &str {
+        self.backend.name()
+    }
+
+    pub(crate) fn is_group_included(&self, email: &str) -> bool {
+        self.ignore_groups_regexes
+            .as_ref()
+            .is_none_or(|r| !r.is_match(email))
+            && self
+                .include_groups_regexes
+                .as_ref()
+                .is_none_or(|r| r.is_match(email))
+    }
+}
+
+/// Directory from which the users and groups of a tenant are read
+#[derive(Debug)]
+pub enum TenantSource<'a> {
+    Google(std::borrow::Cow<'a, Secret>),
+    Ldap(std::borrow::Cow<'a, Secret>),
+}
+
+impl TenantSource<'_> {
+    fn name(&self) -> &str {
+        match *self {
+            Self::Google(ref secret) | Self::Ldap(ref secret) => &secret.id,
+        }
+    }
+}
+
+/// Google Workspace or LDAP directory from which users and groups are synced
+/// together with the filters that apply to it
+#[derive(Debug)]
+pub struct Tenant<'a> {
+    pub(crate) source: TenantSource<'a>,
+    pub(crate) google_api_query_for_users: Option<std::borrow::Cow<'a, str>>,
+    pub(crate) google_api_query_for_groups: Option<std::borrow::Cow<'a, str>>,
+    pub(crate) ignore_users_regexes: Option<regex::RegexSet>,
+    pub(crate) include_users_regexes: Option<regex::RegexSet>,
+    pub(crate) ignore_groups_regexes: Option<regex::RegexSet>,
+    pub(crate) include_groups_regexes: Option<regex::RegexSet>,
+}
+
+impl Tenant<'_> {
+    pub(crate) fn name(&self) -> &str {
+        self.source.name()
+    }
+
+    pub(crate) fn is_user_included(&self, primary_email: &str) -> bool {
+        self.ignore_users_regexes
+            .as_ref()
+            .is_none_or(|r| !r.is_match(primary_email))
+            && self
+                .include_users_regexes
+                .as_ref()
+                .is_none_or(|r| r.is_match(primary_email))
+    }
+
+    pub(crate) fn is_group_included(&self, email: &str) -> bool {
+        self.ignore_groups_regexes
+            .as_ref()
+            .is_none_or(|r| !r.is_match(email))
+            && self
+                .include_groups_regexes
+                .as_ref()
+                .is_none_or(|r| r.is_match(email))
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Event {
+    config: Option<ConfigDocument>,
+    security_hub_google_creds: Option<Secret>,
+    security_hub_ldap_creds: Option<Secret>,
+    security_hub_scim_creds: Option<ScimTargets>,
     google_api_query_for_users: Option<String>,
     google_api_query_for_groups: Option<String>,
     ignore_users_regexes: Option<Vec<String>>,
@@ -21,37 +851,271 @@ pub struct Event {
     ignore_groups_regexes: Option<Vec<String>>,
     include_groups_regexes: Option<Vec<String>>,
     sync_strategie: Option<SyncStrategie>,
+    sync_strategie_overrides: Option<Vec<SyncStrategieOverride>>,
+    group_member_options: Option<GroupMemberOptions>,
+    group_member_options_overrides: Option<Vec<GroupMemberOptionsOverride>>,
+    external_members: Option<ExternalMembers>,
+    identity_normalization: Option<IdentityNormalization>,
+    user_name: Option<UserNameConfig>,
+    google_tenants: Option<Vec<GoogleTenant>>,
+    identity_store: Option<crate::aws::IdentityStoreConfig>,
+    account_assignments: Option<AccountAssignmentsConfig>,
+    incremental: Option<IncrementalSync>,
+    watch_channels: Option<WatchChannels>,
+    deadline: Option<DeadlineConfig>,
+    concurrency: Option<ConcurrencyConfig>,
+    audit_log: Option<AuditLogConfig>,
+    snapshot: Option<SnapshotConfig>,
+    diff: Option<DiffConfig>,
+    continuation: Option<crate::continuation::Continuation>,
+    mode: Option<Mode>,
+    headers: Option<std::collections::HashMap<String, String>>,
+    body: Option<String>,
+    #[serde(rename = "isBase64Encoded", default)]
+    is_base64_encoded: bool,
 }
 
 impl Event {
-    pub(crate) fn get_security_hub_google_creds(
+    pub(crate) fn get_config(
         &self,
-    ) -> anyhow::Result<std::borrow::Cow<'_, Secret>> {
+    ) -> anyhow::Result<Option<std::borrow::Cow<'_, ConfigDocument>>> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.config {
+            return Ok(Some(std::borrow::Cow::Borrowed(v)));
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_CONFIG) {
+            return serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object."))
+                .map(Some);
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn get_tenant_source(&self) -> anyhow::Result<TenantSource<'_>> {
         use anyhow::{bail, Context};
 
         if let Some(ref v) = self.security_hub_google_creds {
-            return Ok(std::borrow::Cow::Borrowed(v));
+            return Ok(TenantSource::Google(std::borrow::Cow::Borrowed(v)));
+        }
+        if let Some(ref v) = self.security_hub_ldap_creds {
+            return Ok(TenantSource::Ldap(std::borrow::Cow::Borrowed(v)));
         }
         if let Ok(env) = std::env::var(crate::ENV_VAR_SH_GOOGLE_CREDS) {
             return serde_json::from_str(&env)
+                .map(TenantSource::Google)
                 .with_context(|| format!("{env} is not a valid json object."));
         }
-        bail!("Either the lambda event must contain an security_hub_google_creds object or the env variable {}, must be defined.", crate::ENV_VAR_SH_GOOGLE_CREDS)
+        if let Ok(env) = std::env::var(crate::ENV_VAR_SH_LDAP_CREDS) {
+            return serde_json::from_str(&env)
+                .map(TenantSource::Ldap)
+                .with_context(|| format!("{env} is not a valid json object."));
+        }
+        bail!("Either the lambda event must contain an security_hub_google_creds or security_hub_ldap_creds object or one of the env variables {} or {}, must be defined.", crate::ENV_VAR_SH_GOOGLE_CREDS, crate::ENV_VAR_SH_LDAP_CREDS)
     }
 
     pub(crate) fn get_security_hub_scim_creds(
         &self,
-    ) -> anyhow::Result<std::borrow::Cow<'_, Secret>> {
-        use anyhow::{bail, Context};
+    ) -> anyhow::Result<Option<std::borrow::Cow<'_, ScimTargets>>> {
+        use anyhow::Context;
 
         if let Some(ref v) = self.security_hub_scim_creds {
-            return Ok(std::borrow::Cow::Borrowed(v));
+            return Ok(Some(std::borrow::Cow::Borrowed(v)));
         }
         if let Ok(env) = std::env::var(crate::ENV_VAR_SH_SCIM_CREDS) {
+            return serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object or array."))
+                .map(Some);
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn get_identity_store(
+        &self,
+    ) -> anyhow::Result<Option<std::borrow::Cow<'_, crate::aws::IdentityStoreConfig>>> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.identity_store {
+            return Ok(Some(std::borrow::Cow::Borrowed(v)));
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_IDENTITY_STORE) {
+            return serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object."))
+                .map(Some);
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn get_account_assignments(&self) -> anyhow::Result<Option<AccountAssignments>> {
+        use anyhow::Context;
+
+        let config = if let Some(ref v) = self.account_assignments {
+            v.clone()
+        } else if let Ok(env) = std::env::var(crate::ENV_VAR_ACCOUNT_ASSIGNMENTS) {
+            serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object."))?
+        } else {
+            return Ok(None);
+        };
+        config.into_assignments().map(Some)
+    }
+
+    pub(crate) fn get_incremental(
+        &self,
+    ) -> anyhow::Result<Option<std::borrow::Cow<'_, IncrementalSync>>> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.incremental {
+            return Ok(Some(std::borrow::Cow::Borrowed(v)));
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_INCREMENTAL) {
+            return serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object."))
+                .map(Some);
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn get_watch_channels(
+        &self,
+    ) -> anyhow::Result<Option<std::borrow::Cow<'_, WatchChannels>>> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.watch_channels {
+            return Ok(Some(std::borrow::Cow::Borrowed(v)));
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_WATCH_CHANNELS) {
+            return serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object."))
+                .map(Some);
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn get_deadline(
+        &self,
+    ) -> anyhow::Result<Option<std::borrow::Cow<'_, DeadlineConfig>>> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.deadline {
+            return Ok(Some(std::borrow::Cow::Borrowed(v)));
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_DEADLINE) {
+            return serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object."))
+                .map(Some);
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn get_concurrency(&self) -> anyhow::Result<ConcurrencyConfig> {
+        use anyhow::Context;
+
+        if let Some(v) = self.concurrency {
+            return Ok(v);
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_CONCURRENCY) {
             return serde_json::from_str(&env)
                 .with_context(|| format!("{env} is not a valid json object."));
         }
-        bail!("Either the lambda event must contain an security_hub_scim_creds object or the env variable {}, must be defined.", crate::ENV_VAR_SH_SCIM_CREDS)
+        Ok(ConcurrencyConfig::default())
+    }
+
+    pub(crate) fn get_audit_log(
+        &self,
+    ) -> anyhow::Result<Option<std::borrow::Cow<'_, AuditLogConfig>>> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.audit_log {
+            return Ok(Some(std::borrow::Cow::Borrowed(v)));
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_AUDIT_LOG) {
+            return serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object."))
+                .map(Some);
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn get_snapshot(
+        &self,
+    ) -> anyhow::Result<Option<std::borrow::Cow<'_, SnapshotConfig>>> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.snapshot {
+            return Ok(Some(std::borrow::Cow::Borrowed(v)));
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_SNAPSHOT) {
+            return serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object."))
+                .map(Some);
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn get_diff(&self) -> anyhow::Result<Option<std::borrow::Cow<'_, DiffConfig>>> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.diff {
+            return Ok(Some(std::borrow::Cow::Borrowed(v)));
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_DIFF) {
+            return serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object."))
+                .map(Some);
+        }
+        Ok(None)
+    }
+
+    /// Returns the progress of the full sync if the lambda was invoked to continue it
+    pub(crate) const fn get_continuation(&self) -> Option<&crate::continuation::Continuation> {
+        self.continuation.as_ref()
+    }
+
+    pub(crate) fn get_mode(&self) -> Mode {
+        self.mode.unwrap_or_default()
+    }
+
+    /// Returns the http request if the lambda was invoked via a Function URL or an API Gateway
+    pub(crate) fn get_http_request(&self) -> Option<HttpRequest<'_>> {
+        Some(HttpRequest {
+            headers: self.headers.as_ref()?,
+            body: self.body.as_deref(),
+            is_base64_encoded: self.is_base64_encoded,
+        })
+    }
+
+    pub(crate) fn get_scim_targets(&self) -> anyhow::Result<Vec<Target<'_>>> {
+        use anyhow::bail;
+
+        let single = |backend| {
+            Ok(vec![Target {
+                backend,
+                ignore_groups_regexes: None,
+                include_groups_regexes: None,
+                account_assignments: self.get_account_assignments()?,
+            }])
+        };
+        match self.get_security_hub_scim_creds()? {
+            Some(std::borrow::Cow::Borrowed(ScimTargets::Single(secret))) => {
+                single(TargetBackend::Scim(std::borrow::Cow::Borrowed(secret)))
+            }
+            Some(std::borrow::Cow::Owned(ScimTargets::Single(secret))) => {
+                single(TargetBackend::Scim(std::borrow::Cow::Owned(secret)))
+            }
+            Some(std::borrow::Cow::Borrowed(ScimTargets::Multiple(targets))) => targets
+                .iter()
+                .cloned()
+                .map(ScimTarget::into_target)
+                .collect(),
+            Some(std::borrow::Cow::Owned(ScimTargets::Multiple(targets))) => {
+                targets.into_iter().map(ScimTarget::into_target).collect()
+            }
+            None => match self.get_identity_store()? {
+                Some(config) => single(TargetBackend::IdentityStore(config)),
+                None => bail!("Either the lambda event must contain an security_hub_scim_creds or identity_store object or one of the env variables {} or {}, must be defined.", crate::ENV_VAR_SH_SCIM_CREDS, crate::ENV_VAR_IDENTITY_STORE),
+            },
+        }
     }
 
     pub(crate) fn get_google_api_query_for_groups(&self) -> Option<std::borrow::Cow<'_, str>> {
@@ -194,4 +1258,138 @@ impl Event {
         }
         Ok(SyncStrategie::GroupMembersOnly)
     }
+
+    pub(crate) fn get_sync_strategie_overrides(&self) -> anyhow::Result<Vec<SyncStrategieRule>> {
+        use anyhow::Context;
+
+        let overrides = if let Some(ref v) = self.sync_strategie_overrides {
+            std::borrow::Cow::Borrowed(v)
+        } else if let Ok(env) = std::env::var(crate::ENV_VAR_SYNC_STRATEGIE_OVERRIDES) {
+            std::borrow::Cow::Owned(
+                serde_json::from_str::<Vec<SyncStrategieOverride>>(&env)
+                    .with_context(|| format!("{env} is not a valid json array."))?,
+            )
+        } else {
+            return Ok(Vec::new());
+        };
+        overrides.iter().map(SyncStrategieRule::new).collect()
+    }
+
+    pub(crate) fn get_user_sync_rules(&self) -> anyhow::Result<UserSyncRules> {
+        Ok(UserSyncRules::new(
+            self.get_sync_strategie()?,
+            self.get_sync_strategie_overrides()?,
+        ))
+    }
+
+    pub(crate) fn get_group_member_options(
+        &self,
+    ) -> anyhow::Result<std::borrow::Cow<'_, GroupMemberOptions>> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.group_member_options {
+            return Ok(std::borrow::Cow::Borrowed(v));
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_GROUP_MEMBER_OPTIONS) {
+            return serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object."));
+        }
+        Ok(std::borrow::Cow::Owned(GroupMemberOptions::default()))
+    }
+
+    pub(crate) fn get_group_member_options_overrides(
+        &self,
+    ) -> anyhow::Result<Vec<(regex::Regex, GroupMemberOptions)>> {
+        use anyhow::Context;
+
+        let overrides = if let Some(ref v) = self.group_member_options_overrides {
+            std::borrow::Cow::Borrowed(v)
+        } else if let Ok(env) = std::env::var(crate::ENV_VAR_GROUP_MEMBER_OPTIONS_OVERRIDES) {
+            std::borrow::Cow::Owned(
+                serde_json::from_str::<Vec<GroupMemberOptionsOverride>>(&env)
+                    .with_context(|| format!("{env} is not a valid json array."))?,
+            )
+        } else {
+            return Ok(Vec::new());
+        };
+        overrides
+            .iter()
+            .map(|o| {
+                regex::Regex::new(&o.group_regex)
+                    .with_context(|| {
+                        format!(
+                            "Unable to parse the following regex value from group_member_options_overrides: {}",
+                            o.group_regex
+                        )
+                    })
+                    .map(|r| (r, o.options.clone()))
+            })
+            .collect()
+    }
+
+    pub(crate) fn get_external_members(
+        &self,
+    ) -> anyhow::Result<std::borrow::Cow<'_, ExternalMembers>> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.external_members {
+            return Ok(std::borrow::Cow::Borrowed(v));
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_EXTERNAL_MEMBERS) {
+            return serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object."));
+        }
+        Ok(std::borrow::Cow::Owned(ExternalMembers::default()))
+    }
+
+    pub(crate) fn get_identity_normalization(&self) -> anyhow::Result<IdentityNormalization> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.identity_normalization {
+            return Ok(v.clone());
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_IDENTITY_NORMALIZATION) {
+            return serde_json::from_str(&env)
+                .with_context(|| format!("{env} is not a valid json object."));
+        }
+        Ok(IdentityNormalization::default())
+    }
+
+    pub(crate) fn get_user_name(&self) -> anyhow::Result<UserNameConfig> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.user_name {
+            return v.clone().validate();
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_USER_NAME) {
+            return serde_json::from_str::<UserNameConfig>(&env)
+                .with_context(|| format!("{env} is not a valid json object."))?
+                .validate();
+        }
+        Ok(UserNameConfig::default())
+    }
+
+    pub(crate) fn get_google_tenants(&self) -> anyhow::Result<Vec<Tenant<'_>>> {
+        use anyhow::Context;
+
+        if let Some(ref v) = self.google_tenants {
+            return v.iter().cloned().map(GoogleTenant::into_tenant).collect();
+        }
+        if let Ok(env) = std::env::var(crate::ENV_VAR_GOOGLE_TENANTS) {
+            return serde_json::from_str::<Vec<GoogleTenant>>(&env)
+                .with_context(|| format!("{env} is not a valid json array."))?
+                .into_iter()
+                .map(GoogleTenant::into_tenant)
+                .collect();
+        }
+        Ok(vec![Tenant {
+            source: self.get_tenant_source()?,
+            google_api_query_for_users: self.get_google_api_query_for_users(),
+            google_api_query_for_groups: self.get_google_api_query_for_groups(),
+            ignore_users_regexes: self.get_ignore_users_regexes()?,
+            include_users_regexes: self.get_include_users_regexes()?,
+            ignore_groups_regexes: self.get_ignore_groups_regexes()?,
+            include_groups_regexes: self.get_include_groups_regexes()?,
+        }])
+    }
 }
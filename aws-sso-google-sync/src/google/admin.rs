@@ -1,5 +1,26 @@
 const ENDPOINT: &str = "https://admin.googleapis.com/admin/directory/v1";
 const SCOPES: &str = "https://www.googleapis.com/auth/admin.directory.group.readonly https://www.googleapis.com/auth/admin.directory.group.member.readonly https://www.googleapis.com/auth/admin.directory.user.readonly";
+/// Only required to list the domains of the customer if `customer_id` is set without `domains`
+const DOMAIN_SCOPE: &str = "https://www.googleapis.com/auth/admin.directory.domain.readonly";
+
+#[derive(Debug, serde::Deserialize)]
+struct Domains {
+    domains: Option<Vec<Domain>>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Domain {
+    #[serde(rename = "domainName")]
+    domain_name: String,
+    #[serde(rename = "domainAliases", default)]
+    domain_aliases: Vec<DomainAlias>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct DomainAlias {
+    #[serde(rename = "domainAliasName")]
+    domain_alias_name: String,
+}
 
 #[derive(Debug, serde::Deserialize)]
 struct Members {
@@ -8,14 +29,17 @@ struct Members {
     next_page_token: Option<String>,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize)]
 pub struct Member {
+    pub(crate) id: Option<String>,
     pub(crate) email: String,
     #[serde(rename = "type")]
     pub(crate) r#type: MemberType,
+    pub(crate) role: Option<MemberRole>,
+    pub(crate) status: Option<MemberStatus>,
 }
 
-#[derive(Debug, serde::Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, serde::Deserialize, PartialEq, Eq)]
 pub enum MemberType {
     #[serde(rename = "USER")]
     User,
@@ -23,6 +47,28 @@ pub enum MemberType {
     Other,
 }
 
+#[derive(Debug, Copy, Clone, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
+pub enum MemberRole {
+    #[serde(rename = "OWNER")]
+    Owner,
+    #[serde(rename = "MANAGER")]
+    Manager,
+    #[serde(rename = "MEMBER")]
+    Member,
+    /// Any other role returned by Google. Members with it are only synced
+    /// if no roles are configured
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, PartialEq, Eq)]
+pub enum MemberStatus {
+    #[serde(rename = "SUSPENDED")]
+    Suspended,
+    #[serde(other)]
+    Other,
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct Groups {
     groups: Option<Vec<Group>>,
@@ -30,7 +76,7 @@ struct Groups {
     next_page_token: Option<String>,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Group {
     pub(crate) id: String,
     pub(crate) email: String,
@@ -43,29 +89,122 @@ struct Users {
     next_page_token: Option<String>,
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct User {
     pub(crate) id: String,
     #[serde(rename = "primaryEmail")]
     pub(crate) primary_email: String,
     pub(crate) suspended: Option<bool>,
+    #[serde(default)]
     pub(crate) name: UserName,
+    #[serde(default)]
     pub(crate) emails: Vec<UserMail>,
     #[serde(rename = "thumbnailPhotoUrl")]
     pub(crate) thumbnail_photo_url: Option<String>,
+    #[serde(default)]
+    pub(crate) aliases: Vec<String>,
+    #[serde(rename = "externalIds", default)]
+    pub(crate) external_ids: Vec<ExternalId>,
+    #[serde(default)]
+    pub(crate) relations: Vec<Relation>,
+    #[serde(rename = "customSchemas", default)]
+    pub(crate) custom_schemas:
+        std::collections::HashMap<String, std::collections::HashMap<String, serde_json::Value>>,
+    #[serde(rename = "orgUnitPath")]
+    pub(crate) org_unit_path: Option<String>,
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+impl User {
+    /// Creates a user with a minimal set of attributes from a group member
+    /// which is not part of the Google Workspace (e.g. an external collaborator)
+    pub(crate) fn from_external_member(member: &Member) -> Self {
+        let local_part = member
+            .email
+            .split_once('@')
+            .map_or(member.email.as_str(), |(l, _)| l);
+        let (given_name, family_name) = local_part
+            .split_once(['.', '_', '-'])
+            .unwrap_or((local_part, local_part));
+        Self {
+            id: member.id.clone().unwrap_or_else(|| member.email.clone()),
+            primary_email: member.email.clone(),
+            suspended: Some(member.status == Some(MemberStatus::Suspended)),
+            name: UserName {
+                full_name: Some(local_part.into()),
+                family_name: Some(family_name.into()),
+                given_name: Some(given_name.into()),
+            },
+            emails: vec![UserMail {
+                address: member.email.clone(),
+                primary: Some(true),
+                r#type: None,
+            }],
+            thumbnail_photo_url: None,
+            aliases: Vec::new(),
+            external_ids: Vec::new(),
+            relations: Vec::new(),
+            custom_schemas: std::collections::HashMap::new(),
+            org_unit_path: None,
+        }
+    }
+
+    /// Returns the email of the manager of the user
+    pub(crate) fn manager(&self) -> Option<&str> {
+        self.relations
+            .iter()
+            .find(|r| r.r#type.as_deref() == Some("manager"))
+            .map(|r| r.value.as_str())
+    }
+
+    /// Returns the given name, the family name and the full name of the user. Missing
+    /// names are derived from the full name or the local part of the primary email.
+    /// Returns `None` if the given or the family name can not be derived
+    pub(crate) fn complete_name(&self) -> Option<(String, String, String)> {
+        fn non_empty(value: &Option<String>) -> Option<String> {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(String::from)
+        }
+
+        let full_name = non_empty(&self.name.full_name);
+        let mut given_name = non_empty(&self.name.given_name);
+        let mut family_name = non_empty(&self.name.family_name);
+        if given_name.is_none() || family_name.is_none() {
+            let local_part = self
+                .primary_email
+                .split_once('@')
+                .map_or(self.primary_email.as_str(), |(l, _)| l);
+            let derived = full_name
+                .as_deref()
+                .and_then(|n| n.rsplit_once(char::is_whitespace))
+                .or_else(|| local_part.split_once(['.', '_', '-']))
+                .map(|(g, f)| (g.trim(), f.trim()))
+                .filter(|(g, f)| !g.is_empty() && !f.is_empty());
+            if let Some((g, f)) = derived {
+                given_name = given_name.or_else(|| Some(g.into()));
+                family_name = family_name.or_else(|| Some(f.into()));
+            }
+        }
+        let (given_name, family_name) = (given_name?, family_name?);
+        let full_name = full_name.unwrap_or_else(|| format!("{given_name} {family_name}"));
+        Some((given_name, family_name, full_name))
+    }
+}
+
+/// Name of a user. Google omits names which were never set, e.g. for service accounts
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
 pub struct UserName {
     #[serde(rename = "fullName")]
-    pub(crate) full_name: String,
+    pub(crate) full_name: Option<String>,
     #[serde(rename = "familyName")]
-    pub(crate) family_name: String,
+    pub(crate) family_name: Option<String>,
     #[serde(rename = "givenName")]
-    pub(crate) given_name: String,
+    pub(crate) given_name: Option<String>,
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct UserMail {
     pub(crate) address: String,
     pub(crate) primary: Option<bool>,
@@ -73,9 +212,45 @@ pub struct UserMail {
     pub(crate) r#type: Option<String>,
 }
 
+impl UserMail {
+    /// Returns the SCIM type of the email. Google emails of type `custom` are
+    /// synced as `other`, emails without a type as `work`
+    pub(crate) fn scim_type(&self) -> &'static str {
+        match self.r#type.as_deref() {
+            Some("home") => "home",
+            Some("other" | "custom") => "other",
+            _ => "work",
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Relation {
+    pub(crate) value: String,
+    pub(crate) r#type: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ExternalId {
+    pub(crate) value: String,
+    pub(crate) r#type: Option<String>,
+    #[serde(rename = "customType")]
+    pub(crate) custom_type: Option<String>,
+}
+
+impl ExternalId {
+    /// Returns the custom type for entries of type `custom`, otherwise the type
+    pub(crate) fn type_name(&self) -> Option<&str> {
+        match self.r#type.as_deref() {
+            Some("custom") => self.custom_type.as_deref(),
+            t => t,
+        }
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
-struct AuthToken {
-    access_token: String,
+pub(super) struct AuthToken {
+    pub(super) access_token: String,
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -91,9 +266,14 @@ struct JwtClaims<'a> {
 #[derive(Debug, serde::Deserialize)]
 pub struct AdminCreds {
     #[serde(default)]
-    customer_id: Option<String>,
+    pub(super) customer_id: Option<String>,
+    #[serde(default)]
+    domains: Option<Vec<String>>,
     mail: String,
-    credential_json: CredentialJsonTypes,
+    #[serde(default)]
+    credential_json: Option<CredentialJsonTypes>,
+    #[serde(default)]
+    workload_identity: Option<super::federation::WorkloadIdentity>,
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -117,25 +297,29 @@ pub struct Admin<'a> {
     client: reqwest::Client,
     token: String,
     customer_id: Option<&'a str>,
+    domains: &'a [String],
     domain: &'a str,
+    /// Domains and domain aliases of the customer, if users and groups of the whole
+    /// customer are listed
+    customer_domains: Vec<String>,
+    throttle: crate::throttle::Throttle,
 }
 
 impl<'a> Admin<'a> {
     pub(crate) async fn new(secret: &'a AdminCreds) -> anyhow::Result<Admin<'a>> {
+        let mut admin = Self::with_scopes(secret, SCOPES).await?;
+        if let (Some(customer_id), true) = (admin.customer_id, admin.domains.is_empty()) {
+            admin.customer_domains =
+                Self::fetch_customer_domains(secret, customer_id, &admin.client).await?;
+        }
+        Ok(admin)
+    }
+
+    async fn with_scopes(secret: &'a AdminCreds, scopes: &str) -> anyhow::Result<Admin<'a>> {
         use anyhow::Context;
 
         let client = reqwest::Client::new();
-        let credential_json;
-        let credential_json_ref = match secret.credential_json {
-            CredentialJsonTypes::Json(ref v) => v,
-            CredentialJsonTypes::String(ref json) => {
-                credential_json =
-                    serde_json::from_str(json).context("Unable to parse credential_json")?;
-                &credential_json
-            }
-        };
-        let jwt = Self::sign_jwt(&secret.mail, credential_json_ref)?;
-        let token = Self::fetch_token_by_jwt(jwt, &credential_json_ref.token_uri, &client).await?;
+        let token = Self::fetch_token(secret, scopes, &client).await?;
         let domain = secret
             .mail
             .split('@')
@@ -145,11 +329,163 @@ impl<'a> Admin<'a> {
             client,
             token,
             customer_id: secret.customer_id.as_deref(),
+            domains: secret.domains.as_deref().unwrap_or_default(),
             domain,
+            customer_domains: Vec::new(),
+            throttle: crate::throttle::Throttle::default(),
         })
     }
 
-    fn sign_jwt(mail: &str, credential_json: &CredentialJson) -> anyhow::Result<String> {
+    /// Returns all domains and domain aliases of the customer
+    async fn fetch_customer_domains(
+        secret: &AdminCreds,
+        customer_id: &str,
+        client: &reqwest::Client,
+    ) -> anyhow::Result<Vec<String>> {
+        use anyhow::Context;
+
+        let token = Self::fetch_token(secret, DOMAIN_SCOPE, client).await?;
+        let domains = client
+            .request(
+                reqwest::Method::GET,
+                format!("{ENDPOINT}/customer/{customer_id}/domains"),
+            )
+            .header("Authorization", format!("Bearer {token}"))
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .context("Unable to send request to Google Admin API")?
+            .error_for_status()
+            .context("Error returned from server (list domains)")?
+            .json::<Domains>()
+            .await
+            .context("Could not parse result from Google Admin API")?
+            .domains
+            .unwrap_or_default();
+        Ok(domains
+            .into_iter()
+            .flat_map(|d| {
+                std::iter::once(d.domain_name)
+                    .chain(d.domain_aliases.into_iter().map(|a| a.domain_alias_name))
+            })
+            .collect())
+    }
+
+    /// Verifies every scope which is required for the sync separately with a minimal
+    /// request, so that a missing domain-wide delegation scope can be identified.
+    /// `Some` contains the reason why a scope could not be verified
+    pub(crate) async fn check_scopes(
+        secret: &'a AdminCreds,
+    ) -> Vec<(&'static str, anyhow::Result<Option<String>>)> {
+        let mut results = Vec::new();
+        let mut group_id = None;
+        for scope in SCOPES.split(' ') {
+            let result = match Self::with_scopes(secret, scope).await {
+                Ok(admin) => admin.check_scope(scope, &mut group_id).await,
+                Err(err) => Err(err),
+            };
+            results.push((scope, result));
+        }
+        if let (Some(customer_id), true) = (
+            secret.customer_id.as_deref(),
+            secret.domains.as_deref().unwrap_or_default().is_empty(),
+        ) {
+            let client = reqwest::Client::new();
+            let result = Self::fetch_customer_domains(secret, customer_id, &client)
+                .await
+                .map(|_| None);
+            results.push((DOMAIN_SCOPE, result));
+        }
+        results
+    }
+
+    async fn check_scope(
+        &self,
+        scope: &str,
+        group_id: &mut Option<String>,
+    ) -> anyhow::Result<Option<String>> {
+        use anyhow::Context;
+
+        let directory_scope = self
+            .directory_scopes()
+            .into_iter()
+            .take(1)
+            .collect::<Vec<_>>();
+        let request = if scope.ends_with("group.member.readonly") {
+            match *group_id {
+                Some(ref group_id) => self.client.request(
+                    reqwest::Method::GET,
+                    format!("{ENDPOINT}/groups/{group_id}/members"),
+                ),
+                // Without a group, only the token is verified
+                None => return Ok(Some("Not verified as no group is available".into())),
+            }
+        } else if scope.ends_with("group.readonly") {
+            self.client
+                .request(reqwest::Method::GET, format!("{ENDPOINT}/groups"))
+                .query(&directory_scope)
+        } else {
+            self.client
+                .request(reqwest::Method::GET, format!("{ENDPOINT}/users"))
+                .query(&directory_scope)
+        };
+        let res = request
+            .query(&[("maxResults", "1")])
+            .header("Authorization", format!("Bearer {}", &self.token))
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .context("Unable to send request to Google Admin API")?
+            .error_for_status()
+            .context("Error returned from server")?;
+        if scope.ends_with("group.readonly") {
+            *group_id = res
+                .json::<Groups>()
+                .await
+                .context("Could not parse result from Google Admin API")?
+                .groups
+                .and_then(|g| g.into_iter().next())
+                .map(|g| g.id);
+        }
+        Ok(None)
+    }
+
+    /// Fetches an access token for the given scopes on behalf of the admin user
+    pub(super) async fn fetch_token(
+        secret: &AdminCreds,
+        scopes: &str,
+        client: &reqwest::Client,
+    ) -> anyhow::Result<String> {
+        use anyhow::Context;
+
+        if let Some(ref workload_identity) = secret.workload_identity {
+            let jwt = workload_identity
+                .sign_jwt(&secret.mail, scopes, client)
+                .await?;
+            return Self::fetch_token_by_jwt(jwt, super::federation::TOKEN_URL, client).await;
+        }
+        let credential_json;
+        let credential_json_ref = match secret
+            .credential_json
+            .as_ref()
+            .context("Either credential_json or workload_identity must be defined")?
+        {
+            CredentialJsonTypes::Json(ref v) => v,
+            CredentialJsonTypes::String(ref json) => {
+                credential_json =
+                    serde_json::from_str(json).context("Unable to parse credential_json")?;
+                &credential_json
+            }
+        };
+        let jwt = Self::sign_jwt(&secret.mail, scopes, credential_json_ref)?;
+        Self::fetch_token_by_jwt(jwt, &credential_json_ref.token_uri, client).await
+    }
+
+    fn sign_jwt(
+        mail: &str,
+        scopes: &str,
+        credential_json: &CredentialJson,
+    ) -> anyhow::Result<String> {
         use anyhow::Context;
 
         let timestamp = get_current_timestamp();
@@ -162,7 +498,7 @@ impl<'a> Admin<'a> {
         let claims = JwtClaims {
             iss: &credential_json.client_email,
             sub: mail,
-            scope: SCOPES,
+            scope: scopes,
             aud: &credential_json.token_uri,
             exp: exp_time,
             iat: timestamp,
@@ -198,134 +534,207 @@ impl<'a> Admin<'a> {
             .context("Could not parse result from Google Auth Url")
     }
 
-    pub(crate) async fn list_users(
+    /// Fetches a single user or group. Returns `None` if it does not exist
+    async fn fetch_entry<T: serde::de::DeserializeOwned>(
         &self,
-        query: Option<&str>,
-        deleted: bool,
-    ) -> anyhow::Result<Vec<User>> {
+        url: &str,
+    ) -> anyhow::Result<Option<T>> {
+        use anyhow::Context;
+
+        let res = self
+            .client
+            .request(reqwest::Method::GET, url)
+            .header("Authorization", format!("Bearer {}", &self.token))
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .context("Unable to send request to Google Admin API")?;
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        res.error_for_status()
+            .context("Error returned from server")?
+            .json::<T>()
+            .await
+            .map(Some)
+            .context("Could not parse result from Google Admin API")
+    }
+
+    /// Returns the query parameters which restrict listings to the configured
+    /// domains, the customer or the domain of the admin user (in this order)
+    fn directory_scopes(&self) -> Vec<(&'static str, &str)> {
+        if !self.domains.is_empty() {
+            return self
+                .domains
+                .iter()
+                .map(|d| ("domain", d.as_str()))
+                .collect();
+        }
+        if let Some(customer_id) = self.customer_id {
+            return vec![("customer", customer_id)];
+        }
+        vec![("domain", self.domain)]
+    }
+
+    async fn fetch_users(&self, query: Option<&str>, deleted: bool) -> anyhow::Result<Vec<User>> {
         use anyhow::Context;
 
         let mut entries = Vec::new();
-        let mut token = None;
         let mut queries = Vec::new();
-        loop {
-            queries.clear();
-            if let Some(token) = token {
-                queries.push(("pageToken", token));
-            }
-            if let Some(query) = query {
-                queries.push(("query", String::from(query)));
-            }
-            if let Some(customer_id) = self.customer_id {
-                queries.push(("customer", String::from(customer_id)));
-            } else {
-                queries.push(("domain", String::from(self.domain)));
+        for (scope, scope_value) in self.directory_scopes() {
+            let mut token = None;
+            loop {
+                queries.clear();
+                if let Some(token) = token {
+                    queries.push(("pageToken", token));
+                }
+                if let Some(query) = query {
+                    queries.push(("query", String::from(query)));
+                }
+                queries.push((scope, String::from(scope_value)));
+                let res = self
+                    .client
+                    .request(
+                        reqwest::Method::GET,
+                        format!("{ENDPOINT}/users?showDeleted={deleted}&projection=full"),
+                    )
+                    .query(&queries)
+                    .header("Authorization", format!("Bearer {}", &self.token))
+                    .header("Accept", "application/json")
+                    .send()
+                    .await
+                    .context("Unable to send request to Google Admin API")?
+                    .error_for_status()
+                    .context("Error returned from server")?
+                    .json::<Users>()
+                    .await
+                    .context("Could not parse result from Google Admin API")?;
+
+                if let Some(mut new_entries) = res.users {
+                    entries.append(&mut new_entries);
+                }
+                if let Some(next_page_token) = res.next_page_token {
+                    token = Some(next_page_token);
+                    continue;
+                }
+                break;
             }
-            let res = self
-                .client
-                .request(
-                    reqwest::Method::GET,
-                    &format!("{ENDPOINT}/users?showDeleted={deleted}"),
-                )
-                .query(&queries)
-                .header("Authorization", format!("Bearer {}", &self.token))
-                .header("Accept", "application/json")
-                .send()
-                .await
-                .context("Unable to send request to Google Admin API")?
-                .error_for_status()
-                .context("Error returned from server")?
-                .json::<Users>()
-                .await
-                .context("Could not parse result from Google Admin API")?;
-
-            if let Some(mut new_entries) = res.users {
-                entries.append(&mut new_entries);
-            }
-            if let Some(next_page_token) = res.next_page_token {
-                token = Some(next_page_token);
-                continue;
-            }
-            break;
         }
 
+        let mut seen = std::collections::HashSet::new();
+        entries.retain(|e: &User| seen.insert(e.id.clone()));
         Ok(entries)
     }
+}
+
+#[async_trait::async_trait]
+impl crate::directory::DirectorySource for Admin<'_> {
+    async fn list_users(&self, query: Option<&str>) -> anyhow::Result<Vec<User>> {
+        self.fetch_users(query, false).await
+    }
 
-    pub(crate) async fn list_groups(&self, query: Option<&str>) -> anyhow::Result<Vec<Group>> {
+    async fn list_deleted_users(&self) -> anyhow::Result<Vec<User>> {
+        self.fetch_users(None, true).await
+    }
+
+    async fn get_user(&self, email: &str) -> anyhow::Result<Option<User>> {
+        self.fetch_entry(&format!("{ENDPOINT}/users/{email}?projection=full"))
+            .await
+    }
+
+    async fn get_group(&self, email: &str) -> anyhow::Result<Option<Group>> {
+        self.fetch_entry(&format!("{ENDPOINT}/groups/{email}"))
+            .await
+    }
+
+    async fn list_groups(&self, query: Option<&str>) -> anyhow::Result<Vec<Group>> {
         use anyhow::Context;
 
         let mut entries = Vec::new();
-        let mut token = None;
         let mut queries = Vec::new();
-        loop {
-            queries.clear();
-            if let Some(token) = token {
-                queries.push(("pageToken", token));
-            }
-            if let Some(query) = query {
-                queries.push(("query", String::from(query)));
-            }
-            let res = self
-                .client
-                .request(
-                    reqwest::Method::GET,
-                    &format!("{}/groups?domain={}", ENDPOINT, self.domain),
-                )
-                .query(&queries)
-                .header("Authorization", format!("Bearer {}", &self.token))
-                .header("Accept", "application/json")
-                .send()
-                .await
-                .context("Unable to send request to Google Admin API")?
-                .error_for_status()
-                .context("Error returned from server")?
-                .json::<Groups>()
-                .await
-                .context("Could not parse result from Google Admin API")?;
-
-            if let Some(mut new_entries) = res.groups {
-                entries.append(&mut new_entries);
+        for (scope, scope_value) in self.directory_scopes() {
+            let mut token = None;
+            loop {
+                queries.clear();
+                if let Some(token) = token {
+                    queries.push(("pageToken", token));
+                }
+                if let Some(query) = query {
+                    queries.push(("query", String::from(query)));
+                }
+                queries.push((scope, String::from(scope_value)));
+                let res = self
+                    .client
+                    .request(reqwest::Method::GET, format!("{ENDPOINT}/groups"))
+                    .query(&queries)
+                    .header("Authorization", format!("Bearer {}", &self.token))
+                    .header("Accept", "application/json")
+                    .send()
+                    .await
+                    .context("Unable to send request to Google Admin API")?
+                    .error_for_status()
+                    .context("Error returned from server")?
+                    .json::<Groups>()
+                    .await
+                    .context("Could not parse result from Google Admin API")?;
+
+                if let Some(mut new_entries) = res.groups {
+                    entries.append(&mut new_entries);
+                }
+                if let Some(next_page_token) = res.next_page_token {
+                    token = Some(next_page_token);
+                    continue;
+                }
+                break;
             }
-            if let Some(next_page_token) = res.next_page_token {
-                token = Some(next_page_token);
-                continue;
-            }
-            break;
         }
 
+        let mut seen = std::collections::HashSet::new();
+        entries.retain(|e: &Group| seen.insert(e.id.clone()));
         Ok(entries)
     }
 
-    pub(crate) async fn list_group_members(
+    async fn list_group_members(
         &self,
         group_id: &str,
-    ) -> anyhow::Result<std::collections::HashSet<String>> {
+        options: &crate::event::GroupMemberOptions,
+    ) -> anyhow::Result<Vec<Member>> {
         use anyhow::Context;
 
-        let mut entries = std::collections::HashSet::new();
+        let mut entries = Vec::new();
         let mut token: Option<String> = None;
         let mut query: [_; 1] = [("", String::new())];
         loop {
-            let query: &[_] = token.map_or_else(
+            let query: &[_] = token.as_ref().map_or_else(
                 || &[][..],
                 |token| {
-                    query = [("pageToken", token)];
+                    query = [("pageToken", token.clone())];
                     &query
                 },
             );
+            self.throttle.wait().await;
             let res = self
                 .client
                 .request(
                     reqwest::Method::GET,
-                    &format!("{ENDPOINT}/groups/{group_id}/members?includeDerivedMembership=true",),
+                    format!(
+                        "{ENDPOINT}/groups/{group_id}/members?includeDerivedMembership={}",
+                        options.include_derived_membership()
+                    ),
                 )
                 .query(query)
                 .header("Authorization", format!("Bearer {}", &self.token))
                 .header("Accept", "application/json")
                 .send()
                 .await
-                .context("Unable to send request to Google Admin API")?
+                .context("Unable to send request to Google Admin API")?;
+            // Members of multiple groups are listed concurrently, so throttled
+            // requests are repeated after a shared cooldown
+            if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                self.throttle.throttled().await;
+                continue;
+            }
+            let res = res
                 .error_for_status()
                 .context("Error returned from server")?
                 .json::<Members>()
@@ -337,7 +746,11 @@ impl<'a> Admin<'a> {
                     new_entries
                         .into_iter()
                         .filter(|e| e.r#type == MemberType::User)
-                        .map(|e| e.email),
+                        .filter(|e| options.is_role_included(e.role))
+                        .filter(|e| {
+                            !options.exclude_suspended()
+                                || e.status != Some(MemberStatus::Suspended)
+                        }),
                 );
             }
             if let Some(next_page_token) = res.next_page_token {
@@ -349,9 +762,21 @@ impl<'a> Admin<'a> {
 
         Ok(entries)
     }
+
+    /// Checks whether the given mail belongs to one of the domains of the Google Workspace
+    fn is_internal(&self, mail: &str) -> bool {
+        mail.rsplit_once('@').is_some_and(|(_, domain)| {
+            domain.eq_ignore_ascii_case(self.domain)
+                || self
+                    .domains
+                    .iter()
+                    .chain(&self.customer_domains)
+                    .any(|d| domain.eq_ignore_ascii_case(d))
+        })
+    }
 }
 
-fn get_current_timestamp() -> u64 {
+pub(super) fn get_current_timestamp() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .map(|d| d.as_secs())
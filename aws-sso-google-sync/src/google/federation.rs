@@ -0,0 +1,102 @@
+const STS_URL: &str = "https://sts.googleapis.com/v1/token";
+const IAM_CREDENTIALS_URL: &str =
+    "https://iamcredentials.googleapis.com/v1/projects/-/serviceAccounts";
+pub(super) const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+
+#[derive(Debug, serde::Deserialize)]
+struct SignJwtResponse {
+    #[serde(rename = "signedJwt")]
+    signed_jwt: String,
+}
+
+/// Service account which is impersonated through the Google Workload Identity
+/// Federation with the AWS credentials of the lambda
+#[derive(Debug, serde::Deserialize)]
+pub struct WorkloadIdentity {
+    /// Full resource name of the workload identity pool provider
+    audience: String,
+    service_account: String,
+    /// Region of the AWS STS endpoint. Defaults to the region of the lambda
+    region: Option<String>,
+}
+
+impl WorkloadIdentity {
+    /// Signs a jwt on behalf of the admin user with the service account, which
+    /// can be exchanged for an access token with domain-wide delegation
+    pub(super) async fn sign_jwt(
+        &self,
+        mail: &str,
+        scopes: &str,
+        client: &reqwest::Client,
+    ) -> anyhow::Result<String> {
+        use anyhow::Context;
+
+        let federated_token = self.fetch_federated_token(client).await?;
+        let timestamp = super::admin::get_current_timestamp();
+        let claims = serde_json::json!({
+            "iss": self.service_account,
+            "sub": mail,
+            "scope": scopes,
+            "aud": TOKEN_URL,
+            "iat": timestamp,
+            "exp": timestamp + (15 * 60),
+        });
+        client
+            .request(
+                reqwest::Method::POST,
+                format!("{IAM_CREDENTIALS_URL}/{}:signJwt", self.service_account),
+            )
+            .header("Authorization", format!("Bearer {federated_token}"))
+            .json(&serde_json::json!({ "payload": claims.to_string() }))
+            .send()
+            .await
+            .context("Unable to send request to Google IAM Credentials API")?
+            .error_for_status()
+            .with_context(|| {
+                format!(
+                    "Error returned from server (signJwt for {})",
+                    self.service_account
+                )
+            })?
+            .json::<SignJwtResponse>()
+            .await
+            .map(|r| r.signed_jwt)
+            .context("Could not parse result from Google IAM Credentials API")
+    }
+
+    /// Exchanges the AWS credentials of the lambda for a federated Google access token
+    async fn fetch_federated_token(&self, client: &reqwest::Client) -> anyhow::Result<String> {
+        use anyhow::Context;
+
+        let region = match self.region {
+            Some(ref region) => region.clone(),
+            None => std::env::var("AWS_REGION")
+                .context("region must be defined if AWS_REGION is not set")?,
+        };
+        let subject_token = crate::aws::get_caller_identity_token(&region, &self.audience).await?;
+        client
+            .request(reqwest::Method::POST, STS_URL)
+            .json(&serde_json::json!({
+                "grantType": "urn:ietf:params:oauth:grant-type:token-exchange",
+                "audience": self.audience,
+                "scope": "https://www.googleapis.com/auth/cloud-platform",
+                "requestedTokenType": "urn:ietf:params:oauth:token-type:access_token",
+                "subjectTokenType": "urn:ietf:params:aws:token-type:aws4_request",
+                "subjectToken": subject_token,
+            }))
+            .send()
+            .await
+            .context("Unable to send request to Google STS")?
+            .error_for_status()
+            .with_context(|| {
+                format!(
+                    "Error returned from server (token exchange for {})",
+                    self.audience
+                )
+            })?
+            .json::<super::admin::AuthToken>()
+            .await
+            .map(|t| t.access_token)
+            .context("Could not parse result from Google STS")
+    }
+}
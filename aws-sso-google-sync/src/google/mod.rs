@@ -1,3 +1,11 @@
 mod admin;
+mod federation;
+mod reports;
+mod watch;
 
-pub use admin::{Admin, AdminCreds, Group, User};
+pub use admin::{
+    Admin, AdminCreds, Group, Member, MemberRole, MemberStatus, MemberType, User, UserMail,
+    UserName,
+};
+pub use reports::{Activity, ActivityEvent, Reports};
+pub use watch::{Channel, ChannelKind, Watch};
@@ -0,0 +1,132 @@
+const ENDPOINT: &str = "https://admin.googleapis.com/admin/reports/v1";
+const SCOPES: &str = "https://www.googleapis.com/auth/admin.reports.audit.readonly";
+
+#[derive(Debug, serde::Deserialize)]
+struct Activities {
+    items: Option<Vec<Activity>>,
+    #[serde(rename = "nextPageToken")]
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Activity {
+    #[serde(default)]
+    pub(crate) events: Vec<ActivityEvent>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ActivityEvent {
+    pub(crate) name: String,
+    #[serde(default)]
+    parameters: Vec<ActivityParameter>,
+}
+
+impl ActivityEvent {
+    pub(crate) fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|p| p.name == name)
+            .and_then(|p| p.value.as_deref())
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ActivityParameter {
+    name: String,
+    value: Option<String>,
+}
+
+/// Client for the Google Admin SDK Reports API
+#[derive(Debug)]
+pub struct Reports<'a> {
+    client: reqwest::Client,
+    token: String,
+    customer_id: Option<&'a str>,
+}
+
+impl<'a> Reports<'a> {
+    pub(crate) async fn new(secret: &'a super::AdminCreds) -> anyhow::Result<Reports<'a>> {
+        let client = reqwest::Client::new();
+        let token = super::Admin::fetch_token(secret, SCOPES, &client).await?;
+        Ok(Self {
+            client,
+            token,
+            customer_id: secret.customer_id.as_deref(),
+        })
+    }
+
+    /// Verifies access to the audit log by fetching a single activity
+    pub(crate) async fn check(&self) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let mut queries = vec![("maxResults", "1")];
+        if let Some(customer_id) = self.customer_id {
+            queries.push(("customerId", customer_id));
+        }
+        let _ = self
+            .client
+            .request(
+                reqwest::Method::GET,
+                format!("{ENDPOINT}/activity/users/all/applications/admin"),
+            )
+            .query(&queries)
+            .header("Authorization", format!("Bearer {}", &self.token))
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .context("Unable to send request to Google Reports API")?
+            .error_for_status()
+            .context("Error returned from server")?;
+        Ok(())
+    }
+
+    /// Lists all activities of the `admin` application since `start_time`
+    pub(crate) async fn list_admin_activities(
+        &self,
+        start_time: &chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Vec<Activity>> {
+        use anyhow::Context;
+
+        let mut entries = Vec::new();
+        let mut token = None;
+        loop {
+            let mut queries = vec![(
+                "startTime",
+                start_time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            )];
+            if let Some(customer_id) = self.customer_id {
+                queries.push(("customerId", customer_id.into()));
+            }
+            if let Some(token) = token {
+                queries.push(("pageToken", token));
+            }
+            let res = self
+                .client
+                .request(
+                    reqwest::Method::GET,
+                    format!("{ENDPOINT}/activity/users/all/applications/admin"),
+                )
+                .query(&queries)
+                .header("Authorization", format!("Bearer {}", &self.token))
+                .header("Accept", "application/json")
+                .send()
+                .await
+                .context("Unable to send request to Google Reports API")?
+                .error_for_status()
+                .context("Error returned from server")?
+                .json::<Activities>()
+                .await
+                .context("Could not parse result from Google Reports API")?;
+
+            if let Some(mut new_entries) = res.items {
+                entries.append(&mut new_entries);
+            }
+            if let Some(next_page_token) = res.next_page_token {
+                token = Some(next_page_token);
+                continue;
+            }
+            break;
+        }
+        Ok(entries)
+    }
+}
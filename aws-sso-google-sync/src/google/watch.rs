@@ -0,0 +1,146 @@
+const DIRECTORY_ENDPOINT: &str = "https://admin.googleapis.com/admin/directory/v1";
+const REPORTS_ENDPOINT: &str = "https://admin.googleapis.com/admin/reports/v1";
+const SCOPES: &str = "https://www.googleapis.com/auth/admin.directory.user.readonly https://www.googleapis.com/auth/admin.reports.audit.readonly";
+
+/// Resource which is watched by a channel
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum ChannelKind {
+    /// Changes of users via the Directory API
+    Users,
+    /// Activities of the `admin` application via the Reports API. Includes changes of
+    /// groups and group members
+    AdminActivities,
+}
+
+impl ChannelKind {
+    pub(crate) const ALL: [Self; 2] = [Self::Users, Self::AdminActivities];
+
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            Self::Users => "users",
+            Self::AdminActivities => "admin-activities",
+        }
+    }
+}
+
+/// Push notification channel which was registered with Google
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Channel {
+    pub(crate) kind: ChannelKind,
+    pub(crate) id: String,
+    pub(crate) resource_id: String,
+    pub(crate) expiration: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ChannelResponse {
+    id: String,
+    #[serde(rename = "resourceId")]
+    resource_id: String,
+    expiration: Option<String>,
+}
+
+/// Client to register push notification channels for users and admin activities
+#[derive(Debug)]
+pub struct Watch<'a> {
+    client: reqwest::Client,
+    token: String,
+    customer_id: Option<&'a str>,
+}
+
+impl<'a> Watch<'a> {
+    pub(crate) async fn new(secret: &'a super::AdminCreds) -> anyhow::Result<Watch<'a>> {
+        let client = reqwest::Client::new();
+        let token = super::Admin::fetch_token(secret, SCOPES, &client).await?;
+        Ok(Self {
+            client,
+            token,
+            customer_id: secret.customer_id.as_deref(),
+        })
+    }
+
+    /// Registers a new channel which sends notifications to `address` until `expiration`
+    pub(crate) async fn watch(
+        &self,
+        kind: ChannelKind,
+        id: &str,
+        address: &str,
+        token: &str,
+        expiration: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Channel> {
+        use anyhow::Context;
+        use chrono::TimeZone;
+
+        let (url, query) = match kind {
+            ChannelKind::Users => (
+                format!("{DIRECTORY_ENDPOINT}/users/watch"),
+                ("customer", self.customer_id.unwrap_or("my_customer")),
+            ),
+            ChannelKind::AdminActivities => (
+                format!("{REPORTS_ENDPOINT}/activity/users/all/applications/admin/watch"),
+                ("customerId", self.customer_id.unwrap_or("my_customer")),
+            ),
+        };
+        let res = self
+            .client
+            .request(reqwest::Method::POST, url)
+            .query(&[query])
+            .header("Authorization", format!("Bearer {}", &self.token))
+            .header("Accept", "application/json")
+            .json(&serde_json::json!({
+                "id": id,
+                "type": "web_hook",
+                "address": address,
+                "token": token,
+                "expiration": expiration.timestamp_millis().to_string(),
+            }))
+            .send()
+            .await
+            .context("Unable to send request to Google API")?
+            .error_for_status()
+            .with_context(|| format!("Error returned from server (watch {})", kind.as_str()))?
+            .json::<ChannelResponse>()
+            .await
+            .context("Could not parse result from Google API")?;
+        let expiration = match res.expiration {
+            Some(ref v) => v
+                .parse::<i64>()
+                .ok()
+                .and_then(|v| chrono::Utc.timestamp_millis_opt(v).single())
+                .with_context(|| format!("Invalid channel expiration: {v}"))?,
+            None => expiration,
+        };
+        Ok(Channel {
+            kind,
+            id: res.id,
+            resource_id: res.resource_id,
+            expiration,
+        })
+    }
+
+    /// Stops the channel, so that no more notifications are sent
+    pub(crate) async fn stop(&self, channel: &Channel) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let url = match channel.kind {
+            ChannelKind::Users => "https://admin.googleapis.com/admin/directory_v1/channels/stop",
+            ChannelKind::AdminActivities => {
+                "https://admin.googleapis.com/admin/reports_v1/channels/stop"
+            }
+        };
+        let _ = self
+            .client
+            .request(reqwest::Method::POST, url)
+            .header("Authorization", format!("Bearer {}", &self.token))
+            .json(&serde_json::json!({
+                "id": channel.id,
+                "resourceId": channel.resource_id,
+            }))
+            .send()
+            .await
+            .context("Unable to send request to Google API")?
+            .error_for_status()
+            .with_context(|| format!("Error returned from server (stop channel {})", channel.id))?;
+        Ok(())
+    }
+}
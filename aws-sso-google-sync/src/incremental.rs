@@ -0,0 +1,166 @@
+/// State of the incremental sync which is stored in SSM between invocations
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Checkpoint {
+    pub(crate) last_sync: chrono::DateTime<chrono::Utc>,
+    pub(crate) last_full_sync: chrono::DateTime<chrono::Utc>,
+}
+
+impl Checkpoint {
+    /// Loads the checkpoint. An invalid checkpoint is ignored, which results in a full sync
+    pub(crate) async fn load(
+        config: &crate::event::IncrementalSync,
+    ) -> anyhow::Result<Option<Self>> {
+        let ssm = crate::aws::Ssm::new(&config.checkpoint.region)?;
+        let value = match ssm.get_parameter(&config.checkpoint.name).await? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        match serde_json::from_str(&value) {
+            Ok(checkpoint) => Ok(Some(checkpoint)),
+            Err(err) => {
+                log::warn!(
+                    "Ignoring invalid checkpoint in {}: {}",
+                    config.checkpoint.name,
+                    err
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    pub(crate) async fn save(&self, config: &crate::event::IncrementalSync) -> anyhow::Result<()> {
+        let ssm = crate::aws::Ssm::new(&config.checkpoint.region)?;
+        ssm.put_parameter(&config.checkpoint.name, &serde_json::to_string(self)?)
+            .await
+    }
+
+    fn is_full_sync_due(
+        &self,
+        config: &crate::event::IncrementalSync,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> bool {
+        now - self.last_full_sync >= config.full_sync_interval()
+    }
+}
+
+/// Returns the users and groups which changed since the last sync according to the
+/// audit log of all tenants or `None` if a full sync is required
+pub async fn get_scope(
+    config: &crate::event::IncrementalSync,
+    checkpoint: Option<&Checkpoint>,
+    directory_creds: &[crate::directory::DirectoryCreds],
+    now: chrono::DateTime<chrono::Utc>,
+) -> anyhow::Result<Option<crate::scoped::Scope>> {
+    let checkpoint = match checkpoint {
+        Some(c) if !c.is_full_sync_due(config, now) => c,
+        Some(_) => {
+            log::info!("Full sync is due");
+            return Ok(None);
+        }
+        None => {
+            log::info!("No checkpoint found. Running full sync");
+            return Ok(None);
+        }
+    };
+    let start_time = checkpoint.last_sync - config.overlap();
+
+    let mut scope = crate::scoped::Scope::default();
+    for creds in directory_creds {
+        let creds = match *creds {
+            crate::directory::DirectoryCreds::Google(ref creds) => creds,
+            crate::directory::DirectoryCreds::Ldap(_) => {
+                log::info!("Incremental sync is not supported for ldap tenants. Running full sync");
+                return Ok(None);
+            }
+        };
+        let reports = crate::google::Reports::new(creds).await?;
+        for activity in reports.list_admin_activities(&start_time).await? {
+            for event in &activity.events {
+                if !add_event(&mut scope, event) {
+                    log::info!("Event {} requires a full sync", event.name);
+                    return Ok(None);
+                }
+            }
+        }
+    }
+    Ok(Some(scope))
+}
+
+/// Adds the users and groups affected by the event to the scope. Returns `false`
+/// if the event cannot be applied incrementally
+pub fn add_event(scope: &mut crate::scoped::Scope, event: &crate::google::ActivityEvent) -> bool {
+    match event.name.as_str() {
+        "CREATE_USER" | "DELETE_USER" | "UNDELETE_USER" | "SUSPEND_USER" | "UNSUSPEND_USER"
+        | "CHANGE_FIRST_NAME" | "CHANGE_LAST_NAME" => match event.parameter("USER_EMAIL") {
+            Some(user) => scope.add_user(user),
+            None => return false,
+        },
+        "CREATE_GROUP" | "DELETE_GROUP" => match event.parameter("GROUP_EMAIL") {
+            Some(group) => scope.add_group(group),
+            None => return false,
+        },
+        "ADD_GROUP_MEMBER" | "REMOVE_GROUP_MEMBER" | "UPDATE_GROUP_MEMBER" => {
+            match (
+                event.parameter("GROUP_EMAIL"),
+                event.parameter("USER_EMAIL"),
+            ) {
+                (Some(group), Some(user)) => scope.add_member(group, user),
+                _ => return false,
+            }
+        }
+        // The org unit decides whether the user is synced by `sync_strategie_overrides`
+        "MOVE_USER_TO_ORG_UNIT" => match event.parameter("USER_EMAIL") {
+            Some(user) => scope.add_user(user),
+            None => return false,
+        },
+        // Aliases, external ids and custom schemas may be the source of the user name,
+        // which is not matched incrementally. Changes of org units affect all their users
+        "RENAME_USER"
+        | "CHANGE_GROUP_EMAIL"
+        | "ADD_NICKNAME"
+        | "REMOVE_NICKNAME"
+        | "CHANGE_USER_EXTERNAL_ID"
+        | "CHANGE_USER_CUSTOM_FIELD"
+        | "MOVE_ORG_UNIT"
+        | "EDIT_ORG_UNIT_NAME"
+        | "REMOVE_ORG_UNIT" => return false,
+        _ => {}
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    fn event(name: &str, parameters: &[(&str, &str)]) -> crate::google::ActivityEvent {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "parameters": parameters
+                .iter()
+                .map(|(name, value)| serde_json::json!({ "name": name, "value": value }))
+                .collect::<Vec<_>>(),
+        }))
+        .expect("Invalid event")
+    }
+
+    #[test]
+    fn move_to_org_unit_marks_user_as_changed() {
+        let mut scope = crate::scoped::Scope::default();
+        assert!(super::add_event(
+            &mut scope,
+            &event(
+                "MOVE_USER_TO_ORG_UNIT",
+                &[("USER_EMAIL", "User@example.org"), ("NEW_VALUE", "/aws")]
+            )
+        ));
+        assert!(scope.users.contains("User@example.org"));
+    }
+
+    #[test]
+    fn alias_change_requires_full_sync() {
+        let mut scope = crate::scoped::Scope::default();
+        assert!(!super::add_event(
+            &mut scope,
+            &event("ADD_NICKNAME", &[("USER_EMAIL", "user@example.org")])
+        ));
+    }
+}
@@ -0,0 +1,621 @@
+const DEFAULT_PAGE_SIZE: i32 = 500;
+const DEFAULT_USER_FILTER: &str = "(objectClass=person)";
+const DEFAULT_GROUP_FILTER: &str =
+    "(|(objectClass=groupOfNames)(objectClass=groupOfUniqueNames)(objectClass=group))";
+
+#[derive(Debug, Copy, Clone, Default, serde::Deserialize)]
+pub enum Membership {
+    /// Groups list their members in the `member` attribute (e.g. OpenLDAP)
+    #[default]
+    Member,
+    /// Users and groups list their groups in the `memberOf` attribute (e.g. Active Directory)
+    MemberOf,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(default)]
+pub struct LdapAttributes {
+    mail: String,
+    given_name: String,
+    family_name: String,
+    display_name: String,
+    group_name: String,
+    member: String,
+    member_of: String,
+}
+
+impl Default for LdapAttributes {
+    fn default() -> Self {
+        Self {
+            mail: "mail".into(),
+            given_name: "givenName".into(),
+            family_name: "sn".into(),
+            display_name: "displayName".into(),
+            group_name: "cn".into(),
+            member: "member".into(),
+            member_of: "memberOf".into(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct LdapCreds {
+    url: String,
+    #[serde(default)]
+    starttls: bool,
+    bind_dn: String,
+    bind_password: String,
+    user_base_dns: Vec<String>,
+    group_base_dns: Vec<String>,
+    user_filter: Option<String>,
+    group_filter: Option<String>,
+    disabled_user_filter: Option<String>,
+    #[serde(default)]
+    membership: Membership,
+    #[serde(default)]
+    attributes: LdapAttributes,
+    #[serde(default)]
+    domains: Vec<String>,
+    page_size: Option<i32>,
+}
+
+impl LdapCreds {
+    fn user_filter(&self) -> &str {
+        self.user_filter.as_deref().unwrap_or(DEFAULT_USER_FILTER)
+    }
+
+    fn group_filter(&self) -> &str {
+        self.group_filter.as_deref().unwrap_or(DEFAULT_GROUP_FILTER)
+    }
+
+    fn page_size(&self) -> i32 {
+        self.page_size.unwrap_or(DEFAULT_PAGE_SIZE)
+    }
+}
+
+#[derive(Debug)]
+struct DirectoryUser {
+    dn: String,
+    email: String,
+    suspended: bool,
+}
+
+/// Users, groups and memberships of the directory. Keys are lowercase DNs
+#[derive(Debug)]
+struct Directory {
+    users: crate::sync::Lookup<DirectoryUser>,
+    groups: std::collections::HashSet<String>,
+    members: crate::sync::Lookup<Vec<String>>,
+    mails: std::collections::HashSet<String>,
+}
+
+pub struct Ldap<'a> {
+    creds: &'a LdapCreds,
+    ldap: ldap3::Ldap,
+    /// Lowercase DNs of all disabled users, which are searched once per sync
+    disabled: tokio::sync::OnceCell<std::collections::HashSet<String>>,
+    directory: tokio::sync::OnceCell<Directory>,
+}
+
+impl std::fmt::Debug for Ldap<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ldap")
+            .field("creds", &self.creds.url)
+            .field("ldap", &"[...]")
+            .field("disabled", &self.disabled)
+            .field("directory", &self.directory)
+            .finish()
+    }
+}
+
+impl<'a> Ldap<'a> {
+    pub(crate) async fn new(creds: &'a LdapCreds) -> anyhow::Result<Ldap<'a>> {
+        use anyhow::Context;
+
+        let settings = ldap3::LdapConnSettings::new().set_starttls(creds.starttls);
+        let (conn, mut ldap) = ldap3::LdapConnAsync::with_settings(settings, &creds.url)
+            .await
+            .with_context(|| format!("Unable to connect to LDAP server {}", creds.url))?;
+        ldap3::drive!(conn);
+        let _ = ldap
+            .simple_bind(&creds.bind_dn, &creds.bind_password)
+            .await
+            .context("Unable to send bind request to LDAP server")?
+            .success()
+            .with_context(|| format!("Unable to bind to LDAP server as {}", creds.bind_dn))?;
+        Ok(Self {
+            creds,
+            ldap,
+            disabled: tokio::sync::OnceCell::new(),
+            directory: tokio::sync::OnceCell::new(),
+        })
+    }
+
+    async fn search(
+        &self,
+        base_dns: &[String],
+        filter: &str,
+        attrs: Vec<&str>,
+    ) -> anyhow::Result<Vec<ldap3::SearchEntry>> {
+        use anyhow::Context;
+        use ldap3::adapters::{Adapter, EntriesOnly, PagedResults};
+
+        let mut ldap = self.ldap.clone();
+        let mut entries = Vec::new();
+        for base_dn in base_dns {
+            let adapters: Vec<Box<dyn Adapter<'_, _, _>>> = vec![
+                Box::new(EntriesOnly::new()),
+                Box::new(PagedResults::new(self.creds.page_size())),
+            ];
+            let mut search = ldap
+                .streaming_search_with(
+                    adapters,
+                    base_dn,
+                    ldap3::Scope::Subtree,
+                    filter,
+                    attrs.clone(),
+                )
+                .await
+                .with_context(|| format!("Unable to search LDAP server in {base_dn}"))?;
+            while let Some(entry) = search
+                .next()
+                .await
+                .with_context(|| format!("Unable to read search result from {base_dn}"))?
+            {
+                entries.push(ldap3::SearchEntry::construct(entry));
+            }
+            let _ = search
+                .finish()
+                .await
+                .success()
+                .with_context(|| format!("Error returned from LDAP server for {base_dn}"))?;
+        }
+        Ok(entries)
+    }
+
+    fn combine_filter(filter: &str, query: Option<&str>) -> String {
+        query.map_or_else(|| filter.into(), |query| format!("(&{filter}{query})"))
+    }
+
+    async fn search_users(&self, query: Option<&str>) -> anyhow::Result<Vec<ldap3::SearchEntry>> {
+        let attributes = &self.creds.attributes;
+        self.search(
+            &self.creds.user_base_dns,
+            &Self::combine_filter(self.creds.user_filter(), query),
+            vec![
+                &attributes.mail,
+                &attributes.given_name,
+                &attributes.family_name,
+                &attributes.display_name,
+                &attributes.member_of,
+            ],
+        )
+        .await
+    }
+
+    async fn search_groups(&self, query: Option<&str>) -> anyhow::Result<Vec<ldap3::SearchEntry>> {
+        let attributes = &self.creds.attributes;
+        self.search(
+            &self.creds.group_base_dns,
+            &Self::combine_filter(self.creds.group_filter(), query),
+            vec![
+                &attributes.group_name,
+                &attributes.member,
+                &attributes.member_of,
+            ],
+        )
+        .await
+    }
+
+    /// Returns the lowercase DNs of all disabled users. They are only searched once
+    async fn get_disabled_users(&self) -> anyhow::Result<&std::collections::HashSet<String>> {
+        self.disabled
+            .get_or_try_init(|| async {
+                let disabled_user_filter = match self.creds.disabled_user_filter {
+                    Some(ref v) => v,
+                    None => return Ok(std::collections::HashSet::new()),
+                };
+                Ok(self
+                    .search(
+                        &self.creds.user_base_dns,
+                        &Self::combine_filter(self.creds.user_filter(), Some(disabled_user_filter)),
+                        vec!["1.1"],
+                    )
+                    .await?
+                    .into_iter()
+                    .map(|e| e.dn.to_lowercase())
+                    .collect())
+            })
+            .await
+    }
+
+    fn first_attr<'e>(entry: &'e ldap3::SearchEntry, name: &str) -> Option<&'e str> {
+        entry
+            .attrs
+            .get(name)
+            .and_then(|v| v.first())
+            .map(String::as_str)
+    }
+
+    fn to_user(
+        &self,
+        entry: &ldap3::SearchEntry,
+        disabled: &std::collections::HashSet<String>,
+    ) -> Option<crate::google::User> {
+        let attributes = &self.creds.attributes;
+        let mails = match entry.attrs.get(&attributes.mail) {
+            Some(v) if !v.is_empty() => v,
+            _ => {
+                log::debug!("Skipping LDAP user without mail: {}", entry.dn);
+                return None;
+            }
+        };
+        let given_name = Self::first_attr(entry, &attributes.given_name).unwrap_or_default();
+        let family_name = Self::first_attr(entry, &attributes.family_name).unwrap_or_default();
+        let full_name = Self::first_attr(entry, &attributes.display_name).map_or_else(
+            || format!("{given_name} {family_name}").trim().to_owned(),
+            String::from,
+        );
+        Some(crate::google::User {
+            id: entry.dn.clone(),
+            primary_email: mails[0].clone(),
+            suspended: Some(disabled.contains(&entry.dn.to_lowercase())),
+            name: crate::google::UserName {
+                full_name: Some(full_name),
+                family_name: Some(family_name.into()),
+                given_name: Some(given_name.into()),
+            },
+            emails: mails
+                .iter()
+                .enumerate()
+                .map(|(i, address)| crate::google::UserMail {
+                    address: address.clone(),
+                    primary: Some(i == 0),
+                    r#type: None,
+                })
+                .collect(),
+            thumbnail_photo_url: None,
+            aliases: mails.iter().skip(1).cloned().collect(),
+            external_ids: Vec::new(),
+            relations: Vec::new(),
+            custom_schemas: std::collections::HashMap::new(),
+            org_unit_path: None,
+        })
+    }
+
+    /// Fetches all users and groups once to resolve group memberships
+    async fn get_directory(&self) -> anyhow::Result<&Directory> {
+        self.directory
+            .get_or_try_init(|| async {
+                let attributes = &self.creds.attributes;
+                let disabled = self.get_disabled_users().await?;
+                let user_entries = self.search_users(None).await?;
+                let group_entries = self.search_groups(None).await?;
+
+                let mut users = crate::sync::Lookup::new();
+                let mut mails = std::collections::HashSet::new();
+                for entry in &user_entries {
+                    if let Some(user) = self.to_user(entry, disabled) {
+                        mails.extend(user.emails.iter().map(|m| m.address.to_lowercase()));
+                        let _ = users.insert(
+                            entry.dn.to_lowercase(),
+                            DirectoryUser {
+                                dn: user.id,
+                                email: user.primary_email,
+                                suspended: user.suspended.unwrap_or(false),
+                            },
+                        );
+                    }
+                }
+                let groups = group_entries.iter().map(|e| e.dn.to_lowercase()).collect();
+                let mut members = crate::sync::Lookup::<Vec<String>>::new();
+                match self.creds.membership {
+                    Membership::Member => {
+                        for entry in &group_entries {
+                            let _ = members.insert(
+                                entry.dn.to_lowercase(),
+                                entry
+                                    .attrs
+                                    .get(&attributes.member)
+                                    .into_iter()
+                                    .flatten()
+                                    .map(|m| m.to_lowercase())
+                                    .collect(),
+                            );
+                        }
+                    }
+                    Membership::MemberOf => {
+                        for entry in user_entries.iter().chain(&group_entries) {
+                            for group in
+                                entry.attrs.get(&attributes.member_of).into_iter().flatten()
+                            {
+                                members
+                                    .entry(group.to_lowercase())
+                                    .or_default()
+                                    .push(entry.dn.to_lowercase());
+                            }
+                        }
+                    }
+                }
+                Ok(Directory {
+                    users,
+                    groups,
+                    members,
+                    mails,
+                })
+            })
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::directory::DirectorySource for Ldap<'_> {
+    async fn list_users(&self, query: Option<&str>) -> anyhow::Result<Vec<crate::google::User>> {
+        let disabled = self.get_disabled_users().await?;
+        Ok(self
+            .search_users(query)
+            .await?
+            .iter()
+            .filter_map(|e| self.to_user(e, disabled))
+            .collect())
+    }
+
+    async fn list_deleted_users(&self) -> anyhow::Result<Vec<crate::google::User>> {
+        Ok(Vec::new())
+    }
+
+    async fn get_user(&self, email: &str) -> anyhow::Result<Option<crate::google::User>> {
+        let query = format!(
+            "({}={})",
+            self.creds.attributes.mail,
+            ldap3::ldap_escape(email)
+        );
+        Ok(self
+            .list_users(Some(&query))
+            .await?
+            .into_iter()
+            .find(|u| u.primary_email.eq_ignore_ascii_case(email)))
+    }
+
+    async fn get_group(&self, email: &str) -> anyhow::Result<Option<crate::google::Group>> {
+        let query = format!(
+            "({}={})",
+            self.creds.attributes.group_name,
+            ldap3::ldap_escape(email)
+        );
+        Ok(self.list_groups(Some(&query)).await?.into_iter().next())
+    }
+
+    async fn list_groups(&self, query: Option<&str>) -> anyhow::Result<Vec<crate::google::Group>> {
+        Ok(self
+            .search_groups(query)
+            .await?
+            .iter()
+            .filter_map(|e| {
+                let name = Self::first_attr(e, &self.creds.attributes.group_name);
+                if name.is_none() {
+                    log::debug!("Skipping LDAP group without name: {}", e.dn);
+                }
+                Some(crate::google::Group {
+                    id: e.dn.clone(),
+                    email: name?.into(),
+                })
+            })
+            .collect())
+    }
+
+    async fn list_group_members(
+        &self,
+        group_id: &str,
+        options: &crate::event::GroupMemberOptions,
+    ) -> anyhow::Result<Vec<crate::google::Member>> {
+        let directory = self.get_directory().await?;
+        let mut entries = Vec::new();
+        let mut visited = std::collections::HashSet::new();
+        let mut pending = vec![group_id.to_lowercase()];
+        while let Some(group) = pending.pop() {
+            if !visited.insert(group.clone()) {
+                continue;
+            }
+            for member in directory.members.get(&group).into_iter().flatten() {
+                if let Some(user) = directory.users.get(member) {
+                    if visited.insert(member.clone()) {
+                        entries.push(crate::google::Member {
+                            id: Some(user.dn.clone()),
+                            email: user.email.clone(),
+                            r#type: crate::google::MemberType::User,
+                            role: None,
+                            status: user
+                                .suspended
+                                .then_some(crate::google::MemberStatus::Suspended),
+                        });
+                    }
+                } else if directory.groups.contains(member) && options.include_derived_membership()
+                {
+                    pending.push(member.clone());
+                }
+            }
+        }
+        entries.retain(|e| {
+            options.is_role_included(e.role)
+                && (!options.exclude_suspended()
+                    || e.status != Some(crate::google::MemberStatus::Suspended))
+        });
+        Ok(entries)
+    }
+
+    fn is_internal(&self, mail: &str) -> bool {
+        if !self.creds.domains.is_empty() {
+            return mail.rsplit_once('@').is_some_and(|(_, domain)| {
+                self.creds
+                    .domains
+                    .iter()
+                    .any(|d| domain.eq_ignore_ascii_case(d))
+            });
+        }
+        self.directory
+            .get()
+            .is_some_and(|d| d.mails.contains(&mail.to_lowercase()))
+    }
+}
+
+/// Tests against an OpenLDAP container which is seeded with `tests/ldap/seed.ldif`:
+/// `docker run --rm -p 389:389 -v "$PWD/tests/ldap:/container/service/slapd/assets/config/bootstrap/ldif/custom" osixia/openldap:1.5.0 --copy-service`
+/// They are run with `cargo test -- --ignored ldap`. The url can be overwritten with
+/// the environment variable `LDAP_TEST_URL`
+#[cfg(test)]
+mod tests {
+    use crate::directory::DirectorySource;
+
+    fn creds(overrides: serde_json::Value) -> super::LdapCreds {
+        let mut creds = serde_json::json!({
+            "url": std::env::var("LDAP_TEST_URL").unwrap_or_else(|_| "ldap://localhost:389".into()),
+            "bind_dn": "cn=admin,dc=example,dc=org",
+            "bind_password": "admin",
+            "user_base_dns": ["ou=people,dc=example,dc=org"],
+            "group_base_dns": ["ou=groups,dc=example,dc=org"],
+            "user_filter": "(objectClass=inetOrgPerson)",
+            "group_filter": "(objectClass=groupOfNames)",
+            "disabled_user_filter": "(employeeType=disabled)",
+        });
+        if let (Some(creds), Some(overrides)) = (creds.as_object_mut(), overrides.as_object()) {
+            creds.extend(overrides.clone());
+        }
+        serde_json::from_value(creds).expect("Invalid LDAP creds")
+    }
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Unable to create runtime")
+            .block_on(future)
+    }
+
+    async fn members(
+        ldap: &super::Ldap<'_>,
+        group: &str,
+        options: serde_json::Value,
+    ) -> Vec<String> {
+        let group = ldap
+            .get_group(group)
+            .await
+            .expect("Unable to get group")
+            .expect("Group does not exist");
+        let options = serde_json::from_value(options).expect("Invalid group member options");
+        let mut emails = ldap
+            .list_group_members(&group.id, &options)
+            .await
+            .expect("Unable to list group members")
+            .into_iter()
+            .map(|m| m.email)
+            .collect::<Vec<_>>();
+        emails.sort();
+        emails
+    }
+
+    #[test]
+    #[ignore = "requires an OpenLDAP container"]
+    fn ldap_member_resolves_nested_groups() {
+        run(async {
+            let creds = creds(serde_json::json!({}));
+            let ldap = super::Ldap::new(&creds).await.expect("Unable to connect");
+            assert_eq!(
+                members(&ldap, "aws-admins", serde_json::json!({})).await,
+                ["alice@example.org", "bob@example.org", "erin@example.org"]
+            );
+            assert_eq!(
+                members(
+                    &ldap,
+                    "aws-admins",
+                    serde_json::json!({ "include_derived_membership": false })
+                )
+                .await,
+                ["alice@example.org", "erin@example.org"]
+            );
+        });
+    }
+
+    #[test]
+    #[ignore = "requires an OpenLDAP container"]
+    fn ldap_member_of_resolves_nested_groups() {
+        run(async {
+            let creds = creds(serde_json::json!({
+                "group_filter": "(objectClass=groupOfUniqueNames)",
+                "membership": "MemberOf",
+            }));
+            let ldap = super::Ldap::new(&creds).await.expect("Unable to connect");
+            assert_eq!(
+                members(&ldap, "aws-unique", serde_json::json!({})).await,
+                ["carol@example.org", "dave@example.org"]
+            );
+            assert_eq!(
+                members(
+                    &ldap,
+                    "aws-unique",
+                    serde_json::json!({ "include_derived_membership": false })
+                )
+                .await,
+                ["carol@example.org"]
+            );
+        });
+    }
+
+    #[test]
+    #[ignore = "requires an OpenLDAP container"]
+    fn ldap_disabled_users_are_suspended() {
+        run(async {
+            let creds = creds(serde_json::json!({}));
+            let ldap = super::Ldap::new(&creds).await.expect("Unable to connect");
+            let erin = ldap
+                .get_user("erin@example.org")
+                .await
+                .expect("Unable to get user")
+                .expect("User does not exist");
+            assert_eq!(erin.suspended, Some(true));
+            let alice = ldap
+                .get_user("alice@example.org")
+                .await
+                .expect("Unable to get user")
+                .expect("User does not exist");
+            assert_eq!(alice.suspended, Some(false));
+            assert_eq!(
+                members(
+                    &ldap,
+                    "aws-admins",
+                    serde_json::json!({ "exclude_suspended": true })
+                )
+                .await,
+                ["alice@example.org", "bob@example.org"]
+            );
+        });
+    }
+
+    #[test]
+    #[ignore = "requires an OpenLDAP container"]
+    fn ldap_lists_all_pages() {
+        run(async {
+            let creds = creds(serde_json::json!({ "page_size": 2 }));
+            let ldap = super::Ldap::new(&creds).await.expect("Unable to connect");
+            let mut emails = ldap
+                .list_users(None)
+                .await
+                .expect("Unable to list users")
+                .into_iter()
+                .map(|u| u.primary_email)
+                .collect::<Vec<_>>();
+            emails.sort();
+            // frank has no mail and is skipped
+            assert_eq!(
+                emails,
+                [
+                    "alice@example.org",
+                    "bob@example.org",
+                    "carol@example.org",
+                    "dave@example.org",
+                    "erin@example.org"
+                ]
+            );
+        });
+    }
+}
@@ -14,6 +14,23 @@
 //! group membership will not be removed, as it is not possible to fetch all groups for
 //! a User in AWS SCIM
 //!
+//! These limitations do not apply if the IAM Identity Store API is used instead of SCIM
+//! (see `identity_store` below), as it supports listing all users, groups and group
+//! memberships.
+//!
+//! Google users with an incomplete profile (e.g. service accounts without a family name)
+//! get the missing names derived from their full name or email. Users which still can not
+//! be created in AWS SSO are skipped and listed in `skipped_users` of the target in the
+//! lambda result.
+//!
+//! The primary email of a user is synced. As AWS SSO accepts only one email per user, all
+//! other emails of the user are only synced to SCIM service providers with
+//! `multiple_emails` in their profile, with the Google types `work`, `home` and `other`
+//! (`custom` is synced as `other`). Emails are only set when the user is created, changes
+//! of the emails in Google are not synced to existing users. The `manager` relation is synced as the manager of
+//! the SCIM enterprise user extension, if the manager is synced as well. Managers are not
+//! supported by the IAM Identity Store API.
+//!
 //! # Recommendations
 //!
 //! To combat these limitations and to get the best performance, adhere to the following
@@ -26,6 +43,8 @@
 //! `include_users_regexes`.
 //! * Only sync users which are members of a group that is synced to AWS by using
 //! the sync strategie `GroupMembersOnly`.
+//! * Use `identity_store` instead of `security_hub_scim_creds` if the lambda runs in the
+//!   account of the AWS SSO instance.
 //!
 //! # Setup
 //!
@@ -38,43 +57,400 @@
 //!   * https://www.googleapis.com/auth/admin.directory.group.readonly
 //!   * https://www.googleapis.com/auth/admin.directory.group.member.readonly
 //!   * https://www.googleapis.com/auth/admin.directory.user.readonly
+//!   * https://www.googleapis.com/auth/admin.reports.audit.readonly (only required for
+//!     `incremental`)
+//!   * https://www.googleapis.com/auth/admin.directory.domain.readonly (only required for
+//!     `customer_id` without `domains`)
 //! * Enable Provisining in the AWS SSO Console <br>
 //! (Keep Token and SCIM endpoint which are required at a later stage)
 //! * Create a Secret in AWS Secret Manager with the following content:
 //! ```json
 //! {
 //!   "endpoint": "<scim_endpoint>",
-//!   "access_token": "<token>"
+//!   "access_token": "<token>",
+//!   // Optional, remove if not required. Example: `2024-01-31`
+//!   // Date on which the access token was created. AWS SSO tokens expire after one year
+//!   "token_created": "<date>",
+//!   // Optional, remove if not required. Takes precedence over token_created
+//!   "token_expires": "<date>",
+//!   // Optional, remove if not required. Default: 30
+//!   // If the expiry date is known, the days until the token expires are emitted as the
+//!   // CloudWatch metric `ScimTokenDaysUntilExpiry` (namespace `aws-sso-google-sync`) and
+//!   // a warning is logged within this number of days before it expires. If the token is
+//!   // rejected, the metric `ScimTokenRejected` is emitted
+//!   "token_expiry_warning_days": 30,
+//!   // Optional, remove if not required. Defaults to the capabilities of AWS SSO.
+//!   // Allows to sync to other SCIM 2.0 service providers
+//!   "profile": {
+//!     // Filter operators supported on GET /Users and GET /Groups. Default: ["eq", "and"]
+//!     // Without eq, users and groups are looked up by listing all of them. Without
+//!     // eq and and, members_on_get_groups is required
+//!     "filter_operators": ["eq", "and"],
+//!     // Whether lists are paged with startIndex and count. Default: false
+//!     // If true, all users and groups are listed and the limitations below do not apply
+//!     "pagination": false,
+//!     // Number of resources per page. Default: 100
+//!     "page_size": 100,
+//!     // Members | MemberFilter. Default: Members
+//!     // Members: Removes members with path `members` and the member as value
+//!     // MemberFilter: Removes members with path `members[value eq "<id>"]`
+//!     "patch_path_style": "Members",
+//!     // Whether GET /Groups/<id> returns the members of the group. Default: false
+//!     "members_on_get_groups": false,
+//!     // Whether membership changes are sent via POST /Bulk. Default: false
+//!     "bulk": false,
+//!     // Maximum number of operations per bulk request. Default: 100
+//!     "bulk_max_operations": 100,
+//!     // Whether users can have more than one email. Default: false
+//!     // If true, all emails of the google user are synced instead of only the primary one
+//!     "multiple_emails": false
+//!   }
 //! }
 //! ```
 //! * Create another Secret in AWS Secret Manager with the following content
 //! ```json
 //! {
 //!   "mail": "<mail of a google admin user>",
-//!   "credential_json": <credentials.json either as String or Object>
+//!   "credential_json": <credentials.json either as String or Object>,
+//!   // Alternatively to credential_json. Uses the Google Workload Identity Federation
+//!   // instead of a service account key. The AWS credentials of the lambda are exchanged
+//!   // for a Google token, which is used to sign the domain-wide delegation jwt with the
+//!   // service account. Requires a workload identity pool with an AWS provider for the
+//!   // account of the lambda and the role `roles/iam.serviceAccountTokenCreator` on the
+//!   // service account for the principal of the lambda role
+//!   "workload_identity": {
+//!     "audience": "//iam.googleapis.com/projects/<project_number>/locations/global/workloadIdentityPools/<pool_id>/providers/<provider_id>",
+//!     "service_account": "<service_account_email>",
+//!     // Optional, remove if not required. Region of the AWS STS endpoint.
+//!     // Default: region of the lambda
+//!     "region": "<region>"
+//!   },
+//!   // Optional, remove if not required.
+//!   // Lists users and groups of all domains of the customer instead of only the
+//!   // domain of the admin user. Group members of all domains of the customer are not
+//!   // treated as external members
+//!   "customer_id": "<google customer id or my_customer>",
+//!   // Optional, remove if not required. Takes precedence over customer_id
+//!   // Lists users and groups of the given domains. Group members of these domains
+//!   // are not treated as external members
+//!   "domains": ["<domain>", "<secondary domain>"]
+//! }
+//! ```
+//! * Alternatively, to sync from LDAP or Active Directory instead of Google Workspace,
+//!   create a Secret in AWS Secret Manager with the following content. For local testing,
+//!   an OpenLDAP container seeded with the test data of this repository can be used with
+//!   the url `ldap://localhost:389`, admin `cn=admin,dc=example,dc=org` and password `admin`:
+//!   `docker run --rm -p 389:389 -v "$PWD/tests/ldap:/container/service/slapd/assets/config/bootstrap/ldif/custom" osixia/openldap:1.5.0 --copy-service`.
+//!   The LDAP tests are run against it with `cargo test -- --ignored ldap`
+//! ```json
+//! {
+//!   "url": "ldaps://<ldap server>:636",
+//!   // Optional, remove if not required. Default: false
+//!   // Upgrades an ldap:// connection with StartTLS
+//!   "starttls": false,
+//!   "bind_dn": "cn=<user>,dc=example,dc=org",
+//!   "bind_password": "<password>",
+//!   "user_base_dns": ["ou=people,dc=example,dc=org"],
+//!   "group_base_dns": ["ou=groups,dc=example,dc=org"],
+//!   // Optional, remove if not required. Default: `(objectClass=person)`
+//!   "user_filter": "(objectClass=inetOrgPerson)",
+//!   // Optional, remove if not required.
+//!   // Default: `(|(objectClass=groupOfNames)(objectClass=groupOfUniqueNames)(objectClass=group))`
+//!   "group_filter": "(objectClass=groupOfNames)",
+//!   // Optional, remove if not required. Example for Active Directory:
+//!   // `(userAccountControl:1.2.840.113556.1.4.803:=2)`
+//!   // Users matching the filter are treated as suspended
+//!   "disabled_user_filter": "",
+//!   // Optional, remove if not required. Member (default) | MemberOf
+//!   // Member: Group members are read from the member attribute of the groups (OpenLDAP)
+//!   // MemberOf: Group members are read from the memberOf attribute of users and groups
+//!   // (Active Directory)
+//!   "membership": "Member",
+//!   // Optional, remove if not required. Names of the attributes which are read.
+//!   // The name of a group is used like the email of a google group
+//!   "attributes": {
+//!     "mail": "mail",
+//!     "given_name": "givenName",
+//!     "family_name": "sn",
+//!     "display_name": "displayName",
+//!     "group_name": "cn",
+//!     "member": "member",
+//!     "member_of": "memberOf"
+//!   },
+//!   // Optional, remove if not required. Group members of these domains are not
+//!   // treated as external members. Default: All users of the directory
+//!   "domains": ["<domain>"],
+//!   // Optional, remove if not required. Default: 500
+//!   // Number of entries which are requested per page of a search
+//!   "page_size": 500
 //! }
 //! ```
 //! * Create a lambda with the binary from this repository using runtime `provided.al2`
 //! and anything as handler. (More Infos about paramters below)
 //! * Create a CloudWatch Event to trigger the lambda regularly
+//! * Optionally, to sync changes shortly after they happen in Google Workspace, create a
+//!   Function URL (or an API Gateway) for the lambda and configure `watch_channels`.
+//!   Create another CloudWatch Event which triggers the lambda every hour with the event
+//!   `{"mode": "RenewWatchChannels"}` to register the push notification channels and to
+//!   renew them before they expire. Notifications of users and of the admin audit log
+//!   trigger a targeted sync of the affected users and groups. Notifications with an
+//!   invalid channel token are answered with 401. The parameters have to be defined via
+//!   environment variables, as the notification replaces the event
 //!
 //! # Parameters
 //!
 //! The lambda function requires a few parameters to correctly work. You can define
 //! them either with the Event that is send to the lambda, or via environment variables.
+//! Alternatively, they can be kept in a config document in S3, the SSM Parameter Store
+//! or the Secrets Manager, which is referenced by `config`. The document contains the
+//! same fields as the event, written as JSON, YAML or TOML. Fields of the event and
+//! environment variables take precedence over the document. Invalid values are reported
+//! together with the path of the offending field (e.g. `group_member_options.roles[0]`)
+//!
+//! All secrets (`region` and `id`) are read from the Secrets Manager by default. With
+//! `"source": "Ssm"` the id is the name of a SecureString parameter in the SSM Parameter
+//! Store, which requires the permission `ssm:GetParameter` and `kms:Decrypt` for its key.
+//! With `"source": "File"` the id is the path of a local file and `region` can be omitted,
+//! which is meant for development only
 //!
 //! ## Event
 //!
 //! ```json
 //! {
+//!     // Optional, remove if not required.
+//!     // Config document which provides all other fields. Exactly one of s3, ssm and
+//!     // secrets_manager has to be set. Requires the permission `s3:GetObject`,
+//!     // `ssm:GetParameter` or `secretsmanager:GetSecretValue` respectively
+//!     "config": {
+//!         "s3": {
+//!             "region": "<region_of_bucket>",
+//!             "bucket": "<bucket_name>",
+//!             "key": "<object_key>"
+//!         },
+//!         // "ssm": {"region": "<region_of_parameter>", "name": "<ssm_parameter_name>"},
+//!         // "secrets_manager": {"region": "<region_of_secret>", "id": "<secret_name>"},
+//!         // Optional, remove if not required. Json | Yaml | Toml
+//!         // Default: derived from the extension of the key, name or id, otherwise Json
+//!         "format": "Yaml"
+//!     },
 //!     "security_hub_google_creds": {
 //!         "region": "<region_of_secret>",
-//!         "id": "<google_secret_name>"
+//!         "id": "<google_secret_name>",
+//!         // Optional, remove if not required. SecretsManager (default) | Ssm | File
+//!         // Where the secret is stored. Supported by all secrets
+//!         "source": "SecretsManager"
 //!     },
+//!     // Alternatively to security_hub_google_creds. Syncs users and groups from LDAP.
+//!     // google_api_query_for_users and google_api_query_for_groups are used as
+//!     // additional LDAP filters (e.g. `(department=IT)`). As LDAP does not list deleted
+//!     // users, users beyond the 50 users listed by AWS SCIM are not removed (see Limitations)
+//!     // "security_hub_ldap_creds": {
+//!     //     "region": "<region_of_secret>",
+//!     //     "id": "<ldap_secret_name>"
+//!     // },
 //!     "security_hub_scim_creds": {
 //!         "region": "<region_of_secret>",
 //!         "id": "<scim_secret_name>"
 //!     },
+//!     // Alternatively, security_hub_scim_creds can be a list of targets to sync the
+//!     // same google users and groups into multiple AWS SSO instances. Every target is
+//!     // synced independently and listed in `targets` of the lambda result
+//!     // "security_hub_scim_creds": [
+//!     //     {
+//!     //         "security_hub_scim_creds": {
+//!     //             "region": "<region_of_secret>",
+//!     //             "id": "<scim_secret_name>"
+//!     //         },
+//!     //         // Alternatively to security_hub_scim_creds. See identity_store below
+//!     //         // "identity_store": {
+//!     //         //     "region": "<region_of_sso_instance>",
+//!     //         //     "identity_store_id": "<identity_store_id>"
+//!     //         // },
+//!     //         // Optional, remove if not required. Example: `aws-.*@domain.org`
+//!     //         // Ignores a group for this target if one of the regexes matches.
+//!     //         // Matches on the email
+//!     //         "ignore_groups_regexes": [],
+//!     //         // Optional, remove if not required. Example: `aws-.*@domain.org`
+//!     //         // Includes a group for this target if one of the regexes matches.
+//!     //         // Matches on the email
+//!     //         "include_groups_regexes": [],
+//!     //         // Optional, remove if not required. See account_assignments below
+//!     //         "account_assignments": {}
+//!     //     }
+//!     // ],
+//!     // Optional, remove if not required. Only used if security_hub_scim_creds is not set.
+//!     // Uses the IAM Identity Store API instead of SCIM. Requires the permissions
+//!     // `identitystore:*` for the given identity store
+//!     "identity_store": {
+//!         "region": "<region_of_sso_instance>",
+//!         "identity_store_id": "<identity_store_id>"
+//!     },
+//!     // Optional, remove if not required.
+//!     // Assigns permission sets to synced groups in AWS accounts after the groups and
+//!     // memberships are synced. Requires the permissions `sso:ListAccountAssignments`,
+//!     // `sso:ListAccountsForProvisionedPermissionSet`, `sso:CreateAccountAssignment`,
+//!     // `sso:DeleteAccountAssignment`, `sso:DescribeAccountAssignmentCreationStatus`,
+//!     // `sso:DescribeAccountAssignmentDeletionStatus` and, if organizational_units are used,
+//!     // `organizations:ListAccountsForParent` and `organizations:ListOrganizationalUnitsForParent`.
+//!     // Only assignments of synced groups and permission sets used in one of the rules are
+//!     // deleted. Changes are listed in `account_assignments` of the target in the lambda result
+//!     "account_assignments": {
+//!         "instance_arn": "<sso_instance_arn>",
+//!         "region": "<region_of_sso_instance>",
+//!         // Optional, remove if not required. true | false (default)
+//!         // Only reports the changes without applying them
+//!         "dry_run": false,
+//!         // Optional, remove if not required. true | false (default)
+//!         // Whether assignments which are no longer defined by a rule are deleted
+//!         "allow_deletions": false,
+//!         // Optional, remove if not required. Default: unlimited
+//!         // Aborts the sync of the assignments if more assignments would be deleted
+//!         "max_deletions": 10,
+//!         "rules": [
+//!             {
+//!                 // Example: `aws-admin-.*@domain.org`. Matches on the email of the group
+//!                 "group_regex": "",
+//!                 "permission_set_arn": "<permission_set_arn>",
+//!                 // Optional, remove if not required
+//!                 "account_ids": [],
+//!                 // Optional, remove if not required. Example: `ou-abcd-12345678`
+//!                 // Includes all active accounts of the organizational units and their children
+//!                 "organizational_units": []
+//!             }
+//!         ]
+//!     },
+//!     // Optional, remove if not required.
+//!     // Only syncs the users and groups which changed according to the Google Admin audit
+//!     // log since the last run. Requires the Domain-Wide Delegation Scope
+//!     // `admin.reports.audit.readonly` and the permissions `ssm:GetParameter` and
+//!     // `ssm:PutParameter` for the checkpoint parameter. A full sync is run if there is no
+//!     // checkpoint yet, the full sync interval elapsed, a user or group was renamed, the
+//!     // aliases, external ids or custom schema fields of a user or an org unit changed or
+//!     // an ldap tenant is used. Users moved to another org unit are synced incrementally.
+//!     // Changes of nested groups, other user attributes and account assignments are only
+//!     // applied by the full sync
+//!     "incremental": {
+//!         "checkpoint": {
+//!             "region": "<region_of_parameter>",
+//!             "name": "<ssm_parameter_name>"
+//!         },
+//!         // Optional, remove if not required. Default: 1440
+//!         "full_sync_interval_minutes": 1440,
+//!         // Optional, remove if not required. Default: 10
+//!         // Audit log events may be delayed, so the window is extended into the past
+//!         "overlap_minutes": 10
+//!     },
+//!     // Optional, remove if not required. Sync (default) | RenewWatchChannels | Check |
+//!     // Snapshot | Diff
+//!     // RenewWatchChannels: Registers and renews the channels of watch_channels instead of
+//!     // syncing. Ldap tenants are skipped
+//!     // Check: Verifies the parameters, every Domain-Wide Delegation Scope, the credentials
+//!     // of all targets and whether the queries and filters match any users and groups.
+//!     // Nothing is synced. The results are listed in `checks` of the lambda result
+//!     // Snapshot: Exports the filtered google users, groups and memberships and the users,
+//!     // groups and memberships of every target as seen by a full sync. Nothing is synced.
+//!     // If a target is not able to list all users and groups, the google users and groups
+//!     // missing in its listing are fetched by name and included as well.
+//!     // The snapshot is written as configured in snapshot
+//!     // Diff: Runs a full sync against the snapshot configured in diff without accessing
+//!     // google or AWS and lists the resulting changes in `changes` of the lambda result.
+//!     // Account assignments are skipped. Use the same parameters as for the snapshot
+//!     "mode": "Sync",
+//!     // Optional, remove if not required.
+//!     // Push notification channels which trigger a targeted sync. Requires the Domain-Wide
+//!     // Delegation Scope `admin.reports.audit.readonly` and the permissions
+//!     // `ssm:GetParameter` and `ssm:PutParameter` for the state parameter. Notifications
+//!     // with an invalid token are rejected. Only users, groups and memberships are synced,
+//!     // everything else is applied by the next full sync
+//!     "watch_channels": {
+//!         // Function URL or API Gateway endpoint of the lambda
+//!         "address": "https://<function_url>",
+//!         // Secret with the content `{"token": "<random_token>"}`
+//!         "token": {
+//!             "region": "<region_of_secret>",
+//!             "id": "<token_secret_name>"
+//!         },
+//!         // Stores the registered channels
+//!         "state": {
+//!             "region": "<region_of_parameter>",
+//!             "name": "<ssm_parameter_name>"
+//!         },
+//!         // Optional, remove if not required. Default: 360 (maximum allowed by Google)
+//!         "ttl_minutes": 360,
+//!         // Optional, remove if not required. Default: 60
+//!         // Channels which expire within this time are renewed
+//!         "renew_before_minutes": 60
+//!     },
+//!     // Optional, remove if not required.
+//!     // Interrupts a full sync shortly before the lambda times out and continues it by
+//!     // invoking the lambda again, which requires the permission `lambda:InvokeAsync` on
+//!     // the lambda itself. The progress is passed in the field `continuation` of the new
+//!     // event and listed in `continuation` of the lambda result. Failed targets are passed
+//!     // on as well, so every following invocation fails and the incremental checkpoint
+//!     // is not saved. Every invocation loads the users and groups from google again, so
+//!     // the timeout has to be large enough for that plus some progress
+//!     "deadline": {
+//!         // Optional, remove if not required. Default: 120
+//!         // Seconds before the timeout of the lambda at which the sync is interrupted
+//!         "margin_seconds": 120
+//!     },
+//!     // Optional, remove if not required.
+//!     // Number of requests which are sent concurrently. When an API throttles a request,
+//!     // all concurrent requests to it wait before they are repeated
+//!     "concurrency": {
+//!         // Optional, remove if not required. Default: 1
+//!         // Number of groups whose members are listed concurrently via the Google Admin API
+//!         "google": 1,
+//!         // Optional, remove if not required. Default: 1
+//!         // Number of users and groups which are created concurrently and of groups whose
+//!         // members are updated concurrently
+//!         "aws": 1
+//!     },
+//!     // Optional, remove if not required.
+//!     // Writes every created or deleted user and group, membership change, manager change
+//!     // and account assignment as JSON Lines with the fields run_id, timestamp, target,
+//!     // action, principal, before, after and rule. The changes are written after each
+//!     // target, also if it fails, to the file
+//!     // `<yyyy>/<mm>/<dd>/<timestamp>-<request_id>-<target_index>.jsonl`, which is never
+//!     // overwritten. Continued syncs share the run_id of the first invocation
+//!     "audit_log": {
+//!         // Requires the permission `s3:PutObject`. The prefix is optional, e.g. `audit/`
+//!         "s3": {
+//!             "region": "<region_of_bucket>",
+//!             "bucket": "<bucket_name>",
+//!             "prefix": ""
+//!         },
+//!         // Optional, remove if not required. Writes the files to a local directory as well,
+//!         // which is meant for testing
+//!         // "directory": "<path>"
+//!     },
+//!     // Optional, remove if not required. Only used in mode Snapshot.
+//!     // Writes the snapshot as `<timestamp>-<request_id>.json`, which is listed in
+//!     // `snapshot_name` of the lambda result. Without this, the snapshot is returned in
+//!     // `snapshot` of the lambda result, which is limited to 6 MB. The snapshot contains
+//!     // the google profiles of all synced users
+//!     "snapshot": {
+//!         // Requires the permission `s3:PutObject`. The prefix is optional, e.g. `snapshots/`
+//!         "s3": {
+//!             "region": "<region_of_bucket>",
+//!             "bucket": "<bucket_name>",
+//!             "prefix": ""
+//!         },
+//!         // Optional, remove if not required. Writes the snapshot to a local directory as
+//!         // well, which is meant for testing
+//!         // "directory": "<path>"
+//!     },
+//!     // Optional, remove if not required. Only used in mode Diff.
+//!     // Snapshot to diff. Exactly one of s3 and file has to be set. s3 requires the
+//!     // permission `s3:GetObject`, file is a local path for running the diff offline
+//!     "diff": {
+//!         "s3": {
+//!             "region": "<region_of_bucket>",
+//!             "bucket": "<bucket_name>",
+//!             "key": "<snapshot_name>"
+//!         },
+//!         // "file": "<path>"
+//!     },
 //!     // Optional, remove if not required. Example: `email:aws-*`
 //!     // Query send via Google API to filter users
 //!     // More Infos at https://developers.google.com/admin-sdk/directory/v1/guides/search-users
@@ -98,13 +474,165 @@
 //!     // Optional, remove if not required. AllUsers | GroupMembersOnly (default)
 //!     // Defines the sync strategie
 //!     "sync_strategie": [],
+//!     // Optional, remove if not required.
+//!     // Overrides the sync strategie for specific users. The first entry whose conditions
+//!     // all match a user decides whether the user is synced. Excluded users are synced
+//!     // neither as user nor as group member
+//!     "sync_strategie_overrides": [
+//!         {
+//!             // Optional, remove if not required. Example: `aws-.*@domain.org`
+//!             // Matches users which are a member of a group where the regex matches the email
+//!             "group_regex": "",
+//!             // Optional, remove if not required. Example: `/Engineering`
+//!             // Matches users within the org unit or one of its child org units
+//!             "org_unit_path": "",
+//!             // Optional, remove if not required. Example: `.*-bot@domain.org`
+//!             // Matches users where the regex matches the primary_email. Like the include
+//!             // and ignore regexes, the regexes are matched against normalized emails
+//!             "user_regex": "",
+//!             // Include | Exclude
+//!             "action": "Include"
+//!         }
+//!     ],
+//!     // Optional, remove if not required.
+//!     // Defines which members of a group are synced
+//!     "group_member_options": {
+//!         // Optional, remove if not required. true (default) | false
+//!         // Whether members of nested groups are synced as well
+//!         "include_derived_membership": true,
+//!         // Optional, remove if not required. OWNER | MANAGER | MEMBER. Default: all roles
+//!         // Only members with one of these roles are synced
+//!         "roles": [],
+//!         // Optional, remove if not required. true | false (default)
+//!         // Whether members with status SUSPENDED are skipped
+//!         "exclude_suspended": false
+//!     },
+//!     // Optional, remove if not required. Example: `aws-admin-.*@domain.org`
+//!     // Overrides group_member_options for groups where group_regex matches the email.
+//!     // Only the first matching entry is applied. Options which are not set are taken
+//!     // from group_member_options
+//!     "group_member_options_overrides": [
+//!         {
+//!             "group_regex": "",
+//!             "roles": ["OWNER", "MEMBER"]
+//!         }
+//!     ],
+//!     // Optional, remove if not required.
+//!     // Defines how group members outside of the domain of the google admin user are handled.
+//!     // Skipped members are listed in `skipped_members` of the lambda result
+//!     "external_members": {
+//!         // Include | Skip (default)
+//!         // Include: External members are synced as AWS SSO users. The name of the user is
+//!         // derived from the email as Google does not provide more infos about them
+//!         "strategie": "Skip",
+//!         // Optional, remove if not required. Example: `partner.org`
+//!         // Only includes external members of one of the given domains
+//!         "allowed_domains": []
+//!     },
+//!     // Optional, remove if not required.
+//!     // Defines how emails are normalized before users, groups and members are matched.
+//!     // The include and exclude regexes are matched against the normalized emails
+//!     "identity_normalization": {
+//!         // Optional, remove if not required. Default: true
+//!         // Matches emails case-insensitive
+//!         "lowercase": true,
+//!         // Optional, remove if not required. Default: true
+//!         // Applies the unicode normalization form NFC
+//!         "unicode_normalization": true,
+//!         // Optional, remove if not required. Default: false
+//!         // Resolves aliases of users and groups in group memberships and external members
+//!         "resolve_aliases": false
+//!     },
+//!     // Optional, remove if not required.
+//!     // Defines from which attribute of the google user the AWS SSO user name is derived.
+//!     // Users without a value for the source are skipped. Changing the source of an
+//!     // existing setup recreates all users, as they are matched by their user name
+//!     "user_name": {
+//!         // PrimaryEmail (default) | LocalPart | Alias | ExternalId | CustomSchema
+//!         // LocalPart: The part of the primary email before the `@`
+//!         // Alias: The first alias of the user
+//!         // ExternalId: The value of the first external id of the user
+//!         // CustomSchema: The value of a field of a custom schema of the user
+//!         "source": "PrimaryEmail",
+//!         // Optional, remove if not required. Example: `aws.domain.org`
+//!         // Only used with Alias. Uses the first alias of the given domain
+//!         "alias_domain": "",
+//!         // Optional, remove if not required. Example: `organization`
+//!         // Only used with ExternalId. Uses the first external id of the given type.
+//!         // For external ids of type `custom`, the custom type is matched instead
+//!         "external_id_type": "",
+//!         // Required for CustomSchema. Name of the schema and of the field
+//!         "schema": "",
+//!         "field": "",
+//!         // Optional, remove if not required. Example: `{value}@aws.domain.org`
+//!         // `{value}` is replaced with the value of the source
+//!         "template": "{value}"
+//!     },
+//!     // Optional, remove if not required.
+//!     // Syncs users and groups of multiple Google Workspaces into the same AWS SSO.
+//!     // If set, security_hub_google_creds, google_api_query_for_users,
+//!     // google_api_query_for_groups and the users and groups regexes are ignored
+//!     // and have to be defined for each tenant instead. If a user or group exists in
+//!     // more than one tenant, the one of the first tenant is used and the conflict is
+//!     // listed in `conflicts` of the lambda result
+//!     "google_tenants": [
+//!         {
+//!             "security_hub_google_creds": {
+//!                 "region": "<region_of_secret>",
+//!                 "id": "<google_secret_name>"
+//!             },
+//!             // Alternatively to security_hub_google_creds. See above
+//!             // "security_hub_ldap_creds": {},
+//!             // Optional, remove if not required. Same as above
+//!             "google_api_query_for_users": "",
+//!             "google_api_query_for_groups": "",
+//!             "ignore_users_regexes": [],
+//!             "include_users_regexes": [],
+//!             "ignore_groups_regexes": [],
+//!             "include_groups_regexes": []
+//!         }
+//!     ],
 //! }
 //! ```
 //!
 //! ## Environment Variables
 //! ```sh
+//! # Optional, skip if not required. Example: `{"s3": {"region": "<region_of_bucket>", "bucket": "<bucket_name>", "key": "config.yaml"}}`
+//! # Config document which provides all other parameters. See event for more infos
+//! CONFIG=""
 //! SH_GOOGLE_CREDS="{\"region\": \"<region_of_secret>\",\"id\": \"<google_secret_name>\"}"
+//! # Alternatively to SH_GOOGLE_CREDS. See event for more infos
+//! # SH_LDAP_CREDS="{\"region\": \"<region_of_secret>\",\"id\": \"<ldap_secret_name>\"}"
 //! SH_SCIM_CREDS="{\"region\": \"<region_of_secret>\",\"id\": \"<scim_secret_name>\"}"
+//! # Alternatively a list of targets. See event for more infos
+//! # SH_SCIM_CREDS="[{\"security_hub_scim_creds\": {\"region\": \"<region_of_secret>\",\"id\": \"<scim_secret_name>\"}}]"
+//! # Optional, skip if not required. Only used if SH_SCIM_CREDS is not set
+//! # Uses the IAM Identity Store API instead of SCIM. See event for more infos
+//! IDENTITY_STORE="{\"region\": \"<region_of_sso_instance>\",\"identity_store_id\": \"<identity_store_id>\"}"
+//! # Optional, skip if not required. Example: `{"instance_arn": "<sso_instance_arn>", "region": "<region_of_sso_instance>", "rules": []}`
+//! # Assigns permission sets to synced groups. See event for more infos
+//! ACCOUNT_ASSIGNMENTS=""
+//! # Optional, skip if not required. Example: `{"checkpoint": {"region": "<region_of_parameter>", "name": "<ssm_parameter_name>"}}`
+//! # Only syncs changed users and groups. See event for more infos
+//! INCREMENTAL=""
+//! # Optional, skip if not required. Example: `{"address": "https://<function_url>", "token": {"region": "<region_of_secret>", "id": "<token_secret_name>"}, "state": {"region": "<region_of_parameter>", "name": "<ssm_parameter_name>"}}`
+//! # Push notification channels which trigger a targeted sync. See event for more infos
+//! WATCH_CHANNELS=""
+//! # Optional, skip if not required. Example: `{"margin_seconds": 120}`
+//! # Continues a full sync in a new invocation before the lambda times out. See event for more infos
+//! DEADLINE=""
+//! # Optional, skip if not required. Example: `{"google": 4, "aws": 4}`
+//! # Number of requests which are sent concurrently. See event for more infos
+//! CONCURRENCY=""
+//! # Optional, skip if not required. Example: `{"s3": {"region": "<region_of_bucket>", "bucket": "<bucket_name>", "prefix": "audit/"}}`
+//! # Writes an audit log of all changes. See event for more infos
+//! AUDIT_LOG=""
+//! # Optional, skip if not required. Example: `{"s3": {"region": "<region_of_bucket>", "bucket": "<bucket_name>", "prefix": "snapshots/"}}`
+//! # Where the snapshot of mode Snapshot is written. See event for more infos
+//! SNAPSHOT=""
+//! # Optional, skip if not required. Example: `{"file": "snapshot.json"}`
+//! # Snapshot which is diffed in mode Diff. See event for more infos
+//! DIFF=""
 //! # Optional, skip if not required. Example: `email:aws-*`
 //! # Query send via Google API to filter users
 //! # More Infos at https://developers.google.com/admin-sdk/directory/v1/guides/search-users
@@ -128,6 +656,27 @@
 //! # Optional, skip if not required. AllUsers | GroupMembersOnly (default)
 //! # Defines the sync strategie
 //! SYNC_STRATEGIE=""
+//! # Optional, skip if not required. Example: `[{"org_unit_path": "/Engineering", "action": "Include"}]`
+//! # Overrides SYNC_STRATEGIE for specific users. See event for more infos
+//! SYNC_STRATEGIE_OVERRIDES=""
+//! # Optional, skip if not required. Example: `{"roles": ["MEMBER"], "exclude_suspended": true}`
+//! # Defines which members of a group are synced. See event for more infos
+//! GROUP_MEMBER_OPTIONS=""
+//! # Optional, skip if not required. Example: `[{"group_regex": "aws-admin-.*@domain.org", "include_derived_membership": false}]`
+//! # Overrides GROUP_MEMBER_OPTIONS for specific groups. See event for more infos
+//! GROUP_MEMBER_OPTIONS_OVERRIDES=""
+//! # Optional, skip if not required. Example: `{"strategie": "Include", "allowed_domains": ["partner.org"]}`
+//! # Defines how group members outside of the domain are handled. See event for more infos
+//! EXTERNAL_MEMBERS=""
+//! # Optional, skip if not required. Example: `{"lowercase": true, "resolve_aliases": true}`
+//! # Defines how emails are normalized before they are matched. See event for more infos
+//! IDENTITY_NORMALIZATION=""
+//! # Optional, skip if not required. Example: `{"source": "LocalPart", "template": "{value}@aws.domain.org"}`
+//! # Defines from which attribute of the google user the AWS SSO user name is derived. See event for more infos
+//! USER_NAME=""
+//! # Optional, skip if not required. Example: `[{"security_hub_google_creds": {"region": "<region_of_secret>", "id": "<google_secret_name>"}}]`
+//! # Syncs users and groups of multiple Google Workspaces. See event for more infos
+//! GOOGLE_TENANTS=""
 //! # Optional, skip if not required. off | error | warn | info (default) | debug | trace
 //! # Defines the log level
 //! LOG_LEVEL=""
@@ -137,16 +686,30 @@
 #![deny(clippy::all, clippy::nursery)]
 #![deny(nonstandard_style, rust_2018_idioms, unused_crate_dependencies)]
 
+mod audit;
 mod aws;
+mod check;
+mod config;
+mod continuation;
+mod directory;
 mod event;
 mod google;
+mod incremental;
+mod ldap;
+mod metrics;
+mod report;
+mod scoped;
+mod snapshot;
 mod sync;
+mod throttle;
+mod webhook;
 
 #[cfg(feature = "test")]
 const TEST_DATA: &str = include_str!("../test.json");
 
 const ENV_VAR_LOG_LEVEL: &str = "LOG_LEVEL";
 pub(crate) const ENV_VAR_SH_GOOGLE_CREDS: &str = "SH_GOOGLE_CREDS";
+pub(crate) const ENV_VAR_SH_LDAP_CREDS: &str = "SH_LDAP_CREDS";
 pub(crate) const ENV_VAR_SH_SCIM_CREDS: &str = "SH_SCIM_CREDS";
 pub(crate) const ENV_VAR_GOOGLE_API_QUERY_FOR_USERS: &str = "GOOGLE_API_QUERY_FOR_USERS";
 pub(crate) const ENV_VAR_GOOGLE_API_QUERY_FOR_GROUPS: &str = "GOOGLE_API_QUERY_FOR_GROUPS";
@@ -155,34 +718,231 @@ pub(crate) const ENV_VAR_INCLUDE_USERS_REGEXES: &str = "INCLUDE_USERS_REGEXES";
 pub(crate) const ENV_VAR_IGNORE_GROUPS_REGEXES: &str = "IGNORE_GROUPS_REGEXES";
 pub(crate) const ENV_VAR_INCLUDE_GROUPS_REGEXES: &str = "INCLUDE_GROUPS_REGEXES";
 pub(crate) const ENV_VAR_SYNC_STRATEGIE: &str = "SYNC_STRATEGIE";
+pub(crate) const ENV_VAR_SYNC_STRATEGIE_OVERRIDES: &str = "SYNC_STRATEGIE_OVERRIDES";
+pub(crate) const ENV_VAR_GROUP_MEMBER_OPTIONS: &str = "GROUP_MEMBER_OPTIONS";
+pub(crate) const ENV_VAR_GROUP_MEMBER_OPTIONS_OVERRIDES: &str = "GROUP_MEMBER_OPTIONS_OVERRIDES";
+pub(crate) const ENV_VAR_EXTERNAL_MEMBERS: &str = "EXTERNAL_MEMBERS";
+pub(crate) const ENV_VAR_IDENTITY_NORMALIZATION: &str = "IDENTITY_NORMALIZATION";
+pub(crate) const ENV_VAR_USER_NAME: &str = "USER_NAME";
+pub(crate) const ENV_VAR_GOOGLE_TENANTS: &str = "GOOGLE_TENANTS";
+pub(crate) const ENV_VAR_IDENTITY_STORE: &str = "IDENTITY_STORE";
+pub(crate) const ENV_VAR_ACCOUNT_ASSIGNMENTS: &str = "ACCOUNT_ASSIGNMENTS";
+pub(crate) const ENV_VAR_INCREMENTAL: &str = "INCREMENTAL";
+pub(crate) const ENV_VAR_WATCH_CHANNELS: &str = "WATCH_CHANNELS";
+pub(crate) const ENV_VAR_DEADLINE: &str = "DEADLINE";
+pub(crate) const ENV_VAR_CONCURRENCY: &str = "CONCURRENCY";
+pub(crate) const ENV_VAR_CONFIG: &str = "CONFIG";
+pub(crate) const ENV_VAR_AUDIT_LOG: &str = "AUDIT_LOG";
+pub(crate) const ENV_VAR_SNAPSHOT: &str = "SNAPSHOT";
+pub(crate) const ENV_VAR_DIFF: &str = "DIFF";
 
 struct Runner;
 
 #[async_trait::async_trait]
-impl<'a> lambda_runtime_types::Runner<'a, (), event::Event, ()> for Runner {
+impl<'a> lambda_runtime_types::Runner<'a, (), event::Event, report::Report> for Runner {
     async fn run(
         _shared: &'a (),
-        event: lambda_runtime_types::LambdaEvent<'a, event::Event>,
-    ) -> anyhow::Result<()> {
-        let security_hub_google_creds: google::AdminCreds = aws::get_secret_from_secret_manager(
-            event.event.get_security_hub_google_creds()?.as_ref(),
-        )
-        .await?;
-        let security_hub_scim_creds: aws::ScimCreds = aws::get_secret_from_secret_manager(
-            event.event.get_security_hub_scim_creds()?.as_ref(),
-        )
-        .await?;
-
-        let scim = aws::Scim::new(&security_hub_scim_creds);
-        let gadmin = google::Admin::new(&security_hub_google_creds).await?;
-
-        let mut sync_op = sync::SyncOp::new(&event.event, &scim, &gadmin).await?;
-        sync_op.sync_groups().await?;
-        sync_op
-            .sync_users(event.event.get_sync_strategie()?)
+        mut event: lambda_runtime_types::LambdaEvent<'a, event::Event>,
+    ) -> anyhow::Result<report::Report> {
+        event.event = config::load(event.event).await?;
+        let mut report = report::Report::default();
+        match event.event.get_mode() {
+            event::Mode::Sync => {}
+            event::Mode::RenewWatchChannels => {
+                report.renewed_channels = webhook::renew_channels(&event.event).await?;
+                return Ok(report);
+            }
+            event::Mode::Check => {
+                report.checks = check::run(&event.event).await;
+                return Ok(report);
+            }
+            event::Mode::Snapshot => {
+                snapshot::create(&event.event, &event.ctx.request_id, &mut report).await?;
+                return Ok(report);
+            }
+            event::Mode::Diff => {
+                snapshot::diff(&event.event, &event.ctx.request_id, &mut report).await?;
+                return Ok(report);
+            }
+        }
+
+        let tenants = event.event.get_google_tenants()?;
+        let mut directory_creds = Vec::with_capacity(tenants.len());
+        for tenant in &tenants {
+            directory_creds.push(directory::DirectoryCreds::load(&tenant.source).await?);
+        }
+        let continuation = event.event.get_continuation();
+        let deadline =
+            continuation::Deadline::new(event.ctx.deadline, event.event.get_deadline()?.as_deref());
+        let notification_scope = match event.event.get_http_request() {
+            Some(request) => match webhook::get_scope(&event.event, &request).await {
+                Ok(Some(scope)) => Some(scope),
+                Ok(None) => return Ok(report),
+                Err(err) if err.downcast_ref::<webhook::InvalidChannelToken>().is_some() => {
+                    log::warn!("Rejecting push notification: {}", err);
+                    report.status_code = Some(401);
+                    return Ok(report);
+                }
+                Err(err) => return Err(err),
+            },
+            None => None,
+        };
+
+        let targets = event.event.get_scim_targets()?;
+        let user_sync_rules = event.event.get_user_sync_rules()?;
+        let audit_log = event.event.get_audit_log()?;
+        let run_id =
+            continuation.map_or_else(|| event.ctx.request_id.clone(), |c| c.run_id.clone());
+        let audit = audit::AuditLog::new(run_id.clone());
+        let mut directories = Vec::with_capacity(directory_creds.len());
+        for creds in &directory_creds {
+            directories.push(creds.connect().await?);
+        }
+        let sources = tenants
+            .iter()
+            .zip(&directories)
+            .map(|(tenant, directory)| sync::Source {
+                tenant,
+                directory: directory.as_ref(),
+            })
+            .collect::<Vec<_>>();
+
+        let incremental = match notification_scope {
+            Some(_) => None,
+            None => event.event.get_incremental()?,
+        };
+        let now = continuation.map_or_else(chrono::Utc::now, |c| c.started);
+        let checkpoint = match incremental {
+            Some(ref config) => incremental::Checkpoint::load(config).await?,
+            None => None,
+        };
+        let scope = match (notification_scope, incremental.as_ref()) {
+            // A continued sync is always a full sync
+            (None, Some(_)) if continuation.is_some() => None,
+            (Some(scope), _) => {
+                report.sync_type = report::SyncType::Targeted;
+                Some(scope)
+            }
+            (None, Some(config)) => {
+                let scope =
+                    incremental::get_scope(config, checkpoint.as_ref(), &directory_creds, now)
+                        .await?;
+                if scope.is_some() {
+                    report.sync_type = report::SyncType::Incremental;
+                }
+                scope
+            }
+            (None, None) => None,
+        };
+        let (full, scoped);
+        let plan = match scope {
+            Some(ref scope) => {
+                if scope.is_empty() {
+                    log::info!("No changes since the last sync");
+                } else {
+                    log::info!(
+                        "Running {:?} sync for {} users and {} groups",
+                        report.sync_type,
+                        scope.users.len(),
+                        scope.groups.len()
+                    );
+                }
+                scoped =
+                    scoped::ScopedState::new(&event.event, &sources, scope, &mut report).await?;
+                SyncPlan::Scoped(&scoped)
+            }
+            None => {
+                full = sync::GoogleState::new(&event.event, &sources, &mut report).await?;
+                SyncPlan::Full(&full)
+            }
+        };
+        // Targets which failed in a previous invocation of a continued sync count as failed
+        let mut failed_targets = continuation.map_or_else(Vec::new, |c| c.failed_targets.clone());
+        for (index, target) in targets.iter().enumerate() {
+            if continuation.is_some_and(|c| index < c.target) {
+                continue;
+            }
+            let resume = continuation::Continuation::resume_point(continuation, index);
+            let mut result =
+                sync_target(target, plan, &user_sync_rules, &audit, &resume, deadline).await;
+            // The changes are written after each target, so that they are kept even if
+            // a later target fails or the lambda times out. A target whose audit log can
+            // not be written counts as failed, the remaining targets are synced anyway
+            if let Some(ref config) = audit_log {
+                if let Err(err) = audit.write(config, &event.ctx.request_id, index).await {
+                    let err = err.context(format!(
+                        "Unable to write the audit log of target {}",
+                        target.name()
+                    ));
+                    match result {
+                        Ok(_) => result = Err(err),
+                        Err(_) => log::error!("{:?}", err),
+                    }
+                }
+            }
+            let target_report = match result {
+                Ok((target_report, Some(progress))) => {
+                    // Groups and users have no position within their phase, so the sync
+                    // only made no progress if nothing was changed either
+                    if continuation.is_some_and(|c| c.target == index)
+                        && progress == resume
+                        && !target_report.has_changes()
+                    {
+                        anyhow::bail!(
+                            "The sync made no progress before the deadline. Increase the timeout of the lambda."
+                        );
+                    }
+                    let next = continuation::Continuation {
+                        started: now,
+                        invocation: continuation.map_or(1, |c| c.invocation + 1),
+                        run_id,
+                        target: index,
+                        failed_targets: failed_targets.clone(),
+                        progress,
+                    };
+                    continuation::invoke(&event.event, &event.ctx, event.region, &next).await?;
+                    report.continuation = Some(next);
+                    report.targets.push(target_report);
+                    break;
+                }
+                Ok((target_report, None)) => target_report,
+                Err(err) => {
+                    log::error!("Unable to sync target {}: {:?}", target.name(), err);
+                    if err.downcast_ref::<aws::InvalidScimToken>().is_some() {
+                        metrics::put_metric("ScimTokenRejected", 1, &[("Target", target.name())]);
+                    }
+                    failed_targets.push(target.name().to_owned());
+                    report::TargetReport {
+                        error: Some(format!("{err:?}")),
+                        ..report::TargetReport::new(target.name())
+                    }
+                }
+            };
+            report.targets.push(target_report);
+        }
+        if !failed_targets.is_empty() {
+            log::info!(
+                "Report: {}",
+                serde_json::to_string(&report).unwrap_or_default()
+            );
+            anyhow::bail!("Unable to sync targets: {}", failed_targets.join(", "));
+        }
+        if report.continuation.is_some() {
+            return Ok(report);
+        }
+        if let Some(ref config) = incremental {
+            let last_full_sync = match (plan, checkpoint) {
+                (SyncPlan::Scoped(_), Some(checkpoint)) => checkpoint.last_full_sync,
+                _ => now,
+            };
+            incremental::Checkpoint {
+                last_sync: now,
+                last_full_sync,
+            }
+            .save(config)
             .await?;
-        sync_op.sync_associations().await?;
-        Ok(())
+        }
+        Ok(report)
     }
 
     async fn setup(_region: &'a str) -> anyhow::Result<()> {
@@ -201,6 +961,130 @@ impl<'a> lambda_runtime_types::Runner<'a, (), event::Event, ()> for Runner {
     }
 }
 
+/// Users and groups of google which are synced to every target
+#[derive(Clone, Copy)]
+enum SyncPlan<'a> {
+    Full(&'a sync::GoogleState<'a>),
+    Scoped(&'a scoped::ScopedState<'a>),
+}
+
+/// Syncs the target starting at `resume`. Returns the progress of the sync
+/// as well if it was interrupted by the deadline
+async fn sync_target(
+    target: &event::Target<'_>,
+    plan: SyncPlan<'_>,
+    user_sync_rules: &event::UserSyncRules,
+    audit: &audit::AuditLog,
+    resume: &continuation::Progress,
+    deadline: continuation::Deadline,
+) -> anyhow::Result<(report::TargetReport, Option<continuation::Progress>)> {
+    match target.backend {
+        event::TargetBackend::Scim(ref secret) => {
+            let security_hub_scim_creds: aws::ScimCreds = aws::get_secret(secret.as_ref()).await?;
+            let days_left = check_scim_token_expiry(target, &security_hub_scim_creds);
+            let scim = aws::Scim::new(&security_hub_scim_creds)?;
+            let (mut target_report, progress) = sync_backend(
+                target,
+                &scim,
+                plan,
+                user_sync_rules,
+                audit,
+                resume,
+                deadline,
+            )
+            .await?;
+            target_report.scim_token_days_left = days_left;
+            Ok((target_report, progress))
+        }
+        event::TargetBackend::IdentityStore(ref config) => {
+            let identity_store = aws::IdentityStore::new(config.as_ref())?;
+            sync_backend(
+                target,
+                &identity_store,
+                plan,
+                user_sync_rules,
+                audit,
+                resume,
+                deadline,
+            )
+            .await
+        }
+    }
+}
+
+/// Emits the days until the SCIM access token expires as metric and warns if it expires soon
+fn check_scim_token_expiry(target: &event::Target<'_>, creds: &aws::ScimCreds) -> Option<i64> {
+    let days_left = creds.token_days_left(chrono::Utc::now().date_naive())?;
+    metrics::put_metric(
+        "ScimTokenDaysUntilExpiry",
+        days_left,
+        &[("Target", target.name())],
+    );
+    if days_left < 0 {
+        log::warn!(
+            "The SCIM access token of {} expired {} days ago. Generate a new one in the AWS SSO console.",
+            creds.endpoint(),
+            -days_left
+        );
+    } else if creds.is_token_expiring(days_left) {
+        log::warn!(
+            "The SCIM access token of {} expires in {} days. Generate a new one in the AWS SSO console.",
+            creds.endpoint(),
+            days_left
+        );
+    }
+    Some(days_left)
+}
+
+async fn sync_backend(
+    target: &event::Target<'_>,
+    backend: &dyn aws::Backend,
+    plan: SyncPlan<'_>,
+    user_sync_rules: &event::UserSyncRules,
+    audit: &audit::AuditLog,
+    resume: &continuation::Progress,
+    deadline: continuation::Deadline,
+) -> anyhow::Result<(report::TargetReport, Option<continuation::Progress>)> {
+    use continuation::Phase;
+
+    let google = match plan {
+        SyncPlan::Full(google) => google,
+        SyncPlan::Scoped(google) => {
+            let mut sync_op =
+                scoped::ScopedSyncOp::new(target, backend, google, user_sync_rules, audit);
+            sync_op.sync_groups().await?;
+            sync_op.sync_users().await?;
+            return Ok((sync_op.into_report(), None));
+        }
+    };
+    let mut sync_op =
+        sync::SyncOp::new(target, backend, google, user_sync_rules, audit, deadline).await?;
+    for phase in Phase::ALL.into_iter().filter(|p| *p >= resume.phase) {
+        match phase {
+            Phase::Groups => sync_op.sync_groups().await?,
+            Phase::Users => sync_op.sync_users().await?,
+            Phase::Associations => {
+                let after = match resume.phase {
+                    Phase::Associations => resume.last_user.as_deref(),
+                    _ => None,
+                };
+                sync_op.sync_associations(after).await?
+            }
+            Phase::AccountAssignments => {
+                if let Some(ref account_assignments) = target.account_assignments {
+                    sync_op
+                        .sync_account_assignments(account_assignments)
+                        .await?;
+                }
+            }
+        }
+        if let Some(progress) = sync_op.interruption(phase) {
+            return Ok((sync_op.into_report(), Some(progress)));
+        }
+    }
+    Ok((sync_op.into_report(), None))
+}
+
 /// Entrypoint for the lambda
 pub fn main() -> anyhow::Result<()> {
     #[cfg(not(feature = "test"))]
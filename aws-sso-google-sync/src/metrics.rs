@@ -0,0 +1,25 @@
+const NAMESPACE: &str = "aws-sso-google-sync";
+
+/// Emits a metric in the CloudWatch Embedded Metric Format. CloudWatch extracts the
+/// metric from the log output of the lambda, so no additional permissions are required
+pub fn put_metric(name: &str, value: i64, dimensions: &[(&str, &str)]) {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let mut entry = serde_json::json!({
+        "_aws": {
+            "Timestamp": timestamp,
+            "CloudWatchMetrics": [{
+                "Namespace": NAMESPACE,
+                "Dimensions": [dimensions.iter().map(|(k, _)| *k).collect::<Vec<_>>()],
+                "Metrics": [{ "Name": name, "Unit": "None" }],
+            }],
+        },
+        name: value,
+    });
+    for (key, value) in dimensions {
+        entry[*key] = serde_json::Value::from(*value);
+    }
+    println!("{entry}");
+}
@@ -0,0 +1,150 @@
+#[derive(Debug, Default, serde::Serialize)]
+pub struct Report {
+    /// Status of the response to a push notification. Only set if it was rejected
+    #[serde(rename = "statusCode", skip_serializing_if = "Option::is_none")]
+    pub(crate) status_code: Option<u16>,
+    pub(crate) sync_type: SyncType,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) renewed_channels: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) checks: Vec<Check>,
+    /// Set if the sync was interrupted and is continued by another invocation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) continuation: Option<crate::continuation::Continuation>,
+    /// Snapshot of the mode `Snapshot` if no location is configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) snapshot: Option<crate::snapshot::Snapshot>,
+    /// Name of the snapshot relative to the configured prefix or directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) snapshot_name: Option<String>,
+    /// Changes computed by the mode `Diff`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) changes: Vec<crate::audit::Entry>,
+    pub(crate) skipped_members: Vec<SkippedMember>,
+    pub(crate) conflicts: Vec<Conflict>,
+    pub(crate) targets: Vec<TargetReport>,
+}
+
+/// Whether all users and groups or only the changed ones were synced
+#[derive(Debug, Copy, Clone, Default, serde::Serialize)]
+pub enum SyncType {
+    #[default]
+    Full,
+    Incremental,
+    /// Triggered by a google push notification
+    Targeted,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct TargetReport {
+    pub(crate) target: String,
+    pub(crate) created_users: usize,
+    pub(crate) deleted_users: usize,
+    pub(crate) created_groups: usize,
+    pub(crate) deleted_groups: usize,
+    pub(crate) added_memberships: usize,
+    pub(crate) removed_memberships: usize,
+    pub(crate) updated_managers: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) skipped_users: Vec<SkippedUser>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) account_assignments: Vec<AccountAssignmentChange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) scim_token_days_left: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+impl TargetReport {
+    pub(crate) fn new(target: &str) -> Self {
+        Self {
+            target: target.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether any user, group, membership, manager or account assignment was changed
+    pub(crate) const fn has_changes(&self) -> bool {
+        self.created_users
+            + self.deleted_users
+            + self.created_groups
+            + self.deleted_groups
+            + self.added_memberships
+            + self.removed_memberships
+            + self.updated_managers
+            > 0
+            || !self.account_assignments.is_empty()
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SkippedMember {
+    pub(crate) group: String,
+    pub(crate) email: String,
+    pub(crate) reason: SkipReason,
+}
+
+/// User which could not be created in the target, e.g. because of an incomplete profile
+#[derive(Debug, serde::Serialize)]
+pub struct SkippedUser {
+    pub(crate) email: String,
+    pub(crate) reason: String,
+}
+
+#[derive(Debug, Copy, Clone, serde::Serialize)]
+pub enum SkipReason {
+    ExternalMember,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Conflict {
+    pub(crate) kind: PrincipalKind,
+    pub(crate) name: String,
+    pub(crate) used_tenant: String,
+    pub(crate) skipped_tenant: String,
+}
+
+#[derive(Debug, Copy, Clone, serde::Serialize)]
+pub enum PrincipalKind {
+    User,
+    Group,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct AccountAssignmentChange {
+    pub(crate) action: AssignmentAction,
+    pub(crate) status: AssignmentStatus,
+    pub(crate) group: String,
+    pub(crate) account_id: String,
+    pub(crate) permission_set_arn: String,
+}
+
+#[derive(Debug, Copy, Clone, serde::Serialize)]
+pub enum AssignmentAction {
+    Create,
+    Delete,
+}
+
+#[derive(Debug, Copy, Clone, serde::Serialize)]
+pub enum AssignmentStatus {
+    Applied,
+    DryRun,
+    DeletionNotAllowed,
+}
+
+/// Result of a single preflight check
+#[derive(Debug, serde::Serialize)]
+pub struct Check {
+    pub(crate) name: String,
+    pub(crate) status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) message: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum CheckStatus {
+    Passed,
+    /// The check succeeded, but the result is probably not intended
+    Warning,
+    Failed,
+}
@@ -0,0 +1,506 @@
+use crate::sync::{Lookup, Source};
+
+/// Users and groups which changed in google and need to be synced. The emails are
+/// kept as reported by google and normalized by `ScopedState`
+#[derive(Debug, Default)]
+pub struct Scope {
+    pub(crate) users: std::collections::BTreeSet<String>,
+    pub(crate) groups: std::collections::BTreeSet<String>,
+    /// Members whose membership of a group changed, keyed by the group email
+    pub(crate) members: Lookup<std::collections::BTreeSet<String>>,
+}
+
+impl Scope {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.users.is_empty() && self.groups.is_empty()
+    }
+
+    pub(crate) fn add_user(&mut self, email: &str) {
+        let _ = self.users.insert(email.into());
+    }
+
+    pub(crate) fn add_group(&mut self, email: &str) {
+        let _ = self.groups.insert(email.into());
+    }
+
+    pub(crate) fn add_member(&mut self, group: &str, email: &str) {
+        self.add_group(group);
+        let _ = self
+            .members
+            .entry(group.into())
+            .or_default()
+            .insert(email.into());
+    }
+}
+
+/// Google state of the users and groups within a scope. Entries which do
+/// not exist in google or are excluded by the tenant filters are `None`.
+/// All entries are keyed by their normalized email
+pub struct ScopedState<'a> {
+    scope: &'a Scope,
+    normalization: crate::event::IdentityNormalization,
+    user_name: crate::event::UserNameConfig,
+    concurrency: crate::event::ConcurrencyConfig,
+
+    groups: Lookup<Option<crate::google::Group>>,
+    group_members: Lookup<std::collections::HashSet<String>>,
+    /// Members whose membership of a group changed according to the scope
+    changed_members: Lookup<std::collections::HashSet<String>>,
+    users: Lookup<Option<crate::google::User>>,
+}
+
+impl<'a> ScopedState<'a> {
+    pub(crate) async fn new(
+        event: &crate::event::Event,
+        sources: &[Source<'_>],
+        scope: &'a Scope,
+        report: &mut crate::report::Report,
+    ) -> anyhow::Result<ScopedState<'a>> {
+        let member_options = event.get_group_member_options()?;
+        let member_options_overrides = event.get_group_member_options_overrides()?;
+        let external_members = event.get_external_members()?;
+        let normalization = event.get_identity_normalization()?;
+        let user_name = event.get_user_name()?;
+        let concurrency = event.get_concurrency()?;
+
+        let mut groups = Lookup::new();
+        let mut group_members = Lookup::new();
+        let mut users = Lookup::new();
+        for email in &scope.groups {
+            let group_key = normalization.normalize(email);
+            if groups.contains_key(&group_key) {
+                continue;
+            }
+            let (source, group) = match Self::find_group(sources, email, &normalization).await? {
+                Some(v) => v,
+                None => {
+                    let _ = groups.insert(group_key, None);
+                    continue;
+                }
+            };
+            let options = crate::sync::group_member_options(
+                &member_options,
+                &member_options_overrides,
+                &normalization.normalize(&group.email),
+            );
+            let members = source
+                .directory
+                .list_group_members(&group.id, &options)
+                .await?;
+            let mut emails = std::collections::HashSet::new();
+            for member in members {
+                let key = normalization.normalize(&member.email);
+                if !users.contains_key(&key) {
+                    let user = match Self::find_user(sources, &member.email, &normalization).await?
+                    {
+                        Some(user) => Some(user),
+                        None if sources
+                            .iter()
+                            .any(|s| s.directory.is_internal(&member.email))
+                            || !source.tenant.is_user_included(&key) =>
+                        {
+                            None
+                        }
+                        None if external_members.is_included(&member.email) => {
+                            Some(crate::google::User::from_external_member(&member))
+                        }
+                        None => {
+                            log::info!(
+                                "Skipping external member {} of group {}",
+                                member.email,
+                                group.email
+                            );
+                            report.skipped_members.push(crate::report::SkippedMember {
+                                group: group.email.clone(),
+                                email: member.email.clone(),
+                                reason: crate::report::SkipReason::ExternalMember,
+                            });
+                            None
+                        }
+                    };
+                    let _ = users.insert(key.clone(), user);
+                }
+                let _ = emails.insert(key);
+            }
+            let _ = group_members.insert(group_key.clone(), emails);
+            let _ = groups.insert(group_key, Some(group));
+        }
+        let mut changed_members: Lookup<std::collections::HashSet<_>> = Lookup::new();
+        for (group, members) in &scope.members {
+            changed_members
+                .entry(normalization.normalize(group))
+                .or_default()
+                .extend(members.iter().map(|m| normalization.normalize(m)));
+        }
+        for email in &scope.users {
+            let key = normalization.normalize(email);
+            if !matches!(users.get(&key), Some(Some(_))) {
+                let user = Self::find_user(sources, email, &normalization).await?;
+                let _ = users.insert(key, user);
+            }
+        }
+
+        Ok(Self {
+            scope,
+            normalization,
+            user_name,
+            concurrency,
+            groups,
+            group_members,
+            changed_members,
+            users,
+        })
+    }
+
+    /// Returns the emails of all groups within the scope the user is a member of
+    fn get_user_groups(&self, key: &str) -> Vec<&str> {
+        self.group_members
+            .iter()
+            .filter(|(_, members)| members.contains(key))
+            .map(|(group, _)| group.as_str())
+            .collect()
+    }
+
+    /// Returns the AWS SSO user name of the user with the given email. Users which
+    /// no longer exist in google only have a user name if it is derived from the email
+    fn get_user_name(&self, email: &str) -> Option<String> {
+        match self.users.get(&self.normalization.normalize(email)) {
+            Some(Some(user)) => self.user_name.get(user),
+            _ => self.user_name.get_by_email(email),
+        }
+    }
+
+    async fn find_group<'s>(
+        sources: &'s [Source<'s>],
+        email: &str,
+        normalization: &crate::event::IdentityNormalization,
+    ) -> anyhow::Result<Option<(&'s Source<'s>, crate::google::Group)>> {
+        let key = normalization.normalize(email);
+        for source in sources {
+            if !source.tenant.is_group_included(&key) {
+                continue;
+            }
+            if let Some(group) = source.directory.get_group(email).await? {
+                return Ok(Some((source, group)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the user with the given primary email. Lookups by an alias are
+    /// ignored, as the user is synced with its primary email
+    async fn find_user(
+        sources: &[Source<'_>],
+        email: &str,
+        normalization: &crate::event::IdentityNormalization,
+    ) -> anyhow::Result<Option<crate::google::User>> {
+        let key = normalization.normalize(email);
+        for source in sources {
+            if !source.tenant.is_user_included(&key) {
+                continue;
+            }
+            if let Some(user) = source.directory.get_user(email).await? {
+                if normalization.normalize(&user.primary_email) == key {
+                    return Ok(Some(user));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Sync of the users and groups within a scope to a single target. Only
+/// the affected entries are read from and written to the target
+pub struct ScopedSyncOp<'a> {
+    target: &'a crate::event::Target<'a>,
+    backend: &'a dyn crate::aws::Backend,
+    google: &'a ScopedState<'a>,
+    user_sync_rules: &'a crate::event::UserSyncRules,
+    audit: &'a crate::audit::AuditLog,
+
+    aws_user_ids: Lookup<Option<String>>,
+
+    report: crate::report::TargetReport,
+}
+
+impl<'a> ScopedSyncOp<'a> {
+    pub(crate) fn new(
+        target: &'a crate::event::Target<'a>,
+        backend: &'a dyn crate::aws::Backend,
+        google: &'a ScopedState<'a>,
+        user_sync_rules: &'a crate::event::UserSyncRules,
+        audit: &'a crate::audit::AuditLog,
+    ) -> Self {
+        Self {
+            target,
+            backend,
+            google,
+            user_sync_rules,
+            audit,
+
+            aws_user_ids: Lookup::new(),
+
+            report: crate::report::TargetReport::new(target.name()),
+        }
+    }
+
+    pub(crate) fn into_report(self) -> crate::report::TargetReport {
+        self.report
+    }
+
+    fn audit(&self, change: crate::audit::Change) {
+        self.audit.record(&self.report.target, change);
+    }
+
+    async fn get_aws_user_id(&mut self, user_name: &str) -> anyhow::Result<Option<String>> {
+        if let Some(id) = self.aws_user_ids.get(user_name) {
+            return Ok(id.clone());
+        }
+        let id = self.backend.get_user(user_name).await?.and_then(|u| u.id);
+        let _ = self.aws_user_ids.insert(user_name.into(), id.clone());
+        Ok(id)
+    }
+
+    /// Returns the id of the AWS SSO user and creates it if required. Returns `None`
+    /// if the configured user name source has no value for the user or if the user
+    /// can not be created. The rule which caused the sync of the user is
+    /// recorded in the audit log
+    async fn ensure_user(
+        &mut self,
+        g_user: &crate::google::User,
+        rule: &str,
+    ) -> anyhow::Result<Option<String>> {
+        use anyhow::Context;
+
+        let user_name = match self.google.user_name.get(g_user) {
+            Some(v) => v,
+            None => {
+                log::warn!(
+                    "Skipping user {} as the configured user name source has no value.",
+                    g_user.primary_email
+                );
+                return Ok(None);
+            }
+        };
+        if let Some(id) = self.get_aws_user_id(&user_name).await? {
+            return Ok(Some(id));
+        }
+        // The manager is only set if it already exists, otherwise it is set by the
+        // next full sync
+        let manager_id = match g_user.manager().and_then(|m| self.google.get_user_name(m)) {
+            Some(manager) if self.backend.is_manager_supported() => {
+                self.get_aws_user_id(&manager).await?
+            }
+            _ => None,
+        };
+        let user = match crate::sync::SyncOp::create_user(
+            g_user,
+            user_name.clone(),
+            manager_id,
+            self.backend.is_multiple_emails_supported(),
+        ) {
+            Ok(u) => u,
+            Err(err) => {
+                log::warn!("Skipping user {}: {:#}", g_user.primary_email, err);
+                self.report.skipped_users.push(crate::report::SkippedUser {
+                    email: g_user.primary_email.clone(),
+                    reason: format!("{err:#}"),
+                });
+                return Ok(None);
+            }
+        };
+        let (user, created) = crate::sync::SyncOp::create_backend_user(self.backend, user).await?;
+        if created {
+            self.audit(
+                crate::audit::Change::new(crate::audit::Action::CreateUser, &user_name)
+                    .after(&user)
+                    .rule(Some(rule)),
+            );
+            self.report.created_users += 1;
+        }
+        let id = user
+            .id
+            .with_context(|| format!("User {} has no id", user_name))?;
+        let _ = self.aws_user_ids.insert(user_name, Some(id.clone()));
+        Ok(Some(id))
+    }
+
+    async fn ensure_group(&mut self, g_group: &crate::google::Group) -> anyhow::Result<String> {
+        use anyhow::Context;
+
+        let group = match self.backend.get_group(&g_group.email).await? {
+            Some(group) => group,
+            None => {
+                let group = crate::sync::SyncOp::create_group(g_group);
+                let (group, created) =
+                    crate::sync::SyncOp::create_backend_group(self.backend, group).await?;
+                if created {
+                    self.audit(
+                        crate::audit::Change::new(
+                            crate::audit::Action::CreateGroup,
+                            &g_group.email,
+                        )
+                        .after(&group),
+                    );
+                    self.report.created_groups += 1;
+                }
+                group
+            }
+        };
+        group
+            .id
+            .with_context(|| format!("Group {} has no id", g_group.email))
+    }
+
+    /// Creates, deletes and updates the memberships of all groups within the scope
+    pub(crate) async fn sync_groups(&mut self) -> anyhow::Result<()> {
+        let mut changes = Vec::new();
+        let mut audited = Vec::new();
+        for (email, group) in &self.google.groups {
+            let g_group = match *group {
+                Some(ref g) if self.target.is_group_included(&g.email) => g,
+                _ => {
+                    if let Some(aws_group) = self.backend.get_group(email).await? {
+                        if let Some(ref aws_id) = aws_group.id {
+                            log::info!("Deleting group: {}", email);
+                            self.backend.delete_group(aws_id).await?;
+                            self.audit(
+                                crate::audit::Change::new(crate::audit::Action::DeleteGroup, email)
+                                    .before(&aws_group),
+                            );
+                            self.report.deleted_groups += 1;
+                        }
+                    }
+                    continue;
+                }
+            };
+            let aws_group_id = self.ensure_group(g_group).await?;
+
+            let mut member_ids = std::collections::HashSet::new();
+            for member in self.google.group_members.get(email).into_iter().flatten() {
+                let g_user = match self.google.users.get(member) {
+                    Some(Some(u)) => u,
+                    _ => continue,
+                };
+                let (synced, rule) = self.user_sync_rules.evaluate(
+                    g_user,
+                    member,
+                    &self.google.get_user_groups(member),
+                    true,
+                );
+                if !synced {
+                    continue;
+                }
+                let aws_user_id = match self.ensure_user(g_user, &rule).await? {
+                    Some(v) => v,
+                    None => continue,
+                };
+                if !self
+                    .backend
+                    .is_group_member(&aws_group_id, &aws_user_id)
+                    .await?
+                {
+                    log::info!("Adding user {} to group {}.", member, email);
+                    changes.push((aws_group_id.clone(), aws_user_id.clone(), true));
+                    audited.push(
+                        crate::audit::Change::new(crate::audit::Action::AddMember, member)
+                            .after(&serde_json::json!({ "group": email })),
+                    );
+                }
+                let _ = member_ids.insert(aws_user_id);
+            }
+
+            let (candidates, confirmed) =
+                match self.backend.list_group_members(&aws_group_id).await? {
+                    Some(ids) => (ids, true),
+                    None => {
+                        let mut ids = std::collections::HashSet::new();
+                        for member in self.google.changed_members.get(email).into_iter().flatten() {
+                            let user_name = match self.google.get_user_name(member) {
+                                Some(v) => v,
+                                None => continue,
+                            };
+                            if let Some(id) = self.get_aws_user_id(&user_name).await? {
+                                let _ = ids.insert(id);
+                            }
+                        }
+                        (ids, false)
+                    }
+                };
+            for aws_user_id in candidates.difference(&member_ids) {
+                if confirmed
+                    || self
+                        .backend
+                        .is_group_member(&aws_group_id, aws_user_id)
+                        .await?
+                {
+                    log::info!("Removing user {} from group {}.", aws_user_id, email);
+                    changes.push((aws_group_id.clone(), aws_user_id.clone(), false));
+                    audited.push(
+                        crate::audit::Change::new(crate::audit::Action::RemoveMember, aws_user_id)
+                            .before(&serde_json::json!({ "group": email })),
+                    );
+                }
+            }
+        }
+        let changes = changes
+            .iter()
+            .map(|&(ref group_id, ref user_id, add)| {
+                if add {
+                    crate::aws::MembershipChange::Add { group_id, user_id }
+                } else {
+                    crate::aws::MembershipChange::Remove { group_id, user_id }
+                }
+            })
+            .collect::<Vec<_>>();
+        crate::sync::apply_membership_changes(
+            self.backend,
+            &changes,
+            audited,
+            self.google.concurrency.aws(),
+            self.audit,
+            &mut self.report,
+        )
+        .await
+    }
+
+    /// Creates or deletes the users within the scope. Users which are not synced
+    /// according to the sync strategie and its overrides are left to the next full sync.
+    /// Group overrides only match groups within the scope
+    pub(crate) async fn sync_users(&mut self) -> anyhow::Result<()> {
+        for email in &self.google.scope.users {
+            let key = self.google.normalization.normalize(email);
+            match self.google.users.get(&key) {
+                Some(Some(g_user)) => {
+                    let groups = self.google.get_user_groups(&key);
+                    let (synced, rule) =
+                        self.user_sync_rules.evaluate(g_user, &key, &groups, false);
+                    if synced {
+                        let _ = self.ensure_user(g_user, &rule).await?;
+                    }
+                }
+                _ => {
+                    let user_name = match self.google.user_name.get_by_email(email) {
+                        Some(v) => v,
+                        None => {
+                            log::info!("Deletion of user {} is left to the next full sync", email);
+                            continue;
+                        }
+                    };
+                    if let Some(aws_id) = self.get_aws_user_id(&user_name).await? {
+                        log::info!("Deleting user: {}", user_name);
+                        self.backend.delete_user(&aws_id).await?;
+                        self.audit(
+                            crate::audit::Change::new(crate::audit::Action::DeleteUser, &user_name)
+                                .before(&serde_json::json!({ "id": aws_id }))
+                                .rule(Some(crate::audit::RULE_MISSING_IN_GOOGLE)),
+                        );
+                        let _ = self.aws_user_ids.insert(user_name, None);
+                        self.report.deleted_users += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
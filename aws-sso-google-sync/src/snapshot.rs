@@ -0,0 +1,429 @@
+/// State of google and of every target as seen by the full sync
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Snapshot {
+    pub(crate) created: chrono::DateTime<chrono::Utc>,
+    pub(crate) targets: Vec<TargetSnapshot>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct TargetSnapshot {
+    pub(crate) target: String,
+    pub(crate) google: GoogleSnapshot,
+    pub(crate) aws: AwsSnapshot,
+}
+
+/// Users, groups and memberships of google after all filters of the target are applied.
+/// Users are keyed by their AWS SSO user name, groups by their email
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct GoogleSnapshot {
+    pub(crate) users: std::collections::BTreeMap<String, crate::google::User>,
+    pub(crate) groups: std::collections::BTreeMap<String, crate::google::Group>,
+    pub(crate) memberships: std::collections::BTreeMap<String, std::collections::BTreeSet<String>>,
+    /// User names of the managers keyed by the user name of the user
+    pub(crate) managers: std::collections::BTreeMap<String, String>,
+    /// Rule which decided whether a user is synced, keyed by its user name
+    pub(crate) rules: std::collections::BTreeMap<String, String>,
+    /// Users deleted in google keyed by their user name. Only set if the sync looks them
+    /// up, as the target is not able to list all users
+    #[serde(default)]
+    pub(crate) deleted_users: std::collections::BTreeMap<String, crate::google::User>,
+}
+
+/// Users, groups and memberships of the target
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct AwsSnapshot {
+    pub(crate) manager_supported: bool,
+    #[serde(default)]
+    pub(crate) multiple_emails_supported: bool,
+    /// Whether `users` and `groups` contain all users and groups of the target
+    pub(crate) listing_complete: bool,
+    pub(crate) users: Vec<crate::aws::User>,
+    pub(crate) groups: Vec<crate::aws::Group>,
+    /// Users and groups of google and users deleted in google, which are missing in the
+    /// listing of the target but can be fetched by name. Only set if the listing is not
+    /// complete
+    #[serde(default)]
+    pub(crate) unlisted_users: Vec<crate::aws::User>,
+    #[serde(default)]
+    pub(crate) unlisted_groups: Vec<crate::aws::Group>,
+    /// User names of the members keyed by the display name of the group
+    pub(crate) memberships: std::collections::BTreeMap<String, std::collections::BTreeSet<String>>,
+}
+
+/// Exports the state of google and of every target. The snapshot is written to the
+/// configured locations or returned in the report
+pub async fn create(
+    event: &crate::event::Event,
+    request_id: &str,
+    report: &mut crate::report::Report,
+) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let tenants = event.get_google_tenants()?;
+    let mut directory_creds = Vec::with_capacity(tenants.len());
+    for tenant in &tenants {
+        directory_creds.push(crate::directory::DirectoryCreds::load(&tenant.source).await?);
+    }
+    let mut directories = Vec::with_capacity(directory_creds.len());
+    for creds in &directory_creds {
+        directories.push(creds.connect().await?);
+    }
+    let sources = tenants
+        .iter()
+        .zip(&directories)
+        .map(|(tenant, directory)| crate::sync::Source {
+            tenant,
+            directory: directory.as_ref(),
+        })
+        .collect::<Vec<_>>();
+    let google = crate::sync::GoogleState::new(event, &sources, report).await?;
+    let user_sync_rules = event.get_user_sync_rules()?;
+    // Nothing is changed, so nothing is recorded
+    let audit = crate::audit::AuditLog::new(request_id.into());
+    let deadline = crate::continuation::Deadline::new(0, None);
+
+    let mut targets = Vec::new();
+    for target in &event.get_scim_targets()? {
+        let (creds, scim, identity_store);
+        let backend: &dyn crate::aws::Backend = match target.backend {
+            crate::event::TargetBackend::Scim(ref secret) => {
+                creds = crate::aws::get_secret::<crate::aws::ScimCreds>(secret.as_ref()).await?;
+                scim = crate::aws::Scim::new(&creds)?;
+                &scim
+            }
+            crate::event::TargetBackend::IdentityStore(ref config) => {
+                identity_store = crate::aws::IdentityStore::new(config.as_ref())?;
+                &identity_store
+            }
+        };
+        log::info!("Creating snapshot of target {}", target.name());
+        let sync_op =
+            crate::sync::SyncOp::new(target, backend, &google, &user_sync_rules, &audit, deadline)
+                .await?;
+        targets.push(sync_op.snapshot().await?);
+    }
+    let snapshot = Snapshot {
+        created: chrono::Utc::now(),
+        targets,
+    };
+
+    let writers = match event.get_snapshot()? {
+        Some(config) => crate::audit::writers(
+            config.s3.as_ref(),
+            config.directory.as_deref(),
+            "application/json",
+        )?,
+        None => Vec::new(),
+    };
+    if writers.is_empty() {
+        report.snapshot = Some(snapshot);
+        return Ok(());
+    }
+    let content = serde_json::to_vec(&snapshot).context("Unable to serialize snapshot")?;
+    let name = format!(
+        "{}-{request_id}.json",
+        snapshot.created.format("%Y%m%dT%H%M%SZ")
+    );
+    for writer in writers {
+        writer.write(&name, &content).await?;
+    }
+    report.snapshot_name = Some(name);
+    Ok(())
+}
+
+/// Computes the changes a full sync would apply to every target of the snapshot.
+/// The changes are applied to an in-memory copy of the target, so neither google
+/// nor AWS are accessed apart from reading the snapshot. Account assignments are
+/// not part of the snapshot and therefore skipped
+pub async fn diff(
+    event: &crate::event::Event,
+    request_id: &str,
+    report: &mut crate::report::Report,
+) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let config = event
+        .get_diff()?
+        .context("diff has to be set in mode Diff")?;
+    let snapshot = load(&config).await?;
+    let audit = crate::audit::AuditLog::new(request_id.into());
+    for target in &snapshot.targets {
+        let google = crate::sync::GoogleState::from_snapshot(event, &target.google)?;
+        let backend = SnapshotBackend::new(&target.aws);
+        let mut sync_op =
+            crate::sync::SyncOp::from_snapshot(target, &backend, &google, &audit).await?;
+        sync_op.sync_groups().await?;
+        sync_op.sync_users().await?;
+        sync_op.sync_associations(None).await?;
+        report.targets.push(sync_op.into_report());
+    }
+    report.changes = audit.take_entries();
+    Ok(())
+}
+
+async fn load(config: &crate::event::DiffConfig) -> anyhow::Result<Snapshot> {
+    use anyhow::{bail, Context};
+
+    let content = match (config.s3.as_ref(), config.file.as_deref()) {
+        (Some(s3), None) => {
+            crate::aws::S3::new(&s3.region)?
+                .get_object(&s3.bucket, &s3.key)
+                .await?
+        }
+        (None, Some(file)) => std::fs::read(file)
+            .with_context(|| format!("Unable to read snapshot from file: {file}"))?,
+        _ => bail!("Exactly one of s3 and file has to be set in diff"),
+    };
+    serde_json::from_slice(&content).with_context(|| format!("Unable to parse snapshot {config}"))
+}
+
+/// Target which is kept in memory and initialized from a snapshot. Like the target,
+/// it does not list unlisted users and groups, but returns them when fetched by name
+#[derive(Debug)]
+struct SnapshotBackend {
+    manager_supported: bool,
+    multiple_emails_supported: bool,
+    listing_complete: bool,
+    state: std::sync::Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    users: Vec<crate::aws::User>,
+    groups: Vec<crate::aws::Group>,
+    unlisted_users: Vec<crate::aws::User>,
+    unlisted_groups: Vec<crate::aws::Group>,
+    /// Ids of the members keyed by the id of the group
+    members: std::collections::HashMap<String, std::collections::HashSet<String>>,
+    /// Number of created users and groups, used to generate their ids
+    created: usize,
+}
+
+impl SnapshotBackend {
+    fn new(aws: &AwsSnapshot) -> Self {
+        let user_ids = aws
+            .users
+            .iter()
+            .chain(&aws.unlisted_users)
+            .filter_map(|u| Some((u.user_name.as_str(), u.id.as_deref()?)))
+            .collect::<std::collections::HashMap<_, _>>();
+        let members = aws
+            .groups
+            .iter()
+            .chain(&aws.unlisted_groups)
+            .filter_map(|g| {
+                let members = aws
+                    .memberships
+                    .get(&g.display_name)?
+                    .iter()
+                    .filter_map(|m| Some(user_ids.get(m.as_str())?.to_string()))
+                    .collect();
+                Some((g.id.clone()?, members))
+            })
+            .collect();
+        Self {
+            manager_supported: aws.manager_supported,
+            multiple_emails_supported: aws.multiple_emails_supported,
+            listing_complete: aws.listing_complete,
+            state: std::sync::Mutex::new(State {
+                users: aws.users.clone(),
+                groups: aws.groups.clone(),
+                unlisted_users: aws.unlisted_users.clone(),
+                unlisted_groups: aws.unlisted_groups.clone(),
+                members,
+                created: 0,
+            }),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[async_trait::async_trait]
+impl crate::aws::Backend for SnapshotBackend {
+    fn is_listing_complete(&self) -> bool {
+        self.listing_complete
+    }
+
+    fn is_manager_supported(&self) -> bool {
+        self.manager_supported
+    }
+
+    fn is_multiple_emails_supported(&self) -> bool {
+        self.multiple_emails_supported
+    }
+
+    async fn list_users(&self) -> anyhow::Result<Vec<crate::aws::User>> {
+        Ok(self.state().users.clone())
+    }
+
+    async fn get_user(&self, user_name: &str) -> anyhow::Result<Option<crate::aws::User>> {
+        let state = self.state();
+        Ok(state
+            .users
+            .iter()
+            .chain(&state.unlisted_users)
+            .find(|u| u.user_name == user_name)
+            .cloned())
+    }
+
+    async fn create_user(
+        &self,
+        mut user: crate::aws::User,
+    ) -> anyhow::Result<Option<crate::aws::User>> {
+        let mut state = self.state();
+        if state
+            .users
+            .iter()
+            .chain(&state.unlisted_users)
+            .any(|u| u.user_name == user.user_name)
+        {
+            return Ok(None);
+        }
+        state.created += 1;
+        user.id = Some(format!("snapshot-{}", state.created));
+        state.users.push(user.clone());
+        drop(state);
+        Ok(Some(user))
+    }
+
+    async fn delete_user(&self, user_id: &str) -> anyhow::Result<()> {
+        let mut state = self.state();
+        state.users.retain(|u| u.id.as_deref() != Some(user_id));
+        state
+            .unlisted_users
+            .retain(|u| u.id.as_deref() != Some(user_id));
+        for members in state.members.values_mut() {
+            let _ = members.remove(user_id);
+        }
+        drop(state);
+        Ok(())
+    }
+
+    async fn set_user_manager(
+        &self,
+        user_id: &str,
+        manager_id: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let mut state = self.state();
+        let State {
+            ref mut users,
+            ref mut unlisted_users,
+            ..
+        } = *state;
+        if let Some(user) = users
+            .iter_mut()
+            .chain(unlisted_users)
+            .find(|u| u.id.as_deref() == Some(user_id))
+        {
+            user.enterprise = Some(crate::aws::EnterpriseUser {
+                manager: manager_id.map(|value| crate::aws::Manager {
+                    value: value.into(),
+                }),
+            });
+        }
+        drop(state);
+        Ok(())
+    }
+
+    async fn list_groups(&self) -> anyhow::Result<Vec<crate::aws::Group>> {
+        Ok(self.state().groups.clone())
+    }
+
+    async fn get_group(&self, display_name: &str) -> anyhow::Result<Option<crate::aws::Group>> {
+        let state = self.state();
+        Ok(state
+            .groups
+            .iter()
+            .chain(&state.unlisted_groups)
+            .find(|g| g.display_name == display_name)
+            .cloned())
+    }
+
+    async fn create_group(
+        &self,
+        mut group: crate::aws::Group,
+    ) -> anyhow::Result<Option<crate::aws::Group>> {
+        let mut state = self.state();
+        if state
+            .groups
+            .iter()
+            .chain(&state.unlisted_groups)
+            .any(|g| g.display_name == group.display_name)
+        {
+            return Ok(None);
+        }
+        state.created += 1;
+        group.id = Some(format!("snapshot-{}", state.created));
+        state.groups.push(group.clone());
+        drop(state);
+        Ok(Some(group))
+    }
+
+    async fn delete_group(&self, group_id: &str) -> anyhow::Result<()> {
+        let mut state = self.state();
+        state.groups.retain(|g| g.id.as_deref() != Some(group_id));
+        state
+            .unlisted_groups
+            .retain(|g| g.id.as_deref() != Some(group_id));
+        let _ = state.members.remove(group_id);
+        drop(state);
+        Ok(())
+    }
+
+    async fn is_group_member(&self, group_id: &str, user_id: &str) -> anyhow::Result<bool> {
+        Ok(self
+            .state()
+            .members
+            .get(group_id)
+            .is_some_and(|m| m.contains(user_id)))
+    }
+
+    async fn list_group_memberships_for_member(
+        &self,
+        user_id: &str,
+    ) -> anyhow::Result<Option<std::collections::HashSet<String>>> {
+        Ok(Some(
+            self.state()
+                .members
+                .iter()
+                .filter(|(_, members)| members.contains(user_id))
+                .map(|(group_id, _)| group_id.clone())
+                .collect(),
+        ))
+    }
+
+    async fn list_group_members(
+        &self,
+        group_id: &str,
+    ) -> anyhow::Result<Option<std::collections::HashSet<String>>> {
+        Ok(Some(
+            self.state()
+                .members
+                .get(group_id)
+                .cloned()
+                .unwrap_or_default(),
+        ))
+    }
+
+    async fn add_group_member(&self, group_id: &str, user_id: &str) -> anyhow::Result<()> {
+        let _ = self
+            .state()
+            .members
+            .entry(group_id.into())
+            .or_default()
+            .insert(user_id.into());
+        Ok(())
+    }
+
+    async fn remove_group_member(&self, group_id: &str, user_id: &str) -> anyhow::Result<()> {
+        if let Some(members) = self.state().members.get_mut(group_id) {
+            let _ = members.remove(user_id);
+        }
+        Ok(())
+    }
+}
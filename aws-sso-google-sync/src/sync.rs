@@ -1,11 +1,421 @@
 const AWS_SSO_USER_LIMIT: usize = 50;
 const AWS_SSO_GROUP_LIMIT: usize = 50;
+/// Number of membership changes after which they are applied while syncing the
+/// memberships, so that the remaining changes are applied before the deadline
+const MEMBERSHIP_BATCH_SIZE: usize = 100;
 
 pub type Lookup<T> = std::collections::HashMap<String, T>;
 
+/// Returns the member options of a group. The first override matching the group email
+/// is merged into the default options
+pub fn group_member_options(
+    options: &crate::event::GroupMemberOptions,
+    overrides: &[(regex::Regex, crate::event::GroupMemberOptions)],
+    email: &str,
+) -> crate::event::GroupMemberOptions {
+    overrides
+        .iter()
+        .find(|(r, _)| r.is_match(email))
+        .map_or_else(|| options.clone(), |(_, o)| options.merge(o))
+}
+
+/// Tenant together with the directory to query it
+pub struct Source<'a> {
+    pub(crate) tenant: &'a crate::event::Tenant<'a>,
+    pub(crate) directory: &'a dyn crate::directory::DirectorySource,
+}
+
+/// Users, groups and memberships of all google tenants which should exist in AWS SSO
+pub struct GoogleState<'a> {
+    sources: &'a [Source<'a>],
+    pub(crate) normalization: crate::event::IdentityNormalization,
+    pub(crate) user_name: crate::event::UserNameConfig,
+    pub(crate) concurrency: crate::event::ConcurrencyConfig,
+
+    group_lookup: Lookup<crate::google::Group>,
+    user_lookup: Lookup<crate::google::User>,
+    /// User names of the managers keyed by the user name of the user
+    managers: Lookup<String>,
+    group_assoc: Lookup<std::collections::HashSet<String>>,
+    user_deleted_lookup: tokio::sync::OnceCell<Lookup<crate::google::User>>,
+}
+
+impl<'a> GoogleState<'a> {
+    pub(crate) async fn new(
+        event: &crate::event::Event,
+        sources: &'a [Source<'a>],
+        report: &mut crate::report::Report,
+    ) -> anyhow::Result<GoogleState<'a>> {
+        let normalization = event.get_identity_normalization()?;
+        let user_name = event.get_user_name()?;
+        let concurrency = event.get_concurrency()?;
+        let mut group_lookup = Lookup::new();
+        let mut user_lookup = Lookup::new();
+        let mut group_assoc = Lookup::new();
+        let mut external_members = Vec::new();
+        let mut group_owners = Lookup::new();
+        let mut user_owners = Lookup::new();
+        for source in sources {
+            let users = Self::get_google_user_lookup(source, &normalization).await?;
+            let _ = Self::merge_lookup(
+                &mut user_lookup,
+                &mut user_owners,
+                users,
+                source.tenant,
+                crate::report::PrincipalKind::User,
+                report,
+            );
+
+            let groups = Self::get_google_group_lookup(source, &normalization).await?;
+            let groups = Self::merge_lookup(
+                &mut group_lookup,
+                &mut group_owners,
+                groups,
+                source.tenant,
+                crate::report::PrincipalKind::Group,
+                report,
+            );
+            let groups = groups
+                .iter()
+                .filter_map(|g| group_lookup.get(g))
+                .collect::<Vec<_>>();
+            let (assoc, members) = Self::get_google_group_assoc_lookup(
+                event,
+                source,
+                &groups,
+                &normalization,
+                concurrency,
+            )
+            .await?;
+            group_assoc.extend(assoc);
+            external_members.extend(members.into_iter().map(|(g, m)| (source, g, m)));
+        }
+        if normalization.resolve_aliases() {
+            let aliases = Self::get_alias_lookup(&user_lookup, &normalization);
+            for members in group_assoc.values_mut() {
+                *members = members
+                    .drain()
+                    .map(|m| aliases.get(&m).cloned().unwrap_or(m))
+                    .collect();
+            }
+            external_members
+                .retain(|(_, _, m)| !aliases.contains_key(&normalization.normalize(&m.email)));
+        }
+        Self::add_external_members(
+            event,
+            sources,
+            external_members,
+            &mut user_lookup,
+            &normalization,
+            report,
+        )?;
+        let (user_lookup, managers) =
+            Self::apply_user_names(user_lookup, &mut group_assoc, &user_name, &normalization);
+
+        Ok(Self {
+            sources,
+            normalization,
+            user_name,
+            concurrency,
+
+            group_lookup,
+            user_lookup,
+            managers,
+            group_assoc,
+            user_deleted_lookup: tokio::sync::OnceCell::new(),
+        })
+    }
+
+    /// Creates the state from a snapshot. Normalization, user names and concurrency
+    /// are taken from the event, so the snapshot should be used with the parameters
+    /// it was created with
+    pub(crate) fn from_snapshot(
+        event: &crate::event::Event,
+        snapshot: &crate::snapshot::GoogleSnapshot,
+    ) -> anyhow::Result<GoogleState<'static>> {
+        Ok(GoogleState {
+            sources: &[],
+            normalization: event.get_identity_normalization()?,
+            user_name: event.get_user_name()?,
+            concurrency: event.get_concurrency()?,
+
+            group_lookup: snapshot.groups.clone().into_iter().collect(),
+            user_lookup: snapshot.users.clone().into_iter().collect(),
+            managers: snapshot.managers.clone().into_iter().collect(),
+            group_assoc: snapshot
+                .memberships
+                .iter()
+                .map(|(g, members)| (g.clone(), members.iter().cloned().collect()))
+                .collect(),
+            user_deleted_lookup: tokio::sync::OnceCell::new_with(Some(
+                snapshot.deleted_users.clone().into_iter().collect(),
+            )),
+        })
+    }
+
+    /// Moves all entries into `lookup` and returns the keys of the moved entries.
+    /// Entries which were already added by a previous tenant are skipped and reported
+    fn merge_lookup<T>(
+        lookup: &mut Lookup<T>,
+        owners: &mut Lookup<String>,
+        entries: Lookup<T>,
+        tenant: &crate::event::Tenant<'_>,
+        kind: crate::report::PrincipalKind,
+        report: &mut crate::report::Report,
+    ) -> Vec<String> {
+        let mut merged = Vec::new();
+        for (key, entry) in entries {
+            if let Some(owner) = owners.get(&key) {
+                log::warn!(
+                    "{:?} {} exists in google tenant {} and {}. Skipping the latter.",
+                    kind,
+                    key,
+                    owner,
+                    tenant.name()
+                );
+                report.conflicts.push(crate::report::Conflict {
+                    kind,
+                    name: key,
+                    used_tenant: owner.clone(),
+                    skipped_tenant: tenant.name().into(),
+                });
+                continue;
+            }
+            let _ = owners.insert(key.clone(), tenant.name().into());
+            let _ = lookup.insert(key.clone(), entry);
+            merged.push(key);
+        }
+        merged
+    }
+
+    /// Returns the primary email of every user keyed by each of its aliases
+    fn get_alias_lookup(
+        user_lookup: &Lookup<crate::google::User>,
+        normalization: &crate::event::IdentityNormalization,
+    ) -> Lookup<String> {
+        user_lookup
+            .iter()
+            .flat_map(|(key, user)| {
+                user.aliases
+                    .iter()
+                    .map(|alias| (normalization.normalize(alias), key.clone()))
+            })
+            .filter(|(alias, _)| !user_lookup.contains_key(alias))
+            .collect()
+    }
+
+    /// Rekeys the users and group members by their AWS SSO user name, so they can be
+    /// compared with the users of the target. Users without a user name and users
+    /// whose user name is already taken by another user are skipped. Returns the users
+    /// together with the user names of their managers
+    fn apply_user_names(
+        user_lookup: Lookup<crate::google::User>,
+        group_assoc: &mut Lookup<std::collections::HashSet<String>>,
+        user_name: &crate::event::UserNameConfig,
+        normalization: &crate::event::IdentityNormalization,
+    ) -> (Lookup<crate::google::User>, Lookup<String>) {
+        let mut entries = user_lookup.into_iter().collect::<Vec<_>>();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut names = Lookup::new();
+        let mut users = Lookup::new();
+        for (key, user) in entries {
+            let name = match user_name.get(&user) {
+                Some(name) => normalization.normalize(&name),
+                None => {
+                    log::warn!(
+                        "Skipping user {} as the configured user name source has no value.",
+                        user.primary_email
+                    );
+                    continue;
+                }
+            };
+            if users.contains_key(&name) {
+                log::warn!(
+                    "User name {} of user {} is already used by another user. Skipping the latter.",
+                    name,
+                    user.primary_email
+                );
+                continue;
+            }
+            let _ = names.insert(key, name.clone());
+            let _ = users.insert(name, user);
+        }
+        for members in group_assoc.values_mut() {
+            *members = members
+                .drain()
+                .filter_map(|m| names.get(&m).cloned())
+                .collect();
+        }
+        let managers = users
+            .iter()
+            .filter_map(|(name, user)| {
+                let manager = names.get(&normalization.normalize(user.manager()?))?;
+                Some((name.clone(), manager.clone()))
+            })
+            .collect();
+        (users, managers)
+    }
+
+    async fn get_google_group_lookup(
+        source: &Source<'_>,
+        normalization: &crate::event::IdentityNormalization,
+    ) -> anyhow::Result<Lookup<crate::google::Group>> {
+        Ok(source
+            .directory
+            .list_groups(
+                source
+                    .tenant
+                    .google_api_query_for_groups
+                    .as_ref()
+                    .map(AsRef::as_ref),
+            )
+            .await?
+            .into_iter()
+            .map(|g| (normalization.normalize(&g.email), g))
+            .filter(|(g, _)| source.tenant.is_group_included(g))
+            .collect::<Lookup<_>>())
+    }
+
+    async fn get_google_user_lookup(
+        source: &Source<'_>,
+        normalization: &crate::event::IdentityNormalization,
+    ) -> anyhow::Result<Lookup<crate::google::User>> {
+        Ok(source
+            .directory
+            .list_users(
+                source
+                    .tenant
+                    .google_api_query_for_users
+                    .as_ref()
+                    .map(AsRef::as_ref),
+            )
+            .await?
+            .into_iter()
+            .map(|g| (normalization.normalize(&g.primary_email), g))
+            .filter(|(g, _)| source.tenant.is_user_included(g))
+            .collect::<Lookup<_>>())
+    }
+
+    async fn get_google_group_assoc_lookup(
+        event: &crate::event::Event,
+        source: &Source<'_>,
+        groups: &[&crate::google::Group],
+        normalization: &crate::event::IdentityNormalization,
+        concurrency: crate::event::ConcurrencyConfig,
+    ) -> anyhow::Result<(
+        Lookup<std::collections::HashSet<String>>,
+        Vec<(String, crate::google::Member)>,
+    )> {
+        use futures::{StreamExt, TryStreamExt};
+
+        let member_options = event.get_group_member_options()?;
+        let member_options_overrides = event.get_group_member_options_overrides()?;
+
+        // The futures are created upfront, as closures in the stream are not `Send`
+        let requests = groups
+            .iter()
+            .map(|group| {
+                let key = normalization.normalize(&group.email);
+                let options =
+                    group_member_options(&member_options, &member_options_overrides, &key);
+                async move {
+                    let members = source
+                        .directory
+                        .list_group_members(&group.id, &options)
+                        .await?;
+                    anyhow::Ok((group, key, members))
+                }
+            })
+            .collect::<Vec<_>>();
+        let groups = futures::stream::iter(requests)
+            .buffer_unordered(concurrency.google())
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut lookup = Lookup::new();
+        let mut external_members = Vec::new();
+        for (group, key, members) in groups {
+            let mut emails = std::collections::HashSet::new();
+            for member in members {
+                if !source.directory.is_internal(&member.email) {
+                    external_members.push((group.email.clone(), member.clone()));
+                }
+                let _ = emails.insert(normalization.normalize(&member.email));
+            }
+            let _ = lookup.insert(key, emails);
+        }
+        Ok((lookup, external_members))
+    }
+
+    fn add_external_members(
+        event: &crate::event::Event,
+        sources: &[Source<'_>],
+        external_members: Vec<(&Source<'_>, String, crate::google::Member)>,
+        google_user_lookup: &mut Lookup<crate::google::User>,
+        normalization: &crate::event::IdentityNormalization,
+        report: &mut crate::report::Report,
+    ) -> anyhow::Result<()> {
+        let external_members_config = event.get_external_members()?;
+
+        for (source, group, member) in external_members {
+            let key = normalization.normalize(&member.email);
+            if google_user_lookup.contains_key(&key)
+                || sources
+                    .iter()
+                    .any(|s| s.directory.is_internal(&member.email))
+                || !source.tenant.is_user_included(&key)
+            {
+                continue;
+            }
+            if !external_members_config.is_included(&member.email) {
+                log::info!(
+                    "Skipping external member {} of group {}",
+                    member.email,
+                    group
+                );
+                report.skipped_members.push(crate::report::SkippedMember {
+                    group,
+                    email: member.email,
+                    reason: crate::report::SkipReason::ExternalMember,
+                });
+                continue;
+            }
+            let _ = google_user_lookup
+                .entry(key)
+                .or_insert_with(|| crate::google::User::from_external_member(&member));
+        }
+        Ok(())
+    }
+
+    /// Returns all deleted users of all google tenants. They are only fetched once,
+    /// even if requested for multiple targets
+    async fn get_google_user_deleted_lookup(&self) -> anyhow::Result<&Lookup<crate::google::User>> {
+        self.user_deleted_lookup
+            .get_or_try_init(|| async {
+                let mut lookup = Lookup::new();
+                for source in self.sources {
+                    lookup.extend(
+                        source
+                            .directory
+                            .list_deleted_users()
+                            .await?
+                            .into_iter()
+                            .filter_map(|g| {
+                                let name = self.user_name.get(&g)?;
+                                Some((self.normalization.normalize(&name), g))
+                            }),
+                    );
+                }
+                Ok(lookup)
+            })
+            .await
+    }
+}
+
 pub struct SyncOp<'a> {
-    scim: &'a crate::aws::Scim<'a>,
-    gadmin: &'a crate::google::Admin<'a>,
+    backend: &'a dyn crate::aws::Backend,
+    google: &'a GoogleState<'a>,
 
     aws_group_lookup: Lookup<crate::aws::Group>,
     aws_user_lookup: Lookup<crate::aws::User>,
@@ -13,26 +423,50 @@ pub struct SyncOp<'a> {
     google_group_lookup: Lookup<crate::google::Group>,
     google_user_lookup: Lookup<crate::google::User>,
     google_group_assoc: Lookup<std::collections::HashSet<String>>,
+    /// Rule which decided whether a google user is synced, keyed by its user name
+    user_rules: Lookup<String>,
+
+    audit: &'a crate::audit::AuditLog,
+    deadline: crate::continuation::Deadline,
+    interrupted: bool,
+    last_user: Option<String>,
+
+    report: crate::report::TargetReport,
 }
 
 impl<'a> SyncOp<'a> {
     pub(crate) async fn new(
-        event: &'a crate::event::Event,
-        scim: &'a crate::aws::Scim<'a>,
-        gadmin: &'a crate::google::Admin<'a>,
+        target: &crate::event::Target<'_>,
+        backend: &'a dyn crate::aws::Backend,
+        google: &'a GoogleState<'a>,
+        user_sync_rules: &crate::event::UserSyncRules,
+        audit: &'a crate::audit::AuditLog,
+        deadline: crate::continuation::Deadline,
     ) -> anyhow::Result<SyncOp<'a>> {
-        let aws_user_lookup = Self::get_aws_user_lookup(scim).await?;
-        let aws_group_lookup = Self::get_aws_group_lookup(scim).await?;
-        Self::print_warning(aws_user_lookup.len(), aws_group_lookup.len());
+        let aws_user_lookup = Self::get_aws_user_lookup(backend, &google.normalization).await?;
+        let aws_group_lookup = Self::get_aws_group_lookup(backend, &google.normalization).await?;
+        if !backend.is_listing_complete() {
+            Self::print_warning(aws_user_lookup.len(), aws_group_lookup.len());
+        }
 
-        let google_group_lookup = Self::get_google_group_lookup(event, gadmin).await?;
-        let google_user_lookup = Self::get_google_user_lookup(event, gadmin).await?;
-        let google_group_assoc =
-            Self::get_google_group_assoc_lookup(gadmin, &google_group_lookup).await?;
+        let google_group_lookup = google
+            .group_lookup
+            .iter()
+            .filter(|(g, _)| target.is_group_included(g))
+            .map(|(g, group)| (g.clone(), group.clone()))
+            .collect::<Lookup<_>>();
+        let mut google_group_assoc = google
+            .group_assoc
+            .iter()
+            .filter(|(g, _)| google_group_lookup.contains_key(*g))
+            .map(|(g, members)| (g.clone(), members.clone()))
+            .collect();
+        let (google_user_lookup, user_rules) =
+            Self::apply_user_sync_rules(google, user_sync_rules, &mut google_group_assoc);
 
         Ok(Self {
-            scim,
-            gadmin,
+            backend,
+            google,
 
             aws_group_lookup,
             aws_user_lookup,
@@ -40,9 +474,201 @@ impl<'a> SyncOp<'a> {
             google_group_lookup,
             google_user_lookup,
             google_group_assoc,
+            user_rules,
+
+            audit,
+            deadline,
+            interrupted: false,
+            last_user: None,
+
+            report: crate::report::TargetReport::new(target.name()),
+        })
+    }
+
+    /// Creates the sync of a target from a snapshot. The google state of the snapshot
+    /// is already filtered, so it is used as is
+    pub(crate) async fn from_snapshot(
+        snapshot: &crate::snapshot::TargetSnapshot,
+        backend: &'a dyn crate::aws::Backend,
+        google: &'a GoogleState<'a>,
+        audit: &'a crate::audit::AuditLog,
+    ) -> anyhow::Result<SyncOp<'a>> {
+        Ok(Self {
+            backend,
+            google,
+
+            aws_group_lookup: Self::get_aws_group_lookup(backend, &google.normalization).await?,
+            aws_user_lookup: Self::get_aws_user_lookup(backend, &google.normalization).await?,
+
+            google_group_lookup: google.group_lookup.clone(),
+            google_user_lookup: google.user_lookup.clone(),
+            google_group_assoc: google.group_assoc.clone(),
+            user_rules: snapshot.google.rules.clone().into_iter().collect(),
+
+            audit,
+            deadline: crate::continuation::Deadline::new(0, None),
+            interrupted: false,
+            last_user: None,
+
+            report: crate::report::TargetReport::new(&snapshot.target),
+        })
+    }
+
+    pub(crate) fn into_report(self) -> crate::report::TargetReport {
+        self.report
+    }
+
+    /// Returns the users, groups and memberships of google and of the target as seen
+    /// by the sync. Memberships of the target are listed per group, or checked for
+    /// every user if the target is not able to list them
+    pub(crate) async fn snapshot(&self) -> anyhow::Result<crate::snapshot::TargetSnapshot> {
+        let mut google = crate::snapshot::GoogleSnapshot {
+            users: self.google_user_lookup.clone().into_iter().collect(),
+            groups: self.google_group_lookup.clone().into_iter().collect(),
+            memberships: self
+                .google_group_assoc
+                .iter()
+                .map(|(g, members)| (g.clone(), members.iter().cloned().collect()))
+                .collect(),
+            managers: self
+                .google
+                .managers
+                .iter()
+                .filter(|(user, _)| self.google_user_lookup.contains_key(*user))
+                .map(|(user, manager)| (user.clone(), manager.clone()))
+                .collect(),
+            rules: self.user_rules.clone().into_iter().collect(),
+            deleted_users: std::collections::BTreeMap::new(),
+        };
+        let listing_complete = self.backend.is_listing_complete();
+        let (unlisted_users, unlisted_groups) = if listing_complete {
+            (Vec::new(), Vec::new())
+        } else {
+            self.snapshot_unlisted(&mut google).await?
+        };
+
+        let user_names = self
+            .aws_user_lookup
+            .values()
+            .chain(&unlisted_users)
+            .filter_map(|u| Some((u.id.clone()?, u.user_name.clone())))
+            .collect::<Lookup<_>>();
+        let mut memberships = std::collections::BTreeMap::new();
+        for group in self.aws_group_lookup.values().chain(&unlisted_groups) {
+            let group_id = match group.id {
+                Some(ref v) => v,
+                None => continue,
+            };
+            let members = match self.backend.list_group_members(group_id).await? {
+                Some(members) => members
+                    .iter()
+                    .filter_map(|m| user_names.get(m).cloned())
+                    .collect(),
+                None => {
+                    let mut members = std::collections::BTreeSet::new();
+                    for (user_id, user_name) in &user_names {
+                        if self.backend.is_group_member(group_id, user_id).await? {
+                            let _ = members.insert(user_name.clone());
+                        }
+                    }
+                    members
+                }
+            };
+            let _ = memberships.insert(group.display_name.clone(), members);
+        }
+        let mut users = self.aws_user_lookup.values().cloned().collect::<Vec<_>>();
+        users.sort_by(|a, b| a.user_name.cmp(&b.user_name));
+        let mut groups = self.aws_group_lookup.values().cloned().collect::<Vec<_>>();
+        groups.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+
+        Ok(crate::snapshot::TargetSnapshot {
+            target: self.report.target.clone(),
+            google,
+            aws: crate::snapshot::AwsSnapshot {
+                manager_supported: self.backend.is_manager_supported(),
+                multiple_emails_supported: self.backend.is_multiple_emails_supported(),
+                listing_complete,
+                users,
+                groups,
+                unlisted_users,
+                unlisted_groups,
+                memberships,
+            },
+        })
+    }
+
+    /// Fetches the users and groups of google which are missing in the incomplete listing
+    /// of the target by name. If the sync looks up users deleted in google, they are added
+    /// to the snapshot of google and fetched as well
+    async fn snapshot_unlisted(
+        &self,
+        google: &mut crate::snapshot::GoogleSnapshot,
+    ) -> anyhow::Result<(Vec<crate::aws::User>, Vec<crate::aws::Group>)> {
+        let mut user_names = self
+            .google_user_lookup
+            .iter()
+            .filter(|(id, _)| !self.aws_user_lookup.contains_key(*id))
+            .filter_map(|(_, user)| self.google.user_name.get(user))
+            .collect::<Vec<_>>();
+        if self.aws_user_lookup.len() >= AWS_SSO_USER_LIMIT {
+            for (id, user) in self.google.get_google_user_deleted_lookup().await? {
+                if self.google_user_lookup.contains_key(id) {
+                    continue;
+                }
+                if let Some(user_name) = self.google.user_name.get(user) {
+                    user_names.push(user_name);
+                }
+                let _ = google.deleted_users.insert(id.clone(), user.clone());
+            }
+        }
+        let mut users = Vec::new();
+        for user_name in user_names {
+            if let Some(user) = self.backend.get_user(&user_name).await? {
+                users.push(user);
+            }
+        }
+        users.sort_by(|a, b| a.user_name.cmp(&b.user_name));
+        users.dedup_by(|a, b| a.user_name == b.user_name);
+
+        let mut groups = Vec::new();
+        for (_, group) in self
+            .google_group_lookup
+            .iter()
+            .filter(|(id, _)| !self.aws_group_lookup.contains_key(*id))
+        {
+            if let Some(group) = self
+                .backend
+                .get_group(&Self::create_group(group).display_name)
+                .await?
+            {
+                groups.push(group);
+            }
+        }
+        groups.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+        Ok((users, groups))
+    }
+
+    /// Returns the progress of the current phase if it was interrupted by the deadline
+    pub(crate) fn interruption(
+        &self,
+        phase: crate::continuation::Phase,
+    ) -> Option<crate::continuation::Progress> {
+        self.interrupted.then(|| crate::continuation::Progress {
+            phase,
+            last_user: self.last_user.clone(),
         })
     }
 
+    /// Checks whether the deadline is reached and marks the sync as interrupted if so.
+    /// Takes the fields instead of `self`, as it is called while iterating over lookups
+    fn is_interrupted(deadline: crate::continuation::Deadline, interrupted: &mut bool) -> bool {
+        if !*interrupted && deadline.is_reached() {
+            log::info!("Interrupting the sync as the deadline of the lambda is reached");
+            *interrupted = true;
+        }
+        *interrupted
+    }
+
     fn print_warning(aws_user_len: usize, aws_group_len: usize) {
         if aws_user_len >= AWS_SSO_USER_LIMIT {
             log::warn!("There are more then 50 users setup in AWS SSO.");
@@ -59,114 +685,69 @@ impl<'a> SyncOp<'a> {
     }
 
     async fn get_aws_group_lookup(
-        scim: &crate::aws::Scim<'_>,
+        backend: &dyn crate::aws::Backend,
+        normalization: &crate::event::IdentityNormalization,
     ) -> anyhow::Result<Lookup<crate::aws::Group>> {
-        Ok(scim
+        Ok(backend
             .list_groups()
             .await?
             .into_iter()
-            .map(|g| (g.display_name.clone(), g))
+            .map(|g| (normalization.normalize(&g.display_name), g))
             .collect())
     }
 
     async fn get_aws_user_lookup(
-        scim: &crate::aws::Scim<'_>,
+        backend: &dyn crate::aws::Backend,
+        normalization: &crate::event::IdentityNormalization,
     ) -> anyhow::Result<Lookup<crate::aws::User>> {
-        Ok(scim
+        Ok(backend
             .list_users()
             .await?
             .into_iter()
-            .map(|g| (g.user_name.clone(), g))
+            .map(|g| (normalization.normalize(&g.user_name), g))
             .collect())
     }
 
-    async fn get_google_group_lookup(
-        event: &crate::event::Event,
-        gadmin: &crate::google::Admin<'_>,
-    ) -> anyhow::Result<Lookup<crate::google::Group>> {
-        let ignore_groups_regex = event.get_ignore_groups_regexes()?;
-        let include_groups_regex = event.get_include_groups_regexes()?;
-
-        Ok(gadmin
-            .list_groups(
-                event
-                    .get_google_api_query_for_groups()
-                    .as_ref()
-                    .map(AsRef::as_ref),
-            )
-            .await?
-            .into_iter()
-            .map(|g| (g.email.clone(), g))
-            .filter(|(g, _)| {
-                ignore_groups_regex
-                    .as_ref()
-                    .map_or(true, |r| !r.is_match(g))
-            })
-            .filter(|(g, _)| {
-                include_groups_regex
-                    .as_ref()
-                    .map_or(true, |r| r.is_match(g))
-            })
-            .collect::<Lookup<_>>())
-    }
-
-    async fn get_google_user_deleted_lookup(
-        gadmin: &crate::google::Admin<'_>,
-    ) -> anyhow::Result<Lookup<crate::google::User>> {
-        Ok(gadmin
-            .list_users(None, true)
-            .await?
-            .into_iter()
-            .map(|g| (g.primary_email.clone(), g))
-            .collect::<Lookup<_>>())
-    }
-
-    async fn get_google_user_lookup(
-        event: &crate::event::Event,
-        gadmin: &crate::google::Admin<'_>,
-    ) -> anyhow::Result<Lookup<crate::google::User>> {
-        let ignore_users_regex = event.get_ignore_users_regexes()?;
-        let include_users_regex = event.get_include_users_regexes()?;
-        Ok(gadmin
-            .list_users(
-                event
-                    .get_google_api_query_for_users()
-                    .as_ref()
-                    .map(AsRef::as_ref),
-                false,
-            )
-            .await?
-            .into_iter()
-            .map(|g| (g.primary_email.clone(), g))
-            .filter(|(g, _)| ignore_users_regex.as_ref().map_or(true, |r| !r.is_match(g)))
-            .filter(|(g, _)| include_users_regex.as_ref().map_or(true, |r| r.is_match(g)))
-            .collect::<Lookup<_>>())
-    }
-
-    fn modify_google_user_lookup_by_membership(&mut self) {
-        let google_user_lookup = &mut self.google_user_lookup;
-
-        let mut users = Lookup::new();
-        for members in self.google_group_assoc.values() {
-            users.extend(
-                members
-                    .iter()
-                    .filter_map(|member| google_user_lookup.remove_entry(member)),
-            );
+    /// Returns the google users which are synced according to the sync strategie and
+    /// its overrides together with the rule which decided it for every user. Excluded
+    /// users are removed from the memberships of the target as well
+    fn apply_user_sync_rules(
+        google: &GoogleState<'_>,
+        user_sync_rules: &crate::event::UserSyncRules,
+        google_group_assoc: &mut Lookup<std::collections::HashSet<String>>,
+    ) -> (Lookup<crate::google::User>, Lookup<String>) {
+        let mut user_groups: Lookup<Vec<&str>> = Lookup::new();
+        for (group, members) in &google.group_assoc {
+            for member in members {
+                user_groups.entry(member.clone()).or_default().push(group);
+            }
+        }
+        let members = google_group_assoc
+            .values()
+            .flatten()
+            .collect::<std::collections::HashSet<_>>();
+        let mut google_user_lookup = Lookup::new();
+        let mut user_rules = Lookup::new();
+        for (key, user) in &google.user_lookup {
+            let groups = user_groups.get(key).map_or(&[][..], Vec::as_slice);
+            let email = google.normalization.normalize(&user.primary_email);
+            let (synced, rule) =
+                user_sync_rules.evaluate(user, &email, groups, members.contains(key));
+            if synced {
+                let _ = google_user_lookup.insert(key.clone(), user.clone());
+            }
+            let _ = user_rules.insert(key.clone(), rule);
+        }
+        for members in google_group_assoc.values_mut() {
+            members.retain(|m| {
+                google_user_lookup.contains_key(m) || !google.user_lookup.contains_key(m)
+            });
         }
-        self.google_user_lookup = users;
+        (google_user_lookup, user_rules)
     }
 
-    async fn get_google_group_assoc_lookup(
-        gadmin: &crate::google::Admin<'_>,
-        google_group_lookup: &Lookup<crate::google::Group>,
-    ) -> anyhow::Result<Lookup<std::collections::HashSet<String>>> {
-        let mut lookup = Lookup::new();
-        for group in google_group_lookup.values() {
-            let members = gadmin.list_group_members(&group.id).await?;
-            let _ = lookup.insert(group.email.clone(), members);
-        }
-        Ok(lookup)
+    fn audit(&self, change: crate::audit::Change) {
+        self.audit.record(&self.report.target, change);
     }
 
     pub(crate) async fn sync_groups(&mut self) -> anyhow::Result<()> {
@@ -180,65 +761,119 @@ impl<'a> SyncOp<'a> {
             .aws_group_lookup
             .iter()
             .filter(|(id, _)| !self.google_group_lookup.contains_key(*id))
-            .filter_map(|(id, u)| {
-                Some((id.clone(), u.display_name.clone(), u.id.as_ref()?.clone()))
-            })
+            .filter_map(|(id, g)| Some((id.clone(), g.id.as_ref()?.clone())))
             .collect::<Vec<_>>();
-        for (id, display_name, aws_id) in to_delete {
-            log::info!("Deleting group: {}", display_name);
-            self.scim.delete_group(&aws_id).await?;
-            let _ = self.aws_group_lookup.remove(&id);
+        for (id, aws_id) in to_delete {
+            let group = match self.aws_group_lookup.remove(&id) {
+                Some(v) => v,
+                None => continue,
+            };
+            log::info!("Deleting group: {}", group.display_name);
+            self.backend.delete_group(&aws_id).await?;
+            self.audit(
+                crate::audit::Change::new(crate::audit::Action::DeleteGroup, &group.display_name)
+                    .before(&group),
+            );
+            self.report.deleted_groups += 1;
         }
         Ok(())
     }
 
     async fn add_groups(&mut self) -> anyhow::Result<()> {
-        let to_remove = self
+        use futures::StreamExt;
+
+        if Self::is_interrupted(self.deadline, &mut self.interrupted) {
+            return Ok(());
+        }
+        let (backend, deadline, audit) = (self.backend, self.deadline, self.audit);
+        let target = self.report.target.as_str();
+        // Each group is recorded as soon as it is created, so that it is audited
+        // even if the creation of another group fails
+        let requests = self
             .google_group_lookup
             .iter()
             .filter(|(id, _)| self.aws_group_lookup.get(*id).is_none())
-            .map(|(_, group)| group)
+            .map(|(_, group)| async move {
+                if deadline.is_reached() {
+                    return Ok(None);
+                }
+                let (group, created) =
+                    Self::create_backend_group(backend, Self::create_group(group)).await?;
+                if created {
+                    audit.record(
+                        target,
+                        crate::audit::Change::new(
+                            crate::audit::Action::CreateGroup,
+                            &group.display_name,
+                        )
+                        .after(&group),
+                    );
+                }
+                anyhow::Ok(Some((group, created)))
+            })
             .collect::<Vec<_>>();
-        for g_group in to_remove {
-            log::info!("Creating group: {}", g_group.email);
-            let group = Self::create_group(g_group);
-            let group = match self.scim.create_group(group).await? {
-                Some(g) => g,
+        let mut created =
+            futures::stream::iter(requests).buffer_unordered(self.google.concurrency.aws());
+        while let Some(group) = created.next().await {
+            let (group, created) = match group? {
+                Some(v) => v,
                 None => {
-                    log::info!("Group already exists - fetching instead");
-                    self.scim.get_group(&g_group.email).await?
+                    let _ = Self::is_interrupted(self.deadline, &mut self.interrupted);
+                    continue;
                 }
             };
-            let _ = self
-                .aws_group_lookup
-                .insert(group.display_name.clone(), group);
+            let _ = self.aws_group_lookup.insert(
+                self.google.normalization.normalize(&group.display_name),
+                group,
+            );
+            if created {
+                self.report.created_groups += 1;
+            }
         }
         Ok(())
     }
 
-    fn create_group(group: &crate::google::Group) -> crate::aws::Group {
+    pub(crate) fn create_group(group: &crate::google::Group) -> crate::aws::Group {
         crate::aws::Group {
             id: None,
             display_name: group.email.to_owned(),
         }
     }
 
-    pub(crate) async fn sync_users(
-        &mut self,
-        sync_strategie: crate::event::SyncStrategie,
-    ) -> anyhow::Result<()> {
-        let require_advanced = self.aws_user_lookup.len() >= AWS_SSO_USER_LIMIT;
-        match sync_strategie {
-            crate::event::SyncStrategie::AllUsers => {}
-            crate::event::SyncStrategie::GroupMembersOnly => {
-                self.modify_google_user_lookup_by_membership()
+    /// Creates the group in the backend or fetches it if it already exists.
+    /// Returns whether the group was created
+    pub(crate) async fn create_backend_group(
+        backend: &dyn crate::aws::Backend,
+        group: crate::aws::Group,
+    ) -> anyhow::Result<(crate::aws::Group, bool)> {
+        use anyhow::Context;
+
+        log::info!("Creating group: {}", group.display_name);
+        let display_name = group.display_name.clone();
+        match backend.create_group(group).await? {
+            Some(g) => Ok((g, true)),
+            None => {
+                log::info!("Group already exists - fetching instead");
+                let group = backend
+                    .get_group(&display_name)
+                    .await?
+                    .with_context(|| format!("Unable to find group with name: {}", display_name))?;
+                Ok((group, false))
             }
         }
+    }
+
+    pub(crate) async fn sync_users(&mut self) -> anyhow::Result<()> {
+        let require_advanced =
+            !self.backend.is_listing_complete() && self.aws_user_lookup.len() >= AWS_SSO_USER_LIMIT;
         self.delete_users_simple().await?;
         if require_advanced {
             self.delete_users_advanced().await?;
         }
         self.add_users().await?;
+        if !self.interrupted {
+            self.sync_managers().await?;
+        }
         Ok(())
     }
 
@@ -247,124 +882,560 @@ impl<'a> SyncOp<'a> {
             .aws_user_lookup
             .iter()
             .filter(|(id, _)| !self.google_user_lookup.contains_key(*id))
-            .filter_map(|(id, u)| Some((id.clone(), u.user_name.clone(), u.id.as_ref()?.clone())))
+            .filter_map(|(id, u)| Some((id.clone(), u.id.as_ref()?.clone())))
             .collect::<Vec<_>>();
-        for (id, user_name, aws_id) in to_delete {
-            log::info!("Deleting user: {}", user_name);
-            self.scim.delete_user(&aws_id).await?;
-            let _ = self.aws_user_lookup.remove(&id);
+        for (id, aws_id) in to_delete {
+            let user = match self.aws_user_lookup.remove(&id) {
+                Some(v) => v,
+                None => continue,
+            };
+            log::info!("Deleting user: {}", user.user_name);
+            self.backend.delete_user(&aws_id).await?;
+            let rule = self
+                .user_rules
+                .get(&id)
+                .map_or(crate::audit::RULE_MISSING_IN_GOOGLE, String::as_str);
+            self.audit(
+                crate::audit::Change::new(crate::audit::Action::DeleteUser, &user.user_name)
+                    .before(&user)
+                    .rule(Some(rule)),
+            );
+            self.report.deleted_users += 1;
         }
         Ok(())
     }
 
     async fn delete_users_advanced(&mut self) -> anyhow::Result<()> {
-        let google_user_active_lookup = Self::get_google_user_deleted_lookup(self.gadmin).await?;
-        for (user_id, user) in google_user_active_lookup {
-            if self.google_user_lookup.contains_key(&user_id) {
+        let google_user_deleted_lookup = self.google.get_google_user_deleted_lookup().await?;
+        for (user_id, user) in google_user_deleted_lookup {
+            if self.google_user_lookup.contains_key(user_id) {
                 continue;
             }
-            if let Some(user_id) = self
-                .scim
-                .get_user(&user.primary_email)
-                .await?
-                .and_then(|u| u.id)
-            {
-                self.scim.delete_user(&user_id).await?;
+            let user_name = match self.google.user_name.get(user) {
+                Some(v) => v,
+                None => continue,
+            };
+            let aws_user = match self.backend.get_user(&user_name).await? {
+                Some(v) => v,
+                None => continue,
+            };
+            if let Some(ref user_id) = aws_user.id {
+                log::info!("Deleting user: {}", user_name);
+                self.backend.delete_user(user_id).await?;
+                self.audit(
+                    crate::audit::Change::new(crate::audit::Action::DeleteUser, &user_name)
+                        .before(&aws_user)
+                        .rule(Some(crate::audit::RULE_DELETED_IN_GOOGLE)),
+                );
+                self.report.deleted_users += 1;
             }
         }
         Ok(())
     }
 
     async fn add_users(&mut self) -> anyhow::Result<()> {
-        use anyhow::Context;
+        use futures::StreamExt;
 
-        let to_remove = self
+        if Self::is_interrupted(self.deadline, &mut self.interrupted) {
+            return Ok(());
+        }
+        let mut to_create = Vec::new();
+        for (_, g_user) in self
             .google_user_lookup
             .iter()
             .filter(|(id, _)| !self.aws_user_lookup.contains_key(*id))
-            .map(|(_, user)| user)
+        {
+            let user_name = match self.google.user_name.get(g_user) {
+                Some(v) => v,
+                None => continue,
+            };
+            match Self::create_user(
+                g_user,
+                user_name,
+                None,
+                self.backend.is_multiple_emails_supported(),
+            ) {
+                Ok(u) => to_create.push(u),
+                Err(err) => {
+                    log::warn!("Skipping user {}: {:#}", g_user.primary_email, err);
+                    self.report.skipped_users.push(crate::report::SkippedUser {
+                        email: g_user.primary_email.clone(),
+                        reason: format!("{err:#}"),
+                    });
+                }
+            }
+        }
+        let (backend, deadline, audit) = (self.backend, self.deadline, self.audit);
+        let (target, normalization, user_rules) = (
+            self.report.target.as_str(),
+            &self.google.normalization,
+            &self.user_rules,
+        );
+        // Each user is recorded as soon as it is created, so that it is audited
+        // even if the creation of another user fails
+        let requests = to_create
+            .into_iter()
+            .map(|user| async move {
+                if deadline.is_reached() {
+                    return Ok(None);
+                }
+                let (user, created) = Self::create_backend_user(backend, user).await?;
+                let key = normalization.normalize(&user.user_name);
+                if created {
+                    audit.record(
+                        target,
+                        crate::audit::Change::new(
+                            crate::audit::Action::CreateUser,
+                            &user.user_name,
+                        )
+                        .after(&user)
+                        .rule(user_rules.get(&key).map(String::as_str)),
+                    );
+                }
+                anyhow::Ok(Some((key, user, created)))
+            })
             .collect::<Vec<_>>();
-        for g_user in to_remove {
-            log::info!("Creating user: {}", g_user.primary_email);
-            let user = Self::create_user(g_user);
-            let user = match self.scim.create_user(user).await? {
-                Some(u) => u,
+        let mut created =
+            futures::stream::iter(requests).buffer_unordered(self.google.concurrency.aws());
+        while let Some(user) = created.next().await {
+            let (key, user, created) = match user? {
+                Some(v) => v,
                 None => {
-                    log::info!("User already exists - fetching instead");
-                    self.scim
-                        .get_user(&g_user.primary_email)
-                        .await?
-                        .with_context(|| {
-                            format!(
-                                "Unable to find user with user_name: {}",
-                                g_user.primary_email
-                            )
-                        })?
+                    let _ = Self::is_interrupted(self.deadline, &mut self.interrupted);
+                    continue;
                 }
             };
-            let _ = self.aws_user_lookup.insert(user.user_name.clone(), user);
+            let _ = self.aws_user_lookup.insert(key, user);
+            if created {
+                self.report.created_users += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the user in the backend or fetches it if it already exists.
+    /// Returns whether the user was created
+    pub(crate) async fn create_backend_user(
+        backend: &dyn crate::aws::Backend,
+        user: crate::aws::User,
+    ) -> anyhow::Result<(crate::aws::User, bool)> {
+        use anyhow::Context;
+
+        log::info!("Creating user: {}", user.user_name);
+        let user_name = user.user_name.clone();
+        match backend.create_user(user).await? {
+            Some(u) => Ok((u, true)),
+            None => {
+                log::info!("User already exists - fetching instead");
+                let user = backend.get_user(&user_name).await?.with_context(|| {
+                    format!("Unable to find user with user_name: {}", user_name)
+                })?;
+                Ok((user, false))
+            }
+        }
+    }
+
+    /// Sets the manager of every synced user to the AWS SSO user of its google manager
+    async fn sync_managers(&mut self) -> anyhow::Result<()> {
+        if !self.backend.is_manager_supported() {
+            return Ok(());
+        }
+        let changes = self
+            .aws_user_lookup
+            .iter()
+            .filter(|(key, _)| self.google_user_lookup.contains_key(*key))
+            .filter_map(|(key, user)| {
+                let manager_id = self
+                    .google
+                    .managers
+                    .get(key)
+                    .and_then(|m| self.aws_user_lookup.get(m)?.id.clone());
+                if manager_id.as_deref() == user.manager_id() {
+                    return None;
+                }
+                let previous = user.manager_id().map(ToOwned::to_owned);
+                Some((key.clone(), user.id.clone()?, previous, manager_id))
+            })
+            .collect::<Vec<_>>();
+        for (key, user_id, previous, manager_id) in changes {
+            log::info!(
+                "Setting manager of user {} to {}.",
+                key,
+                manager_id.as_deref().unwrap_or("none")
+            );
+            self.backend
+                .set_user_manager(&user_id, manager_id.as_deref())
+                .await?;
+            self.audit(
+                crate::audit::Change::new(crate::audit::Action::SetManager, &key)
+                    .before(&serde_json::json!({ "manager": previous }))
+                    .after(&serde_json::json!({ "manager": manager_id })),
+            );
+            if let Some(user) = self.aws_user_lookup.get_mut(&key) {
+                user.enterprise = Some(crate::aws::EnterpriseUser {
+                    manager: manager_id.map(|value| crate::aws::Manager { value }),
+                });
+            }
+            self.report.updated_managers += 1;
         }
         Ok(())
     }
 
-    fn create_user(user: &crate::google::User) -> crate::aws::User {
-        crate::aws::User {
+    /// Returns the AWS SSO user of a google user with its primary email. All other emails
+    /// are only added if `all_emails` is set, as AWS SSO rejects more than one email.
+    /// Fails if the profile of the user is incomplete or does not fit the constraints
+    /// of AWS SSO
+    pub(crate) fn create_user(
+        user: &crate::google::User,
+        user_name: String,
+        manager_id: Option<String>,
+        all_emails: bool,
+    ) -> anyhow::Result<crate::aws::User> {
+        use anyhow::Context;
+
+        let (given_name, family_name, full_name) = user
+            .complete_name()
+            .context("Unable to derive the given and family name")?;
+        let primary = user.emails.iter().find(|e| e.primary == Some(true));
+        let mut emails = vec![crate::aws::UserMail {
+            value: primary.map_or_else(|| user.primary_email.clone(), |e| e.address.clone()),
+            r#type: Some(
+                primary
+                    .map_or("work", crate::google::UserMail::scim_type)
+                    .into(),
+            ),
+            primary: Some(true),
+        }];
+        if all_emails {
+            let mut seen = std::collections::HashSet::new();
+            let _ = seen.insert(emails[0].value.to_lowercase());
+            for email in &user.emails {
+                if seen.insert(email.address.to_lowercase()) {
+                    emails.push(crate::aws::UserMail {
+                        value: email.address.clone(),
+                        r#type: Some(email.scim_type().into()),
+                        primary: None,
+                    });
+                }
+            }
+        }
+        let aws_user = crate::aws::User {
             id: None,
             external_id: Some(user.id.to_owned()),
-            user_name: user.primary_email.to_owned(),
+            user_name,
             name: crate::aws::UserName {
-                formatted: Some(user.name.full_name.clone()),
-                family_name: user.name.family_name.clone(),
-                given_name: user.name.given_name.clone(),
+                formatted: Some(full_name.clone()),
+                family_name,
+                given_name,
             },
-            display_name: user.name.full_name.clone(),
+            display_name: full_name,
             profile_url: user.thumbnail_photo_url.clone(),
-            emails: user
-                .emails
-                .iter()
-                .filter(|email| email.primary == Some(true))
-                .map(|email| {
-                    vec![crate::aws::UserMail {
-                        value: email.address.clone(),
-                        r#type: email.r#type.clone(),
-                        primary: email.primary,
-                    }]
-                })
-                .next(),
+            emails: Some(emails),
             active: !user.suspended.unwrap_or(false),
-        }
+            enterprise: manager_id.map(|value| crate::aws::EnterpriseUser {
+                manager: Some(crate::aws::Manager { value }),
+            }),
+        };
+        aws_user.validate()?;
+        Ok(aws_user)
     }
 
-    pub(crate) async fn sync_associations(&mut self) -> anyhow::Result<()> {
-        for (group_id, members) in &self.google_group_assoc {
-            let aws_group_id = match self
-                .aws_group_lookup
-                .get(group_id)
-                .and_then(|g| g.id.as_ref())
-            {
-                Some(v) => v,
+    /// Syncs the memberships of all users in the order of their keys. If `after` is set,
+    /// only the memberships of users after the given key are synced
+    pub(crate) async fn sync_associations(&mut self, after: Option<&str>) -> anyhow::Result<()> {
+        let mut users = self
+            .aws_user_lookup
+            .iter()
+            .filter(|(user_id, _)| after.is_none_or(|after| user_id.as_str() > after))
+            .collect::<Vec<_>>();
+        users.sort_by_key(|(a, _)| *a);
+
+        let mut changes = Vec::new();
+        let mut audited = Vec::new();
+        for (user_id, user) in users {
+            if Self::is_interrupted(self.deadline, &mut self.interrupted) {
+                break;
+            }
+            self.last_user = Some(user_id.clone());
+            let aws_user_id = match user.id {
+                Some(ref v) => v,
                 None => continue,
             };
-            for (user_id, user) in &self.aws_user_lookup {
-                let aws_user_id = match user.id {
-                    Some(ref v) => v,
+            let aws_memberships = self
+                .backend
+                .list_group_memberships_for_member(aws_user_id)
+                .await?;
+            for (group_id, members) in &self.google_group_assoc {
+                let aws_group_id = match self
+                    .aws_group_lookup
+                    .get(group_id)
+                    .and_then(|g| g.id.as_ref())
+                {
+                    Some(v) => v,
                     None => continue,
                 };
-                let aws_is_member = self.scim.is_group_member(aws_group_id, aws_user_id).await?;
+                let aws_is_member = match aws_memberships {
+                    Some(ref m) => m.contains(aws_group_id),
+                    None => {
+                        self.backend
+                            .is_group_member(aws_group_id, aws_user_id)
+                            .await?
+                    }
+                };
                 let google_is_member = members.contains(user_id);
                 if google_is_member && !aws_is_member {
                     log::info!("Adding user {} to group {}.", user_id, group_id);
-                    self.scim
-                        .add_group_member(aws_group_id, aws_user_id)
-                        .await?;
+                    changes.push(crate::aws::MembershipChange::Add {
+                        group_id: aws_group_id,
+                        user_id: aws_user_id,
+                    });
+                    audited.push(
+                        crate::audit::Change::new(crate::audit::Action::AddMember, user_id)
+                            .after(&serde_json::json!({ "group": group_id })),
+                    );
                 } else if !google_is_member && aws_is_member {
                     log::info!("Removing user {} from group {}.", user_id, group_id);
-                    self.scim
-                        .remove_group_member(aws_group_id, aws_user_id)
-                        .await?;
+                    changes.push(crate::aws::MembershipChange::Remove {
+                        group_id: aws_group_id,
+                        user_id: aws_user_id,
+                    });
+                    audited.push(
+                        crate::audit::Change::new(crate::audit::Action::RemoveMember, user_id)
+                            .before(&serde_json::json!({ "group": group_id })),
+                    );
                 }
             }
+            if changes.len() >= MEMBERSHIP_BATCH_SIZE {
+                apply_membership_changes(
+                    self.backend,
+                    &changes,
+                    std::mem::take(&mut audited),
+                    self.google.concurrency.aws(),
+                    self.audit,
+                    &mut self.report,
+                )
+                .await?;
+                changes.clear();
+            }
+        }
+        apply_membership_changes(
+            self.backend,
+            &changes,
+            audited,
+            self.google.concurrency.aws(),
+            self.audit,
+            &mut self.report,
+        )
+        .await
+    }
+
+    /// Creates and deletes account assignments of synced groups so they match the
+    /// given rules. Only assignments of groups synced from google and permission sets
+    /// used in one of the rules are deleted
+    pub(crate) async fn sync_account_assignments(
+        &mut self,
+        config: &crate::event::AccountAssignments,
+    ) -> anyhow::Result<()> {
+        use crate::report::{AccountAssignmentChange, AssignmentAction, AssignmentStatus};
+        use anyhow::bail;
+
+        let sso_admin = crate::aws::SsoAdmin::new(&config.instance_arn, &config.region)?;
+        let organizations = crate::aws::Organizations::new();
+        let managed_groups = self
+            .google_group_lookup
+            .keys()
+            .filter_map(|g| Some((self.aws_group_lookup.get(g)?.id.clone()?, g.clone())))
+            .collect::<Lookup<_>>();
+
+        let mut ou_lookup = Lookup::<Vec<String>>::new();
+        let mut desired = std::collections::BTreeSet::new();
+        for rule in &config.rules {
+            let mut account_ids = rule.account_ids.clone();
+            for ou in &rule.organizational_units {
+                if !ou_lookup.contains_key(ou) {
+                    let ou_accounts = organizations.list_accounts_for_ou(ou).await?;
+                    let _ = ou_lookup.insert(ou.clone(), ou_accounts);
+                }
+                account_ids.extend(ou_lookup[ou].iter().cloned());
+            }
+            for (group_id, email) in &managed_groups {
+                if !rule.group_regex.is_match(email) {
+                    continue;
+                }
+                for account_id in &account_ids {
+                    let _ = desired.insert((
+                        account_id.clone(),
+                        rule.permission_set_arn.clone(),
+                        group_id.clone(),
+                    ));
+                }
+            }
+        }
+
+        let permission_set_arns = config
+            .rules
+            .iter()
+            .map(|r| r.permission_set_arn.as_str())
+            .collect::<std::collections::BTreeSet<_>>();
+        let mut current = std::collections::BTreeSet::new();
+        for permission_set_arn in permission_set_arns {
+            for account_id in sso_admin
+                .list_accounts_for_permission_set(permission_set_arn)
+                .await?
+            {
+                current.extend(
+                    sso_admin
+                        .list_account_assignments(&account_id, permission_set_arn)
+                        .await?
+                        .into_iter()
+                        .filter(|a| {
+                            a.principal_type == "GROUP"
+                                && managed_groups.contains_key(&a.principal_id)
+                        })
+                        .map(|a| (a.account_id, a.permission_set_arn, a.principal_id)),
+                );
+            }
+        }
+
+        let to_delete = current.difference(&desired).collect::<Vec<_>>();
+        if config.allow_deletions && !config.dry_run {
+            if let Some(max_deletions) = config.max_deletions {
+                if to_delete.len() > max_deletions {
+                    bail!(
+                        "Refusing to delete {} account assignments as only {} deletions are allowed (max_deletions).",
+                        to_delete.len(),
+                        max_deletions
+                    );
+                }
+            }
+        }
+
+        for (account_id, permission_set_arn, group_id) in desired.difference(&current) {
+            let group = &managed_groups[group_id];
+            let status = if config.dry_run {
+                log::info!(
+                    "Would assign {} to group {} in account {} (dry run).",
+                    permission_set_arn,
+                    group,
+                    account_id
+                );
+                AssignmentStatus::DryRun
+            } else {
+                log::info!(
+                    "Assigning {} to group {} in account {}.",
+                    permission_set_arn,
+                    group,
+                    account_id
+                );
+                sso_admin
+                    .create_account_assignment(account_id, permission_set_arn, group_id)
+                    .await?;
+                self.audit(
+                    crate::audit::Change::new(crate::audit::Action::CreateAccountAssignment, group)
+                        .after(&serde_json::json!({
+                            "account_id": account_id,
+                            "permission_set_arn": permission_set_arn,
+                        })),
+                );
+                AssignmentStatus::Applied
+            };
+            self.report
+                .account_assignments
+                .push(AccountAssignmentChange {
+                    action: AssignmentAction::Create,
+                    status,
+                    group: group.clone(),
+                    account_id: account_id.clone(),
+                    permission_set_arn: permission_set_arn.clone(),
+                });
+        }
+        for (account_id, permission_set_arn, group_id) in to_delete {
+            let group = &managed_groups[group_id];
+            let status = if config.dry_run {
+                log::info!(
+                    "Would remove {} from group {} in account {} (dry run).",
+                    permission_set_arn,
+                    group,
+                    account_id
+                );
+                AssignmentStatus::DryRun
+            } else if !config.allow_deletions {
+                log::warn!(
+                    "Not removing {} from group {} in account {} as allow_deletions is not set.",
+                    permission_set_arn,
+                    group,
+                    account_id
+                );
+                AssignmentStatus::DeletionNotAllowed
+            } else {
+                log::info!(
+                    "Removing {} from group {} in account {}.",
+                    permission_set_arn,
+                    group,
+                    account_id
+                );
+                sso_admin
+                    .delete_account_assignment(account_id, permission_set_arn, group_id)
+                    .await?;
+                self.audit(
+                    crate::audit::Change::new(crate::audit::Action::DeleteAccountAssignment, group)
+                        .before(&serde_json::json!({
+                            "account_id": account_id,
+                            "permission_set_arn": permission_set_arn,
+                        })),
+                );
+                AssignmentStatus::Applied
+            };
+            self.report
+                .account_assignments
+                .push(AccountAssignmentChange {
+                    action: AssignmentAction::Delete,
+                    status,
+                    group: group.clone(),
+                    account_id: account_id.clone(),
+                    permission_set_arn: permission_set_arn.clone(),
+                });
         }
         Ok(())
     }
 }
+
+/// Applies the membership changes and records each of them as soon as it is applied,
+/// so that changes applied before a failure are audited and counted. `audited`
+/// contains the audit log change of each membership change
+pub async fn apply_membership_changes(
+    backend: &dyn crate::aws::Backend,
+    changes: &[crate::aws::MembershipChange<'_>],
+    audited: Vec<crate::audit::Change>,
+    concurrency: usize,
+    audit: &crate::audit::AuditLog,
+    report: &mut crate::report::TargetReport,
+) -> anyhow::Result<()> {
+    let pending = std::sync::Mutex::new(audited.into_iter().map(Some).collect::<Vec<_>>());
+    let target = report.target.as_str();
+    let result = backend
+        .apply_membership_changes(changes, concurrency, &|index| {
+            let change = pending
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .get_mut(index)
+                .and_then(Option::take);
+            if let Some(change) = change {
+                audit.record(target, change);
+            }
+        })
+        .await;
+    let pending = pending
+        .into_inner()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    for (change, pending) in changes.iter().zip(pending) {
+        match (change, pending) {
+            (&crate::aws::MembershipChange::Add { .. }, None) => report.added_memberships += 1,
+            (&crate::aws::MembershipChange::Remove { .. }, None) => {
+                report.removed_memberships += 1;
+            }
+            (_, Some(_)) => {}
+        }
+    }
+    result
+}
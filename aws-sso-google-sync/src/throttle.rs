@@ -0,0 +1,37 @@
+const COOLDOWN: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Backoff which is shared by all concurrent requests to the same API. Once a
+/// request is throttled, every request waits until the cooldown is over, so that
+/// concurrent requests do not keep the API throttled
+#[derive(Debug, Default)]
+pub struct Throttle {
+    until: std::sync::Mutex<Option<tokio::time::Instant>>,
+}
+
+impl Throttle {
+    /// Waits until the cooldown of a previously throttled request is over
+    pub(crate) async fn wait(&self) {
+        let until = *self
+            .until
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(until) = until {
+            tokio::time::sleep_until(until).await;
+        }
+    }
+
+    /// Starts a cooldown for all requests and waits until it is over
+    pub(crate) async fn throttled(&self) {
+        let next = tokio::time::Instant::now() + COOLDOWN;
+        {
+            let mut until = self
+                .until
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            if until.is_none_or(|until| until < next) {
+                *until = Some(next);
+            }
+        }
+        self.wait().await;
+    }
+}
@@ -0,0 +1,281 @@
+const HEADER_CHANNEL_ID: &str = "X-Goog-Channel-ID";
+const HEADER_CHANNEL_TOKEN: &str = "X-Goog-Channel-Token";
+const HEADER_RESOURCE_STATE: &str = "X-Goog-Resource-State";
+
+/// Body of a push notification. Notifications of user channels contain the user,
+/// notifications of admin activity channels contain the activity
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum NotificationBody {
+    User {
+        #[serde(rename = "primaryEmail")]
+        primary_email: String,
+    },
+    Activity(crate::google::Activity),
+}
+
+/// Channels registered for a tenant, which are stored in SSM between invocations
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct StoredChannel {
+    tenant: String,
+    #[serde(flatten)]
+    channel: crate::google::Channel,
+}
+
+/// Returned if a push notification has a missing or invalid channel token. The request
+/// is answered with 401, so that it is not retried
+#[derive(Debug)]
+pub struct InvalidChannelToken {
+    channel_id: String,
+}
+
+impl std::fmt::Display for InvalidChannelToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid token in push notification of channel {}",
+            self.channel_id
+        )
+    }
+}
+
+impl std::error::Error for InvalidChannelToken {}
+
+/// Validates a google push notification and returns the users and groups which are
+/// affected by it. Returns `None` if there is nothing to sync
+pub async fn get_scope(
+    event: &crate::event::Event,
+    request: &crate::event::HttpRequest<'_>,
+) -> anyhow::Result<Option<crate::scoped::Scope>> {
+    use anyhow::{bail, Context};
+    use base64::Engine;
+
+    let channel_id = request
+        .header(HEADER_CHANNEL_ID)
+        .context("Received http request which is not a google push notification")?;
+    let config = event
+        .get_watch_channels()?
+        .context("watch_channels must be defined to receive push notifications")?;
+    let token: crate::event::ChannelToken = crate::aws::get_secret(&config.token).await?;
+    if !request
+        .header(HEADER_CHANNEL_TOKEN)
+        .is_some_and(|received| is_token_equal(received, &token.token))
+    {
+        return Err(InvalidChannelToken {
+            channel_id: channel_id.into(),
+        }
+        .into());
+    }
+
+    let resource_state = request.header(HEADER_RESOURCE_STATE).unwrap_or_default();
+    if resource_state == "sync" {
+        log::info!("Channel {} was registered", channel_id);
+        return Ok(None);
+    }
+    let body = match request.body {
+        Some(body) if request.is_base64_encoded => std::borrow::Cow::Owned(
+            String::from_utf8(base64::prelude::BASE64_STANDARD.decode(body)?)
+                .context("Push notification body is not valid utf-8")?,
+        ),
+        Some(body) => std::borrow::Cow::Borrowed(body),
+        None => bail!("Push notification of channel {channel_id} has no body"),
+    };
+    let body: NotificationBody = serde_json::from_str(&body)
+        .with_context(|| format!("Invalid push notification body: {body}"))?;
+
+    let mut scope = crate::scoped::Scope::default();
+    match body {
+        NotificationBody::User { primary_email } => {
+            log::info!(
+                "Received {} notification for user {}",
+                resource_state,
+                primary_email
+            );
+            scope.add_user(&primary_email);
+        }
+        NotificationBody::Activity(activity) => {
+            for event in &activity.events {
+                if !crate::incremental::add_event(&mut scope, event) {
+                    log::info!("Event {} is applied by the next full sync", event.name);
+                }
+            }
+        }
+    }
+    Ok(Some(scope))
+}
+
+/// Registers channels for all google tenants and renews channels which expire soon.
+/// Channels of tenants which are no longer configured are stopped. The state is saved
+/// even if a registration fails, so that no registered channel is lost.
+/// Returns the ids of the new channels
+pub async fn renew_channels(event: &crate::event::Event) -> anyhow::Result<Vec<String>> {
+    use anyhow::Context;
+
+    let config = event
+        .get_watch_channels()?
+        .context("watch_channels must be defined to renew channels")?;
+    let tenants = event.get_google_tenants()?;
+    let mut directory_creds = Vec::with_capacity(tenants.len());
+    for tenant in &tenants {
+        directory_creds.push(crate::directory::DirectoryCreds::load(&tenant.source).await?);
+    }
+    let token: crate::event::ChannelToken = crate::aws::get_secret(&config.token).await?;
+    let ssm = crate::aws::Ssm::new(&config.state.region)?;
+    let mut channels = match ssm.get_parameter(&config.state.name).await? {
+        Some(v) => serde_json::from_str::<Vec<StoredChannel>>(&v)
+            .with_context(|| format!("Invalid channel state in {}", config.state.name))?,
+        None => Vec::new(),
+    };
+    let mut watches = Vec::new();
+    for (index, (tenant, creds)) in tenants.iter().zip(&directory_creds).enumerate() {
+        match *creds {
+            crate::directory::DirectoryCreds::Google(ref creds) => {
+                watches.push((
+                    index,
+                    tenant.name().to_owned(),
+                    crate::google::Watch::new(creds).await?,
+                ));
+            }
+            crate::directory::DirectoryCreds::Ldap(_) => log::info!(
+                "Skipping tenant {} as push notifications are only supported for google",
+                tenant.name()
+            ),
+        }
+    }
+
+    let now = chrono::Utc::now();
+    let mut renewed = Vec::new();
+    let mut active = Vec::new();
+    let result = register_channels(
+        &watches,
+        &config,
+        &token,
+        now,
+        &mut channels,
+        &mut active,
+        &mut renewed,
+    )
+    .await;
+    if result.is_ok() {
+        stop_removed_channels(&watches, &mut channels, now).await;
+    }
+    // Channels which were not processed are kept, so that they are renewed or
+    // stopped by the next invocation
+    active.append(&mut channels);
+    ssm.put_parameter(&config.state.name, &serde_json::to_string(&active)?)
+        .await?;
+    result?;
+    Ok(renewed)
+}
+
+/// Registers the channels which are missing or expire soon. Processed channels are moved
+/// from `channels` to `active`
+async fn register_channels(
+    watches: &[(usize, String, crate::google::Watch<'_>)],
+    config: &crate::event::WatchChannels,
+    token: &crate::event::ChannelToken,
+    now: chrono::DateTime<chrono::Utc>,
+    channels: &mut Vec<StoredChannel>,
+    active: &mut Vec<StoredChannel>,
+    renewed: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    for &(index, ref tenant, ref watch) in watches {
+        for kind in crate::google::ChannelKind::ALL {
+            let existing = channels
+                .iter()
+                .position(|c| &c.tenant == tenant && c.channel.kind == kind)
+                .map(|i| channels.swap_remove(i));
+            let existing = match existing {
+                Some(existing) if existing.channel.expiration - now > config.renew_before() => {
+                    active.push(existing);
+                    continue;
+                }
+                existing => existing,
+            };
+            let id = format!(
+                "aws-sso-google-sync-{}-{}-{}",
+                index,
+                kind.as_str(),
+                now.timestamp_millis()
+            );
+            log::info!("Registering channel {} for tenant {}", id, tenant);
+            let channel = match watch
+                .watch(kind, &id, &config.address, &token.token, now + config.ttl())
+                .await
+            {
+                Ok(v) => v,
+                Err(err) => {
+                    active.extend(existing);
+                    return Err(err);
+                }
+            };
+            renewed.push(channel.id.clone());
+            active.push(StoredChannel {
+                tenant: tenant.clone(),
+                channel,
+            });
+            // The replaced channel is stopped after the new one is registered,
+            // so that no notifications are lost in between
+            if let Some(existing) = existing {
+                if let Err(err) = watch.stop(&existing.channel).await {
+                    log::warn!("Unable to stop channel {}: {:?}", existing.channel.id, err);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Stops the channels of tenants which are no longer configured with the credentials of
+/// the configured tenants. Channels which can not be stopped are kept until they expire
+async fn stop_removed_channels(
+    watches: &[(usize, String, crate::google::Watch<'_>)],
+    channels: &mut Vec<StoredChannel>,
+    now: chrono::DateTime<chrono::Utc>,
+) {
+    let mut remaining = Vec::new();
+    for removed in channels.drain(..) {
+        if removed.channel.expiration <= now {
+            continue;
+        }
+        let mut stopped = false;
+        for (_, _, watch) in watches {
+            match watch.stop(&removed.channel).await {
+                Ok(()) => {
+                    stopped = true;
+                    break;
+                }
+                Err(err) => {
+                    log::debug!("Unable to stop channel {}: {:?}", removed.channel.id, err);
+                }
+            }
+        }
+        if stopped {
+            log::info!(
+                "Stopped channel {} of removed tenant {}",
+                removed.channel.id,
+                removed.tenant
+            );
+        } else {
+            log::warn!(
+                "Unable to stop channel {} of removed tenant {}, it expires at {}",
+                removed.channel.id,
+                removed.tenant,
+                removed.channel.expiration
+            );
+            remaining.push(removed);
+        }
+    }
+    *channels = remaining;
+}
+
+/// Compares the channel token in constant time, so that it can not be guessed by
+/// measuring the response time
+fn is_token_equal(received: &str, expected: &str) -> bool {
+    received.len() == expected.len()
+        && received
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}